@@ -0,0 +1,285 @@
+//! The services loader.dll offers plugins, through the `YabgGetApi` export.
+//! See the yabg3nml-api crate for the safe wrapper plugins use
+
+use std::{ffi::c_void, fs, path::PathBuf, ptr, sync::LazyLock, time::Duration};
+
+use sayuri::sync::Mutex;
+use shared::{
+    paths::get_bg3_plugins_dir,
+    plugins::{CONFIG_DIR, DATA_DIR, PLUGIN_FILES_DIR},
+};
+use tracing::{debug, error, info, trace, warn};
+use yabg3nml_api::sys::{
+    API_VERSION, Api, LEVEL_DEBUG, LEVEL_ERROR, LEVEL_TRACE, LEVEL_WARN, PluginCallback,
+    PluginInfo, ShutdownCallback, Str, StrCallback, WAIT_FOREVER,
+};
+
+use crate::{LOADED_PLUGINS, services};
+
+static API: Api = Api {
+    version: API_VERSION,
+    loader_version: Str::new(env!("CARGO_PKG_VERSION")),
+    log,
+    plugins,
+    config_path,
+    data_dir,
+    on_shutdown,
+    publish_service,
+    get_service,
+    wait_service,
+};
+
+/// Callbacks plugins registered with `on_shutdown`, oldest first
+static SHUTDOWN: LazyLock<Mutex<Vec<Shutdown>>> = LazyLock::new(Mutex::default);
+
+struct Shutdown {
+    /// The module handle of the plugin which registered it
+    module: usize,
+    callback: ShutdownCallback,
+    user: usize,
+}
+
+#[unsafe(no_mangle)]
+extern "C" fn YabgGetApi(version: u32) -> *const Api {
+    // fields are only appended, so the newest table serves every older version
+    if version == 0 || version > API_VERSION {
+        warn!(
+            "A plugin asked for api version {version}, but this loader only supports up to {API_VERSION}"
+        );
+        return ptr::null();
+    }
+
+    &API
+}
+
+/// Run the shutdown callbacks of the plugin with this `module`, or of every plugin,
+/// newest first
+pub fn shutdown(module: Option<*mut c_void>) {
+    let callbacks = {
+        let mut callbacks = SHUTDOWN.lock();
+        let (matching, rest) = callbacks
+            .drain(..)
+            .partition::<Vec<_>, _>(|s| module.is_none_or(|m| s.module == m as usize));
+
+        *callbacks = rest;
+        matching
+    };
+
+    for Shutdown {
+        module,
+        callback,
+        user,
+    } in callbacks.into_iter().rev()
+    {
+        trace!(module, "running shutdown callback");
+
+        // SAFETY: the plugin registered it with this user pointer
+        unsafe {
+            callback(user as *mut c_void);
+        }
+    }
+}
+
+/// The name of the loaded plugin with this module handle
+fn plugin_name(plugin: *mut c_void) -> Option<String> {
+    let plugins = LOADED_PLUGINS.lock();
+    let name = plugins
+        .iter()
+        .find(|p| p.module.0 == plugin)
+        .map(|p| p.name.clone());
+
+    if name.is_none() {
+        warn!("A module which isn't a loaded plugin called the api; it works from Init on");
+    }
+
+    name
+}
+
+/// A service name passed by a plugin
+///
+/// # Safety
+/// The string must be valid during the call
+unsafe fn service_name(name: &Str) -> Option<&str> {
+    // SAFETY: upheld by the caller
+    let name = unsafe { name.as_bytes() }.and_then(|b| std::str::from_utf8(b).ok());
+
+    if name.is_none() {
+        warn!("A plugin passed a missing or invalid service name");
+    }
+
+    name
+}
+
+/// Give a path to a plugin's callback. Returns whether there was one
+fn send_path(path: Option<PathBuf>, user: *mut c_void, callback: StrCallback) -> bool {
+    let Some(path) = path else {
+        return false;
+    };
+
+    let path = path.to_string_lossy();
+
+    // SAFETY: the plugin declared its callback with this signature, and the string
+    // outlives the call
+    unsafe {
+        callback(user, Str::new(&path));
+    }
+
+    true
+}
+
+/// `<plugins dir>/.yabg3nml/<dir>/<file>`, with its folder created
+fn plugin_path(dir: &str, file: &str) -> Option<PathBuf> {
+    let dir = match get_bg3_plugins_dir() {
+        Ok(plugins_dir) => plugins_dir.join(PLUGIN_FILES_DIR).join(dir),
+        Err(e) => {
+            error!("{e:#}");
+            return None;
+        }
+    };
+
+    if let Err(e) = fs::create_dir_all(&dir) {
+        error!(dir = %dir.display(), "failed to create plugin dir: {e}");
+        return None;
+    }
+
+    Some(dir.join(file))
+}
+
+unsafe extern "C" fn log(plugin: *mut c_void, level: u32, message: Str) {
+    let name = plugin_name(plugin).unwrap_or_default();
+
+    // SAFETY: the plugin passes a string which is valid during the call
+    let message = unsafe { message.as_bytes() }.unwrap_or_default();
+    let message = String::from_utf8_lossy(message);
+
+    match level {
+        LEVEL_TRACE => trace!(plugin = %name, "{message}"),
+        LEVEL_DEBUG => debug!(plugin = %name, "{message}"),
+        LEVEL_WARN => warn!(plugin = %name, "{message}"),
+        LEVEL_ERROR => error!(plugin = %name, "{message}"),
+        _ => info!(plugin = %name, "{message}"),
+    }
+}
+
+unsafe extern "C" fn plugins(user: *mut c_void, callback: PluginCallback) {
+    // copied out, so the callback may call the api again
+    let plugins = LOADED_PLUGINS
+        .lock()
+        .iter()
+        .map(|p| (p.name.clone(), p.metadata.clone()))
+        .collect::<Vec<_>>();
+
+    for (name, metadata) in &plugins {
+        let info = PluginInfo {
+            name: Str::new(name),
+            display: Str::new(&metadata.display),
+            id: metadata.id.as_deref().into(),
+            version: metadata.version.as_deref().into(),
+        };
+
+        // SAFETY: the plugin declared its callback with this signature, and info
+        // outlives the call
+        unsafe {
+            callback(user, &info);
+        }
+    }
+}
+
+unsafe extern "C" fn config_path(
+    plugin: *mut c_void,
+    user: *mut c_void,
+    callback: StrCallback,
+) -> bool {
+    let path =
+        plugin_name(plugin).and_then(|name| plugin_path(CONFIG_DIR, &format!("{name}.toml")));
+    send_path(path, user, callback)
+}
+
+unsafe extern "C" fn data_dir(
+    plugin: *mut c_void,
+    user: *mut c_void,
+    callback: StrCallback,
+) -> bool {
+    let path = plugin_name(plugin).and_then(|name| plugin_path(DATA_DIR, &name));
+
+    if let Some(path) = &path
+        && let Err(e) = fs::create_dir_all(path)
+    {
+        error!(dir = %path.display(), "failed to create plugin data dir: {e}");
+        return false;
+    }
+
+    send_path(path, user, callback)
+}
+
+unsafe extern "C" fn on_shutdown(
+    plugin: *mut c_void,
+    user: *mut c_void,
+    callback: ShutdownCallback,
+) -> bool {
+    let Some(name) = plugin_name(plugin) else {
+        return false;
+    };
+
+    trace!(plugin = %name, "registered shutdown callback");
+
+    SHUTDOWN.lock().push(Shutdown {
+        module: plugin as usize,
+        callback,
+        user: user as usize,
+    });
+
+    true
+}
+
+unsafe extern "C" fn publish_service(
+    plugin: *mut c_void,
+    name: Str,
+    version: u32,
+    interface: *const c_void,
+) -> bool {
+    let Some(owner) = plugin_name(plugin) else {
+        return false;
+    };
+
+    // SAFETY: the plugin passes a string which is valid during the call
+    let Some(name) = (unsafe { service_name(&name) }) else {
+        return false;
+    };
+
+    if interface.is_null() {
+        warn!(plugin = %owner, "Plugin {owner} tried to publish service {name} as null");
+        return false;
+    }
+
+    services::publish(plugin as usize, &owner, name, version, interface as usize)
+}
+
+unsafe extern "C" fn get_service(name: Str, version: u32) -> *const c_void {
+    // SAFETY: the plugin passes a string which is valid during the call
+    let Some(name) = (unsafe { service_name(&name) }) else {
+        return ptr::null();
+    };
+
+    services::get(name, version).map_or(ptr::null(), |i| i as *const c_void)
+}
+
+unsafe extern "C" fn wait_service(
+    plugin: *mut c_void,
+    name: Str,
+    version: u32,
+    timeout_ms: u32,
+) -> *const c_void {
+    let Some(caller) = plugin_name(plugin) else {
+        return ptr::null();
+    };
+
+    // SAFETY: the plugin passes a string which is valid during the call
+    let Some(name) = (unsafe { service_name(&name) }) else {
+        return ptr::null();
+    };
+
+    let timeout = (timeout_ms != WAIT_FOREVER).then(|| Duration::from_millis(timeout_ms.into()));
+
+    services::wait(&caller, name, version, timeout).map_or(ptr::null(), |i| i as *const c_void)
+}
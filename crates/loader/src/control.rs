@@ -0,0 +1,47 @@
+//! Messages the host sends to the loader after plugins loaded
+
+use std::thread;
+
+use shared::pipe::commands::Control;
+use tracing::{error, trace};
+
+use crate::{client::CLIENT, hot_reload, logging};
+
+/// Handle messages from the host until it disconnects
+pub fn listen() {
+    thread::spawn(|| {
+        let Ok(client) = &*CLIENT else {
+            return;
+        };
+
+        loop {
+            match client.recv::<Control>() {
+                Ok(Some(control)) => handle(control),
+
+                Ok(None) => {
+                    trace!("host disconnected");
+                    return;
+                }
+
+                Err(e) => {
+                    error!(%e, "failed to receive from host");
+                    return;
+                }
+            }
+        }
+    });
+}
+
+fn handle(control: Control) {
+    match control {
+        Control::Reload { name } => hot_reload::reload_named(name.as_deref()),
+
+        Control::LogLevel { level } => {
+            trace!(?level, "changing log level");
+
+            if let Err(e) = logging::set_level(level.into()) {
+                error!("failed to change log level: {e:#}");
+            }
+        }
+    }
+}
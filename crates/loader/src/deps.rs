@@ -0,0 +1,85 @@
+use std::{
+    env,
+    ffi::OsString,
+    fs, iter,
+    os::windows::ffi::OsStringExt as _,
+    path::{Path, PathBuf},
+};
+
+use shared::plugins::imports::{self, ImportEnv, LoadPath, MissingDll};
+use windows::{
+    Win32::System::{
+        LibraryLoader::GetModuleHandleW,
+        SystemInformation::{GetSystemDirectoryW, GetWindowsDirectoryW},
+    },
+    core::PCWSTR,
+};
+
+/// The real machine, as seen from inside the game process
+struct Process;
+
+impl ImportEnv for Process {
+    fn is_loaded(&self, name: &str) -> bool {
+        let name = name.encode_utf16().chain(iter::once(0)).collect::<Vec<_>>();
+        // SAFETY: name is a valid null terminated string
+        unsafe { GetModuleHandleW(PCWSTR::from_raw(name.as_ptr())) }.is_ok()
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        path.is_file()
+    }
+
+    fn read(&self, path: &Path) -> Option<Vec<u8>> {
+        fs::read(path).ok()
+    }
+
+    fn system_dirs(&self) -> Vec<PathBuf> {
+        [GetSystemDirectoryW, GetWindowsDirectoryW]
+            .into_iter()
+            .filter_map(system_dir)
+            .collect()
+    }
+
+    fn current_dir(&self) -> Option<PathBuf> {
+        env::current_dir().ok()
+    }
+
+    fn path_dirs(&self) -> Vec<PathBuf> {
+        env::var_os("PATH")
+            .iter()
+            .flat_map(env::split_paths)
+            .collect()
+    }
+
+    fn exe_dir(&self) -> Option<PathBuf> {
+        env::current_exe()
+            .ok()
+            .and_then(|p| p.parent().map(Path::to_path_buf))
+    }
+}
+
+/// Which of a plugin's dependencies can't be found when it is loaded through `load`, to
+/// explain why it failed to load
+pub fn missing_dlls(plugin: &Path, load: LoadPath) -> Vec<MissingDll> {
+    let Ok(image) = fs::read(plugin) else {
+        return Vec::new();
+    };
+
+    imports::find_missing(
+        plugin,
+        &image,
+        &imports::search_order(plugin, load, &Process),
+        &Process,
+    )
+}
+
+fn system_dir(get: unsafe fn(Option<&mut [u16]>) -> u32) -> Option<PathBuf> {
+    let mut buf = vec![0u16; 260];
+    // SAFETY: the buffer is valid for its whole length
+    let len = unsafe { get(Some(&mut buf)) } as usize;
+    if len == 0 || len > buf.len() {
+        return None;
+    }
+
+    Some(PathBuf::from(OsString::from_wide(&buf[..len])))
+}
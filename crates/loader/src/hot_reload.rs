@@ -0,0 +1,295 @@
+//! Developer mode which reloads a plugin whenever its dll changes, or when the host asks
+
+use std::{
+    collections::{HashMap, hash_map::Entry},
+    fs, mem,
+    path::{Path, PathBuf},
+    sync::{Arc, LazyLock, OnceLock},
+    thread,
+    time::{Duration, SystemTime},
+};
+
+use eyre::{Context as _, Result, bail};
+use sayuri::sync::Mutex;
+use shared::{
+    config::{Config, TrustMode},
+    paths::get_bg3_plugins_dir,
+    pipe::commands::{PluginMetadata, Receive},
+    plugins::lock_path,
+};
+use tracing::{error, info, trace, warn};
+use unicase::UniCase;
+use windows::{Win32::System::LibraryLoader::GetProcAddress, core::s};
+
+use crate::{
+    LOADED_PLUGINS, api,
+    loader::{load_lockfile, load_plugin, send, untrusted},
+    report::Tracker,
+    services, shadow,
+    utils::ThreadManager,
+};
+
+/// How often plugin dlls are checked for changes
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The options reloads follow. Set when hot reload is on
+static OPTIONS: OnceLock<Options> = OnceLock::new();
+
+/// Every plugin which was loaded, so it can be loaded again
+static WATCHED: LazyLock<Mutex<Vec<Watched>>> = LazyLock::new(Mutex::default);
+
+/// Only one plugin is reloaded at a time
+static RELOADING: LazyLock<Mutex<()>> = LazyLock::new(Mutex::default);
+
+#[derive(Clone)]
+struct Watched {
+    name: String,
+    /// The original dll, not the copy
+    path: PathBuf,
+    /// From the first load; the manifest and plugin data aren't read again on reload
+    metadata: PluginMetadata,
+}
+
+struct Options {
+    /// [core]trust, which rebuilt dlls are checked against too
+    trust: TrustMode,
+    /// Plugins with [plugins.<name>]reload_without_deinit
+    without_deinit: Vec<String>,
+}
+
+/// A file's modified time and size, to tell when it changed
+type Stamp = (SystemTime, u64);
+
+pub fn enable(config: &Config) {
+    let without_deinit = config
+        .plugins
+        .iter()
+        .filter(|(_, p)| p.reload_without_deinit)
+        .map(|(name, _)| name.clone())
+        .collect();
+
+    _ = OPTIONS.set(Options {
+        trust: config.core.trust,
+        without_deinit,
+    });
+}
+
+pub fn is_enabled() -> bool {
+    OPTIONS.get().is_some()
+}
+
+/// Remember a plugin being loaded, so it is reloaded when its dll changes
+pub fn register(name: &str, path: &Path, metadata: &PluginMetadata) {
+    let mut watched = WATCHED.lock();
+    watched.retain(|w| UniCase::new(w.name.as_str()) != UniCase::new(name));
+    watched.push(Watched {
+        name: name.to_owned(),
+        path: path.to_owned(),
+        metadata: metadata.clone(),
+    });
+}
+
+/// Start reloading plugins when their dll changes
+pub fn watch() {
+    thread::spawn(|| {
+        let mut stamps = HashMap::<UniCase<String>, (Stamp, bool)>::new();
+
+        loop {
+            thread::sleep(POLL_INTERVAL);
+
+            let watched = WATCHED.lock().clone();
+            for plugin in watched {
+                // missing while it is being rebuilt
+                let Some(stamp) = stamp(&plugin.path) else {
+                    continue;
+                };
+
+                match stamps.entry(UniCase::new(plugin.name.clone())) {
+                    Entry::Vacant(entry) => {
+                        entry.insert((stamp, false));
+                    }
+
+                    Entry::Occupied(mut entry) => {
+                        let (seen, changed) = entry.get_mut();
+
+                        // wait a poll for it to stop changing, so a half written dll isn't loaded
+                        if *seen != stamp {
+                            *seen = stamp;
+                            *changed = true;
+                        } else if mem::take(changed) {
+                            reload(plugin);
+                        }
+                    }
+                }
+            }
+        }
+    });
+}
+
+fn stamp(path: &Path) -> Option<Stamp> {
+    let metadata = fs::metadata(path).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
+
+/// Reload one plugin by name, or all of them, as the host asked
+pub fn reload_named(name: Option<&str>) {
+    if !is_enabled() {
+        warn!(
+            "Ignoring reload request; set [core]hot_reload in config.toml to true and restart the game to use it"
+        );
+        return;
+    }
+
+    let watched = WATCHED.lock().clone();
+
+    let plugins = watched
+        .into_iter()
+        .filter(|w| name.is_none_or(|n| UniCase::new(n) == UniCase::new(w.name.as_str())))
+        .collect::<Vec<_>>();
+
+    if let Some(name) = name
+        && plugins.is_empty()
+    {
+        warn!("Cannot reload plugin {name}; it was never loaded");
+    }
+
+    for plugin in plugins {
+        reload(plugin);
+    }
+}
+
+fn reload(plugin: Watched) {
+    let _guard = RELOADING.lock();
+
+    info!("Reloading plugin {}", plugin.metadata.display);
+
+    // the old copy keeps running if the new one can't be used
+    let copy = match checked_copy(&plugin) {
+        Ok(copy) => copy,
+        Err(e) => {
+            error!(plugin = %plugin.name, "Not reloading plugin {}: {e:#}", plugin.metadata.display);
+            return;
+        }
+    };
+
+    if let Err(e) = unload(&plugin.name) {
+        error!(plugin = %plugin.name, "Not reloading plugin {}: {e}", plugin.metadata.display);
+        return;
+    }
+
+    let tracker = Arc::new(Tracker::new());
+
+    // same as the first load; the plugin may ExitThread, so it gets a thread of its own
+    let mut m = ThreadManager::new();
+    m.spawn({
+        let tracker = tracker.clone();
+        move || {
+            load_plugin(
+                plugin.name,
+                plugin.path,
+                Some(copy),
+                Duration::ZERO,
+                plugin.metadata,
+                &tracker,
+            );
+        }
+    });
+    drop(m);
+
+    for name in tracker.abandon_unfinished() {
+        services::finished(&name);
+        send(Receive::PluginExited { name });
+    }
+}
+
+/// Copy a rebuilt dll, after checking it against plugins.lock like the first load did
+fn checked_copy(plugin: &Watched) -> Result<PathBuf> {
+    let image = fs::read(&plugin.path).context("failed to read it")?;
+    let plugins_dir = get_bg3_plugins_dir()?;
+
+    let trust = OPTIONS.get().map(|o| o.trust).unwrap_or_default();
+    if trust != TrustMode::Off
+        && let Some(problem) = untrusted(
+            &load_lockfile(&plugins_dir, trust),
+            &plugin.name,
+            &lock_path(&plugins_dir, &plugin.path),
+            &image,
+        )
+    {
+        if trust == TrustMode::Enforce {
+            bail!(
+                "{problem}. Approve it with `yabg3nml plugins approve {}` if it is trusted",
+                plugin.name
+            );
+        }
+
+        warn!(plugin = %plugin.name, "Plugin {} is untrusted: {problem}", plugin.metadata.display);
+    }
+
+    shadow::copy(&plugins_dir, &plugin.path, &image)
+}
+
+/// Let a plugin clean up through its `Deinit` or `Shutdown` export, then free it. A plugin
+/// without either is left loaded, unless it opted in with [plugins.<name>]reload_without_deinit
+fn unload(name: &str) -> Result<()> {
+    let mut plugins = LOADED_PLUGINS.lock();
+    let Some(idx) = plugins
+        .iter()
+        .position(|p| UniCase::new(p.name.as_str()) == UniCase::new(name))
+    else {
+        return Ok(());
+    };
+
+    // SAFETY: Standard function, and proper args
+    let deinit = [s!("Deinit"), s!("Shutdown")]
+        .into_iter()
+        .find_map(|symbol| unsafe { GetProcAddress(plugins[idx].module, symbol) });
+
+    if deinit.is_none() {
+        let opted_in = OPTIONS.get().is_some_and(|o| {
+            o.without_deinit
+                .iter()
+                .any(|p| UniCase::new(p.as_str()) == UniCase::new(name))
+        });
+
+        if !opted_in {
+            bail!(
+                "it has no Deinit or Shutdown export, so any hooks or threads it left running would crash the game once it is unloaded. Set reload_without_deinit in [plugins.{name}] to reload it anyway"
+            );
+        }
+
+        warn!(
+            plugin = %name,
+            "Plugin {name} has no Deinit or Shutdown export; unloading it anyway since reload_without_deinit is set"
+        );
+    }
+
+    let plugin = plugins.remove(idx);
+    drop(plugins);
+
+    api::shutdown(Some(plugin.module.0));
+    services::remove(plugin.module.0 as usize);
+
+    if let Some(deinit) = deinit {
+        type FarProc = unsafe extern "system" fn() -> isize;
+        type Deinit = unsafe extern "C" fn();
+
+        // SAFETY: We declared the signature to be `unsafe extern "C" fn()`. Implementer must abide by this
+        #[allow(non_snake_case)]
+        let Deinit = unsafe { mem::transmute::<FarProc, Deinit>(deinit) };
+
+        trace!(plugin = %name, "running Deinit");
+
+        // SAFETY: Guaranteed by implementer to not be UB
+        unsafe {
+            Deinit();
+        }
+    }
+
+    // frees the module
+    drop(plugin);
+
+    info!("Unloaded plugin {name}");
+
+    Ok(())
+}
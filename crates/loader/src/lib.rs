@@ -1,7 +1,15 @@
+mod api;
 mod client;
+mod control;
+mod deps;
+mod hot_reload;
 mod loader;
 mod logging;
+mod order;
 mod panic_hook;
+mod report;
+mod services;
+mod shadow;
 mod utils;
 
 use std::{
@@ -33,6 +41,7 @@ use windows::{
 use client::{CLIENT, TrySend as _};
 use loader::load_plugins;
 use logging::setup_logging;
+use services::Registry;
 use shared::utils::ThreadedWrapper;
 use utils::Plugin;
 
@@ -48,6 +57,8 @@ declare_plugin! {
 }
 
 static LOADED_PLUGINS: LazyLock<Mutex<Vec<Plugin>>> = LazyLock::new(Mutex::default);
+/// Interfaces plugins published for each other
+static SERVICES: LazyLock<Registry> = LazyLock::new(Registry::default);
 static MODULE: OnceLock<ThreadedWrapper<HINSTANCE>> = OnceLock::new();
 
 #[unsafe(no_mangle)]
@@ -75,6 +86,8 @@ extern "system" fn DllMain(
         DLL_PROCESS_DETACH => {
             trace!("detaching plugins");
 
+            api::shutdown(None);
+
             let mut plugins = LOADED_PLUGINS.lock();
             // drop all modules if we can
             plugins.clear();
@@ -135,8 +148,14 @@ unsafe extern "system" fn InitLoader(data: *mut c_void) -> u32 {
 
         setup_logging(&data.log).context("failed to setup logging")?;
 
+        // SAFETY: the host wrote the config buffer into our process before starting this thread
+        let config = unsafe { data.config() }.context("failed to read config sent by host")?;
+
         // blocking call which waits for all plugins to finish DllMain/Init
-        load_plugins()?;
+        load_plugins(&config, data.safe_mode)?;
+
+        // the host may ask for reloads and log level changes from here on
+        control::listen();
 
         Ok::<_, Error>(())
     });
@@ -1,91 +1,200 @@
-use std::{fs, iter, mem, os::windows::ffi::OsStrExt, path::PathBuf};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet, hash_map::Entry},
+    env, fs, iter, mem,
+    os::windows::ffi::OsStrExt,
+    path::{Path, PathBuf},
+    process,
+    sync::{
+        Arc,
+        mpsc::{self, RecvTimeoutError},
+    },
+    thread,
+    time::Duration,
+};
 
 use eyre::{Context as _, Report, Result};
 use native_plugin_lib::{Dll, PluginData, PluginError, Version};
-use shared::{config::get_config, paths::get_bg3_plugins_dir, popup::warn_popup, utils::tri};
+use shared::{
+    config::{Config, GameExe, LoadMode, TrustMode},
+    paths::get_bg3_plugins_dir,
+    pipe::commands::{LoadStatus, LoadSummary, PluginMetadata, Receive},
+    plugins::{
+        Lockfile, Manifest, PluginFile, Trust, Version as PluginVersion, VersionReq, find_plugins,
+        imports::LoadPath,
+        lock_path,
+        pe::{self, Severity},
+    },
+    popup::warn_popup,
+    utils::tri,
+};
 use tracing::{error, info, trace, warn};
+use unicase::UniCase;
 use windows::{
-    Win32::System::LibraryLoader::{GetProcAddress, LoadLibraryW},
+    Win32::System::LibraryLoader::{GetProcAddress, LOAD_WITH_ALTERED_SEARCH_PATH, LoadLibraryExW},
     core::{PCWSTR, s},
 };
 
-use crate::{LOADED_PLUGINS, Plugin, utils::ThreadManager};
+use crate::{
+    LOADED_PLUGINS, Plugin,
+    client::{CLIENT, TrySend as _},
+    deps, hot_reload, logging,
+    order::{self, Node},
+    report::Tracker,
+    services, shadow,
+    utils::{Job, ThreadManager, game_version, run_in_order},
+};
 
-pub fn load_plugins() -> Result<()> {
+/// How often the watchdog checks for hung plugins
+const WATCHDOG_INTERVAL: Duration = Duration::from_millis(250);
+
+/// In safe mode every check still runs, but plugins which pass them are only reported
+pub fn load_plugins(config: &Config, safe_mode: bool) -> Result<()> {
     // SAFETY:
     // Any spawned threads MUST be joined. This is taken care of by ThreadManager,
     // but it is still an unsafe requirement that could be circumvented.
     // This function is safe because we upheld this requirement
 
     let plugins_dir = get_bg3_plugins_dir()?;
-    let config = get_config()?.get();
+
+    send(Receive::LoadStarted { pid: process::id() });
+
+    shadow::clean_stale(&plugins_dir);
 
     if !config.core.enabled {
         info!(
             "Plugins are globally disabled. If you want to re-enable them, set [core]enabled in config.toml to true"
         );
+        send(Receive::LoadComplete(LoadSummary::default()));
         return Ok(());
     }
 
-    let read_dir = fs::read_dir(plugins_dir).context("failed to read plugins_dir {plugins_dir}");
-    let Ok(read_dir) = read_dir else {
-        error!(?read_dir, "failed to read plugins dir");
+    if let Some((profile, _)) = config.active_profile() {
+        info!("Using profile {profile}");
+    }
+
+    if safe_mode {
+        warn!("Safe mode is on; no plugins will be loaded");
+    } else if config.core.hot_reload {
+        warn!("Hot reload is on; plugins are loaded from copies and reloaded when they change");
+        hot_reload::enable(config);
+    }
+
+    let trust = config.core.trust;
+
+    // with trust on, what loads must be exactly what was checked. The original could be
+    // swapped between the check and LoadLibrary, but a copy of the checked image can't
+    let shadow_copy = !safe_mode
+        && (config.core.shadow_copy || config.core.hot_reload || trust != TrustMode::Off);
+
+    let plugins = find_plugins(&plugins_dir).context("failed to read plugins_dir {plugins_dir}");
+    let Ok(plugins) = plugins else {
+        error!(?plugins, "failed to read plugins dir");
 
         warn_popup(
             "Failed to read plugins dir",
             "Attempted to read plugins dir, but failed opening it\n\nDo you have correct perms? See log for more details",
         );
 
+        send(Receive::LoadComplete(LoadSummary::default()));
         return Ok(());
     };
 
-    let mut m = ThreadManager::new();
+    let exe = env::current_exe().ok().and_then(|p| GameExe::from_path(&p));
+    let game_version = game_version();
+    let loader_version = env!("CARGO_PKG_VERSION")
+        .parse::<PluginVersion>()
+        .expect("crate version is a valid version");
 
-    for entry in read_dir {
-        let Ok(entry) = entry else {
-            warn!(?entry, "skipping unreadable dir entry");
-            continue;
-        };
+    trace!(?game_version, %loader_version, "versions for manifest checks");
+
+    let lockfile = load_lockfile(&plugins_dir, trust);
+
+    let mut pending = Vec::new();
+
+    for PluginFile {
+        name,
+        mut path,
+        dir,
+        manifest,
+    } in plugins
+    {
+        send(Receive::PluginDiscovered {
+            name: name.clone(),
+            path: path.clone(),
+        });
 
-        let mut path = entry.path();
         // lowercase the path for comparisons
         path.as_mut_os_str().make_ascii_lowercase();
 
-        // not a file or dll
-        if !path.is_file()
-            || !path
-                .extension()
-                .unwrap_or_default()
-                .eq_ignore_ascii_case("dll")
-        {
+        let name = name.as_str();
+
+        let file = match &dir {
+            Some(dir) => format!(
+                "{}/{name}.dll",
+                dir.file_name().unwrap_or_default().to_string_lossy()
+            ),
+            None => format!("{name}.dll"),
+        };
+
+        // plugins which won't load aren't read, so a broken one doesn't count as failed
+        if config.is_plugin_disabled(name) {
+            info!("Skipping disabled plugin {file}");
+            skipped(name, "disabled");
             continue;
         }
 
-        // check if plugin is disallowed or allowed
-        let name = {
-            let name = path
-                .file_stem()
-                .unwrap_or_default()
-                .to_str()
-                .unwrap_or_default();
+        let options = config.plugin(name).cloned().unwrap_or_default();
 
-            if name.is_empty() { "<unknown>" } else { name }
+        if let Some(only) = options.only_for_exe
+            && exe != Some(only)
+        {
+            info!("Skipping plugin {file} since it is only for the {only} exe");
+            skipped(name, format!("only for the {only} exe"));
+            continue;
+        }
+
+        // catch dlls which can't load, and say why, before the opaque LoadLibrary error would
+        let image = match fs::read(&path) {
+            Ok(image) => image,
+            Err(e) => {
+                error!(plugin = %name, "Skipping plugin {file}: failed to read it: {e}");
+                skipped(name, format!("failed to read it: {e}"));
+                continue;
+            }
         };
 
+        let diagnostics = pe::validate(&image);
+
+        if let Some(e) = diagnostics.iter().find(|d| d.severity == Severity::Error) {
+            error!(plugin = %name, "Skipping plugin {file}: it {e}");
+            skipped(name, format!("it {e}"));
+            continue;
+        }
+
         let dll = match Dll::new(&path) {
             Ok(dll) => dll,
             Err(e) => {
                 error!(plugin = %name, ?e, "failed to open dll");
+                skipped(name, format!("failed to open dll: {e}"));
                 continue;
             }
         };
 
         if dll.symbol_exists("__NOT_A_PLUGIN_DO_NOT_LOAD_OR_YOU_WILL_BE_FIRED") {
             trace!(plugin = %name, "aborting load because this is not a plugin");
+            skipped(name, "not a plugin");
             continue;
         }
 
-        let name_formatted = {
+        // a broken manifest only matters once we know the plugin is meant to load
+        let manifest = manifest.as_deref().map(Manifest::load).transpose();
+        let manifest_version = manifest
+            .as_ref()
+            .ok()
+            .flatten()
+            .and_then(|m| m.version.clone());
+
+        let (name_formatted, version) = {
             let data = PluginData::from_dll(dll);
 
             match data {
@@ -98,10 +207,17 @@ pub fn load_plugins() -> Result<()> {
                         patch,
                     } = data.version;
 
+                    let version = manifest_version.unwrap_or_else(|| {
+                        PluginVersion::new([u64::from(major), u64::from(minor), u64::from(patch)])
+                    });
+
                     let p_name = data.name;
                     let author = data.author;
 
-                    format!("{p_name} by {author} v{major}.{minor}.{patch} ({name}.dll)")
+                    (
+                        format!("{p_name} by {author} v{version} ({file})"),
+                        Some(version),
+                    )
                 }
 
                 Err(e) => {
@@ -111,61 +227,470 @@ pub fn load_plugins() -> Result<()> {
                         _ => trace!(plugin = %name, ?e),
                     }
 
-                    format!("{name}.dll")
+                    match manifest_version {
+                        Some(version) => (format!("{name} v{version} ({file})"), Some(version)),
+                        None => (file, None),
+                    }
                 }
             }
         };
 
-        if config.core.is_plugin_disabled(name) {
-            info!("Skipping disabled plugin {name_formatted}");
+        if trust != TrustMode::Off
+            && let Some(problem) =
+                untrusted(&lockfile, name, &lock_path(&plugins_dir, &path), &image)
+        {
+            if trust == TrustMode::Enforce {
+                error!(plugin = %name, "Skipping plugin {name_formatted}: {problem}. Approve it with `yabg3nml plugins approve {name}` if it is trusted");
+                skipped(name, problem);
+                continue;
+            }
+
+            warn!(plugin = %name, "Plugin {name_formatted} is untrusted: {problem}");
+        }
+
+        let manifest = match manifest {
+            Ok(manifest) => manifest.unwrap_or_default(),
+            Err(e) => {
+                error!(plugin = %name, "Skipping plugin {name_formatted}: {e:#}");
+                skipped(name, format!("{e:#}"));
+                continue;
+            }
+        };
+
+        if let Err(e) = manifest.check_compat(&loader_version, game_version.as_ref()) {
+            error!(plugin = %name, "Skipping plugin {name_formatted}: {e}");
+            skipped(name, e.to_string());
             continue;
         }
 
-        info!("Loading plugin {name_formatted}");
+        for d in &diagnostics {
+            warn!(plugin = %name, "Plugin {name_formatted} {d}");
+        }
 
-        // do not join the handle, or it will panic
-        // this is because we use ExitThread which yanks the thread out from
-        // underneath rust. it does not expect this
-        m.spawn({
-            let name = name.to_owned();
-            move || load_plugin(name, path)
+        let copy = if shadow_copy {
+            match shadow::copy(&plugins_dir, &path, &image) {
+                Ok(copy) => Some(copy),
+                Err(e) => {
+                    error!(plugin = %name, "Skipping plugin {name_formatted}: {e:#}");
+                    skipped(name, format!("{e:#}"));
+                    continue;
+                }
+            }
+        } else {
+            None
+        };
+
+        let mut requires = options.requires;
+        requires.extend(manifest.dependencies.keys().cloned());
+
+        pending.push(PendingPlugin {
+            name_formatted,
+            path,
+            copy,
+            delay: Duration::from_millis(options.delay_ms),
+            load_mode: options.load_mode.unwrap_or(config.core.load_mode),
+            id: manifest.id,
+            version,
+            dependencies: manifest.dependencies,
+            conflicts: manifest.conflicts,
+            node: Node {
+                name: name.to_owned(),
+                load_order: options.load_order,
+                after: options.after,
+                before: options.before,
+                requires,
+            },
         });
     }
 
+    check_manifests(&mut pending);
+
+    trace!(mode = %config.core.load_mode, "load mode");
+
+    let plan = {
+        let nodes = pending.iter().map(|p| p.node.clone()).collect::<Vec<_>>();
+        order::resolve(&nodes)
+    };
+
+    for (idx, e) in &plan.failed {
+        let plugin = &pending[*idx];
+        error!(plugin = %plugin.node.name, "Skipping plugin {}: {e}", plugin.name_formatted);
+        skipped(&plugin.node.name, e.to_string());
+    }
+
+    // plugins safe mode would have loaded; the tracker knows which ones actually did
+    let mut would_load = HashSet::new();
+    let tracker = Arc::new(Tracker::new());
+
+    // nothing can stop a hung plugin, but we can at least say which one it is
+    let (stop_watchdog, stop) = mpsc::channel::<()>();
+    let watchdog = (config.core.init_deadline_ms > 0).then(|| {
+        let deadline = Duration::from_millis(config.core.init_deadline_ms);
+        let tracker = tracker.clone();
+
+        thread::spawn(move || {
+            while let Err(RecvTimeoutError::Timeout) = stop.recv_timeout(WATCHDOG_INTERVAL) {
+                tracker.check(deadline);
+            }
+        })
+    });
+    let mut pending = pending.into_iter().map(Some).collect::<Vec<_>>();
+
+    for (n, wave) in plan.waves.into_iter().enumerate() {
+        trace!(wave = n, "starting load wave");
+
+        let mut m = ThreadManager::new();
+        // run after the parallel plugins were started, one at a time in wave order
+        let mut sequential = Vec::<Job>::new();
+
+        for idx in wave {
+            let Some(PendingPlugin {
+                name_formatted,
+                path,
+                copy,
+                delay,
+                load_mode,
+                id,
+                version,
+                node,
+                ..
+            }) = pending[idx].take()
+            else {
+                continue;
+            };
+
+            // the ordering only knows what is installed, not what actually loaded
+            let failed_dep = node
+                .requires
+                .iter()
+                .find(|r| {
+                    !would_load.contains(&UniCase::new(r.to_string())) && !tracker.is_loaded(r)
+                })
+                .cloned();
+
+            if let Some(dep) = failed_dep {
+                error!(plugin = %node.name, "Skipping plugin {name_formatted}: required plugin {dep} failed to load");
+                skipped(&node.name, format!("required plugin {dep} failed to load"));
+                continue;
+            }
+
+            if safe_mode {
+                info!("Safe mode: would load plugin {name_formatted}");
+                skipped(&node.name, "safe mode");
+
+                // count it as loaded so its dependents are reported as they would load too
+                would_load.insert(UniCase::new(node.name));
+                continue;
+            }
+
+            // before any of them runs, so a plugin waiting on a service in Init knows every
+            // plugin which may still publish it
+            if load_mode == LoadMode::Parallel {
+                services::starting(&node.name);
+            }
+
+            let job = {
+                let tracker = tracker.clone();
+                move || {
+                    info!("Loading plugin {name_formatted}");
+
+                    let metadata = PluginMetadata {
+                        display: name_formatted,
+                        id,
+                        version: version.map(|v| v.to_string()),
+                    };
+
+                    load_plugin(node.name, path, copy, delay, metadata, &tracker);
+                }
+            };
+
+            // do not join the handle, or it will panic
+            // this is because we use ExitThread which yanks the thread out from
+            // underneath rust. it does not expect this
+            match load_mode {
+                LoadMode::Parallel => m.spawn(job),
+                LoadMode::Sequential => sequential.push(Box::new(job)),
+            }
+        }
+
+        // the worker thread copes with ExitThread the same way
+        run_in_order(sequential);
+
+        // dependents in the next wave may only start once every Init in this one returned
+        drop(m);
+
+        for name in tracker.abandon_unfinished() {
+            services::finished(&name);
+            send(Receive::PluginExited { name });
+        }
+    }
+
+    drop(stop_watchdog);
+    if let Some(watchdog) = watchdog {
+        _ = watchdog.join();
+    }
+
+    let summary = tracker.summary();
+    info!("{summary}");
+    send(Receive::LoadComplete(summary));
+
+    if hot_reload::is_enabled() {
+        hot_reload::watch();
+    }
+
     Ok(())
 }
 
-fn load_plugin(name: String, path: PathBuf) {
+/// Tell the host how loading is going. Nothing is lost if it isn't listening
+pub fn send(event: Receive) {
+    _ = CLIENT.try_send(event.into());
+}
+
+fn skipped(name: &str, reason: impl Into<String>) {
+    send(Receive::PluginSkipped {
+        name: name.to_owned(),
+        reason: reason.into(),
+    });
+}
+
+/// Why a plugin doesn't match plugins.lock, if it doesn't. `path` is relative to the plugins dir
+pub fn untrusted(lockfile: &Lockfile, name: &str, path: &str, image: &[u8]) -> Option<String> {
+    match lockfile.check(name, path, image) {
+        Trust::Approved => None,
+        Trust::Unapproved => Some("it is not approved in plugins.lock".to_owned()),
+        Trust::Changed { approved, actual } => Some(format!(
+            "it changed since it was approved in plugins.lock (approved sha256 {approved}, now {actual})"
+        )),
+        Trust::Moved { approved, actual } => Some(format!(
+            "plugins.lock approved {approved} under this name, not {actual}"
+        )),
+    }
+}
+
+/// The approved plugin hashes, if `trust` checks them
+pub fn load_lockfile(plugins_dir: &Path, trust: TrustMode) -> Lockfile {
+    match trust {
+        TrustMode::Off => Lockfile::default(),
+        _ => Lockfile::load(plugins_dir).unwrap_or_else(|e| {
+            // approving nothing is the safe choice; enforce then refuses every plugin
+            error!("{e:#}; treating every plugin as unapproved");
+            Lockfile::default()
+        }),
+    }
+}
+
+/// A plugin which passed all checks and is waiting to be loaded
+struct PendingPlugin {
+    name_formatted: String,
+    path: PathBuf,
+    /// The shadow copy to load instead, with [core]shadow_copy, hot_reload or trust
+    copy: Option<PathBuf>,
+    delay: Duration,
+    load_mode: LoadMode,
+    /// The manifest id, if it declared one
+    id: Option<String>,
+    /// From the manifest, else from the dll's plugin data
+    version: Option<PluginVersion>,
+    dependencies: BTreeMap<String, VersionReq>,
+    conflicts: Vec<String>,
+    node: Node,
+}
+
+/// Resolve manifest ids to plugin names, then drop plugins whose dependencies are
+/// the wrong version or which conflict with another plugin
+///
+/// Missing dependencies are left to the ordering, which also fails their dependents
+fn check_manifests(pending: &mut Vec<PendingPlugin>) {
+    let mut ids = HashMap::new();
+    for plugin in pending.iter() {
+        let Some(id) = &plugin.id else {
+            continue;
+        };
+
+        match ids.entry(UniCase::new(id.clone())) {
+            Entry::Vacant(entry) => {
+                entry.insert(plugin.node.name.clone());
+            }
+
+            Entry::Occupied(entry) => {
+                warn!(
+                    plugin = %plugin.node.name,
+                    "plugin id {id} is already used by {}; refer to this plugin by name instead",
+                    entry.get()
+                );
+            }
+        }
+    }
+
+    let resolve = |name: &String| {
+        ids.get(&UniCase::new(name.clone()))
+            .cloned()
+            .unwrap_or_else(|| name.clone())
+    };
+
+    for plugin in pending.iter_mut() {
+        let node = &mut plugin.node;
+        for list in [&mut node.after, &mut node.before, &mut node.requires] {
+            *list = list.iter().map(resolve).collect();
+        }
+
+        plugin.dependencies = mem::take(&mut plugin.dependencies)
+            .into_iter()
+            .map(|(dep, req)| (resolve(&dep), req))
+            .collect();
+
+        plugin.conflicts = plugin.conflicts.iter().map(resolve).collect();
+    }
+
+    let find = |name: &str| {
+        pending
+            .iter()
+            .find(|p| UniCase::new(p.node.name.as_str()) == UniCase::new(name))
+    };
+
+    // decide against the full set first, so that two plugins conflicting with each other both go
+    let mut failed = Vec::new();
+    for (idx, plugin) in pending.iter().enumerate() {
+        let name = &plugin.node.name;
+
+        for (dep, req) in &plugin.dependencies {
+            let Some(dep) = find(dep) else {
+                continue;
+            };
+
+            match &dep.version {
+                Some(version) if !req.matches(version) => failed.push((
+                    idx,
+                    format!(
+                        "requires {} {req}, but version {version} is installed",
+                        dep.node.name
+                    ),
+                )),
+
+                None if !req.is_any() => warn!(
+                    plugin = %name,
+                    "cannot check that {} matches {req} since it has no version",
+                    dep.node.name
+                ),
+
+                _ => (),
+            }
+        }
+
+        for conflict in &plugin.conflicts {
+            if let Some(other) = find(conflict)
+                && UniCase::new(&other.node.name) != UniCase::new(name)
+            {
+                failed.push((idx, format!("conflicts with {}", other.name_formatted)));
+            }
+        }
+    }
+
+    for (idx, e) in &failed {
+        let plugin = &pending[*idx];
+        error!(plugin = %plugin.node.name, "Skipping plugin {}: {e}", plugin.name_formatted);
+        skipped(&plugin.node.name, e.to_string());
+    }
+
+    let mut idx = 0;
+    pending.retain(|_| {
+        let keep = !failed.iter().any(|(i, _)| *i == idx);
+        idx += 1;
+        keep
+    });
+}
+
+/// Load a plugin and run its Init, reporting how it went to the tracker and the host
+///
+/// `path` is the original dll; `copy` is its shadow copy to load instead, if any
+pub fn load_plugin(
+    name: String,
+    path: PathBuf,
+    copy: Option<PathBuf>,
+    delay: Duration,
+    metadata: PluginMetadata,
+    tracker: &Tracker,
+) {
+    if !delay.is_zero() {
+        trace!(plugin = %name, ?delay, "delaying load");
+        thread::sleep(delay);
+    }
+
+    // so everything logged while it loads is attributed to it
+    let _attribution = logging::attribute(&name);
+
+    tracker.start(&name);
+    services::starting(&name);
+    send(Receive::PluginLoading { name: name.clone() });
+
+    if hot_reload::is_enabled() {
+        hot_reload::register(&name, &path, &metadata);
+    }
+
     // wrap this in try{} block and return result
     // by doing this we can return the self library guard and
     // prevent a shutdown until the end of this scope
     //
     // The purpose of doing that so we can
     let result = tri! {
-        let plugin_path = path
+        let plugin_path = copy
+            .as_ref()
+            .unwrap_or(&path)
             .as_os_str()
             .encode_wide()
             .chain(iter::once(0))
             .collect::<Vec<_>>();
 
         // SAFETY: Standard function, and our string is formatted properly
+        //
+        // altered search path resolves the plugin's own imports from its folder first,
+        // so helper dlls shipped next to it are found
         let module = {
-            let path = PCWSTR::from_raw(plugin_path.as_ptr());
-            let res = unsafe { LoadLibraryW(path) };
+            let lib = PCWSTR::from_raw(plugin_path.as_ptr());
+            let res = match &copy {
+                Some(_) => shadow::load(lib, &path),
+                None => unsafe { LoadLibraryExW(lib, None, LOAD_WITH_ALTERED_SEARCH_PATH) },
+            };
 
             match res {
                 Ok(v) => v,
                 Err(e) => {
                     error!(plugin = %name, err = ?e, "failed to load library");
+
+                    // nearly always a dependency which isn't installed or wasn't shipped with it
+                    let load = match &copy {
+                        Some(_) => LoadPath::ShadowCopy,
+                        None => LoadPath::Original,
+                    };
+
+                    let missing = deps::missing_dlls(&path, load);
+                    for dll in &missing {
+                        error!(plugin = %name, "missing dependency {dll}");
+                    }
+
+                    if !missing.is_empty() {
+                        let names = missing.iter().map(|m| m.name.as_str()).collect::<Vec<_>>();
+                        return Err(e).context(format!(
+                            "failed to load library; missing {}",
+                            names.join(", ")
+                        ));
+                    }
+
                     return Err(e).context("failed to load library")
                 }
             }
         };
 
+        tracker.loaded(&name);
+
         // so plugin can be unloaded on dll exit
         {
             let mut plugins = LOADED_PLUGINS.lock();
-            plugins.push(Plugin(module));
+            plugins.push(Plugin {
+                name: name.clone(),
+                metadata: metadata.clone(),
+                module,
+            });
         }
 
         // SAFETY: Standard function, and again proper args
@@ -179,6 +704,7 @@ fn load_plugin(name: String, path: PathBuf) {
             let Init = unsafe { mem::transmute::<FarProc, Init>(init) };
 
             trace!(plugin = %name, "running Init");
+            tracker.init(&name);
 
             // SAFETY: Guaranteed by implementer to not be UB
             //         Plugin is responsible
@@ -192,9 +718,28 @@ fn load_plugin(name: String, path: PathBuf) {
         Ok::<_, Report>(())
     };
 
-    if let Err(e) = result {
-        error!(plugin = %name, path = %path.display(), %e, "load_plugin failed");
+    match result {
+        Ok(()) => {
+            let (load, init) = tracker.finish(&name, LoadStatus::Loaded);
+            send(Receive::PluginLoaded {
+                name: name.clone(),
+                metadata,
+                load: load.unwrap_or_default(),
+                init,
+            });
+        }
+
+        Err(e) => {
+            error!(plugin = %name, path = %path.display(), %e, "load_plugin failed");
+            tracker.finish(&name, LoadStatus::Failed(e.to_string()));
+            send(Receive::PluginFailed {
+                name: name.clone(),
+                error: e.to_string(),
+            });
+        }
     }
 
+    services::finished(&name);
+
     trace!(plugin = %name, "exit load plugin");
 }
@@ -1,24 +1,80 @@
-use std::io::{self, ErrorKind, Write};
+use std::{
+    cell::RefCell,
+    io::{self, ErrorKind, Write},
+    sync::OnceLock,
+};
 
 use eyre::Result;
 use sayuri::sync::{Mutex, MutexGuard};
-use shared::{pipe::commands::Receive, popup::warn_popup, thread_data::LogData};
-use tracing_subscriber::{fmt::MakeWriter, util::SubscriberInitExt};
+use shared::{
+    pipe::commands::{LogMsg, Receive},
+    popup::warn_popup,
+    thread_data::LogData,
+};
+use tracing::level_filters::LevelFilter;
+use tracing_subscriber::{EnvFilter, fmt::MakeWriter, util::SubscriberInitExt};
 
 use crate::client::{CLIENT, TrySend};
 
+type ReloadFn = Box<dyn Fn(EnvFilter) -> Result<()> + Send + Sync>;
+
+/// Swaps out the filter of the global subscriber
+static RELOAD: OnceLock<ReloadFn> = OnceLock::new();
+
+thread_local! {
+    /// The plugin whose load runs on this thread, which its logs are attributed to
+    static PLUGIN: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// Attributes this thread's logs to a plugin until dropped
+pub struct Attribution(Option<String>);
+
+impl Drop for Attribution {
+    fn drop(&mut self) {
+        PLUGIN.set(self.0.take());
+    }
+}
+
+/// Attribute logs on this thread without their own `plugin` field to `plugin`, until the
+/// returned guard drops
+pub fn attribute(plugin: &str) -> Attribution {
+    Attribution(PLUGIN.replace(Some(plugin.to_owned())))
+}
+
+/// The plugin this thread's logs are attributed to, if any
+pub fn attributed() -> Option<String> {
+    PLUGIN.with_borrow(Clone::clone)
+}
+
+fn make_filter(level: LevelFilter) -> EnvFilter {
+    EnvFilter::default().add_directive(level.into())
+}
+
 pub fn setup_logging(data: &LogData) -> Result<()> {
     let maker = PipeMaker::new();
 
-    tracing_subscriber::fmt()
+    let builder = tracing_subscriber::fmt()
         .with_line_number(true)
         .with_file(true)
         .json()
-        .with_max_level(data.level)
+        .with_env_filter(make_filter(data.level.into()))
         .with_writer(maker)
         .with_target(data.target)
-        .finish()
-        .init();
+        .with_filter_reloading();
+
+    let handle = builder.reload_handle();
+    _ = RELOAD.set(Box::new(move |f| Ok(handle.reload(f)?)));
+
+    builder.finish().init();
+
+    Ok(())
+}
+
+/// Log at a new level, which the host sends when its config changes
+pub fn set_level(level: LevelFilter) -> Result<()> {
+    if let Some(reload) = RELOAD.get() {
+        reload(make_filter(level))?;
+    }
 
     Ok(())
 }
@@ -75,7 +131,7 @@ impl Write for PipeWriter<'_> {
 
         let v = BufClear(self.buf.lock());
 
-        let data = match v.0.as_slice().try_into() {
+        let mut data: LogMsg = match v.0.as_slice().try_into() {
             Ok(v) => v,
             Err(e) => {
                 warn_popup(
@@ -88,6 +144,12 @@ impl Write for PipeWriter<'_> {
             }
         };
 
+        // the writer runs on the thread which emitted the record
+        data.plugin = data
+            .fields
+            .remove("plugin")
+            .or_else(|| PLUGIN.with_borrow(Clone::clone));
+
         let c = Receive::Log(data);
         CLIENT.try_send(c.into())?;
 
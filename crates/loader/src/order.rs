@@ -0,0 +1,478 @@
+use std::{
+    collections::HashMap,
+    fmt::{self, Display},
+};
+
+use unicase::UniCase;
+
+/// A plugin and the ordering rules it declared
+#[derive(Debug, Clone, Default)]
+pub struct Node {
+    pub name: String,
+    pub load_order: i32,
+    /// Load after these plugins, if they are present
+    pub after: Vec<String>,
+    /// Load before these plugins, if they are present
+    pub before: Vec<String>,
+    /// Load after these plugins, and do not load at all if they are missing
+    pub requires: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+pub enum OrderError {
+    /// Required plugins are not installed or are disabled
+    Missing(Vec<String>),
+    /// The plugin is part of a dependency cycle
+    Cycle(Vec<String>),
+    /// A required plugin could not be ordered itself
+    Dependency(String),
+}
+
+impl Display for OrderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Missing(missing) => {
+                write!(f, "required plugin(s) missing: {}", missing.join(", "))
+            }
+
+            Self::Cycle(cycle) => {
+                write!(
+                    f,
+                    "dependency cycle: {} -> {}",
+                    cycle.join(" -> "),
+                    cycle[0]
+                )
+            }
+
+            Self::Dependency(dep) => write!(f, "required plugin {dep} cannot be loaded"),
+        }
+    }
+}
+
+/// The result of ordering. Indices refer to the slice passed to [`resolve`]
+#[derive(Debug, Default)]
+pub struct Plan {
+    /// Each wave may only start once the previous wave has finished.
+    /// Within a wave, plugins are sorted by load order, then name
+    pub waves: Vec<Vec<usize>>,
+    /// Plugins which can't be loaded, and why
+    pub failed: Vec<(usize, OrderError)>,
+}
+
+struct Edge {
+    to: usize,
+    /// `to` requires this node, rather than merely wanting to load after it
+    hard: bool,
+}
+
+struct Graph<'a> {
+    nodes: &'a [Node],
+    outgoing: Vec<Vec<Edge>>,
+    incoming: Vec<Vec<usize>>,
+    indegree: Vec<usize>,
+    /// still waiting to be placed in a wave
+    remaining: Vec<bool>,
+    failed: Vec<(usize, OrderError)>,
+}
+
+impl Graph<'_> {
+    fn add_edge(&mut self, from: usize, to: usize, hard: bool) {
+        if from == to {
+            return;
+        }
+
+        // a hard edge supersedes a soft one between the same nodes
+        if let Some(edge) = self.outgoing[from].iter_mut().find(|e| e.to == to) {
+            edge.hard |= hard;
+            return;
+        }
+
+        self.outgoing[from].push(Edge { to, hard });
+        self.incoming[to].push(from);
+        self.indegree[to] += 1;
+    }
+
+    /// Remove nodes from the graph without loading them. Anything which requires them fails as well
+    fn fail(&mut self, failures: Vec<(usize, OrderError)>) {
+        // remove all of them up front, so that e.g. every member of a cycle gets the cycle
+        // error instead of a dependency error from a fellow member
+        let mut removed = Vec::new();
+        for (idx, error) in failures {
+            if self.remaining[idx] {
+                self.remaining[idx] = false;
+                self.failed.push((idx, error));
+                removed.push(idx);
+            }
+        }
+
+        while let Some(idx) = removed.pop() {
+            for edge in &self.outgoing[idx] {
+                if !self.remaining[edge.to] {
+                    continue;
+                }
+
+                self.indegree[edge.to] -= 1;
+
+                if edge.hard {
+                    let name = self.nodes[idx].name.clone();
+                    self.remaining[edge.to] = false;
+                    self.failed.push((edge.to, OrderError::Dependency(name)));
+                    removed.push(edge.to);
+                }
+            }
+        }
+    }
+
+    /// Every remaining node has a remaining predecessor when this is called,
+    /// so walking predecessors must eventually loop back on itself
+    fn find_cycle(&self) -> Vec<usize> {
+        let start = self.remaining.iter().position(|&r| r).unwrap();
+
+        let mut path = vec![start];
+        let mut node = start;
+        loop {
+            node = self.incoming[node]
+                .iter()
+                .copied()
+                .find(|&n| self.remaining[n])
+                .unwrap();
+
+            if let Some(pos) = path.iter().position(|&n| n == node) {
+                let mut cycle = path.split_off(pos);
+                // we walked backwards, so flip it into load order
+                cycle.reverse();
+                return cycle;
+            }
+
+            path.push(node);
+        }
+    }
+}
+
+/// Sort plugins into load waves so that each plugin loads after everything it depends on
+pub fn resolve(nodes: &[Node]) -> Plan {
+    let index = nodes
+        .iter()
+        .enumerate()
+        .map(|(i, n)| (UniCase::new(n.name.as_str()), i))
+        .collect::<HashMap<_, _>>();
+
+    let find = |name: &str| index.get(&UniCase::new(name)).copied();
+
+    let mut graph = Graph {
+        nodes,
+        outgoing: nodes.iter().map(|_| Vec::new()).collect(),
+        incoming: nodes.iter().map(|_| Vec::new()).collect(),
+        indegree: vec![0; nodes.len()],
+        remaining: vec![true; nodes.len()],
+        failed: Vec::new(),
+    };
+
+    let mut missing = Vec::new();
+
+    for (idx, node) in nodes.iter().enumerate() {
+        for dep in &node.after {
+            if let Some(dep) = find(dep) {
+                graph.add_edge(dep, idx, false);
+            }
+        }
+
+        for dep in &node.before {
+            if let Some(dep) = find(dep) {
+                graph.add_edge(idx, dep, false);
+            }
+        }
+
+        let mut node_missing = Vec::new();
+        for dep in &node.requires {
+            match find(dep) {
+                Some(dep) => graph.add_edge(dep, idx, true),
+                None => node_missing.push(dep.clone()),
+            }
+        }
+
+        if !node_missing.is_empty() {
+            missing.push((idx, node_missing));
+        }
+    }
+
+    graph.fail(
+        missing
+            .into_iter()
+            .map(|(idx, missing)| (idx, OrderError::Missing(missing)))
+            .collect(),
+    );
+
+    let sort_key = |&i: &usize| (nodes[i].load_order, nodes[i].name.to_lowercase());
+
+    let mut plan = Plan::default();
+
+    while graph.remaining.iter().any(|&r| r) {
+        let mut wave = (0..nodes.len())
+            .filter(|&i| graph.remaining[i] && graph.indegree[i] == 0)
+            .collect::<Vec<_>>();
+
+        if wave.is_empty() {
+            let cycle = graph.find_cycle();
+            let names = cycle
+                .iter()
+                .map(|&i| nodes[i].name.clone())
+                .collect::<Vec<_>>();
+
+            graph.fail(
+                cycle
+                    .into_iter()
+                    .map(|idx| (idx, OrderError::Cycle(names.clone())))
+                    .collect(),
+            );
+
+            continue;
+        }
+
+        wave.sort_by_key(sort_key);
+
+        for &idx in &wave {
+            graph.remaining[idx] = false;
+
+            for edge in &graph.outgoing[idx] {
+                graph.indegree[edge.to] -= 1;
+            }
+        }
+
+        plan.waves.push(wave);
+    }
+
+    plan.failed = graph.failed;
+    plan
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(name: &str) -> Node {
+        Node {
+            name: name.to_owned(),
+            ..Default::default()
+        }
+    }
+
+    fn strings(names: &[&str]) -> Vec<String> {
+        names.iter().map(|&n| n.to_owned()).collect()
+    }
+
+    fn waves(nodes: &[Node], plan: &Plan) -> Vec<Vec<String>> {
+        plan.waves
+            .iter()
+            .map(|w| w.iter().map(|&i| nodes[i].name.clone()).collect())
+            .collect()
+    }
+
+    fn failed(nodes: &[Node], plan: &Plan) -> Vec<(String, String)> {
+        let mut failed = plan
+            .failed
+            .iter()
+            .map(|(i, e)| (nodes[*i].name.clone(), e.to_string()))
+            .collect::<Vec<_>>();
+        failed.sort();
+        failed
+    }
+
+    #[test]
+    fn independent_plugins_share_a_wave() {
+        let nodes = [
+            node("b"),
+            Node {
+                load_order: -1,
+                ..node("c")
+            },
+            node("A"),
+        ];
+
+        let plan = resolve(&nodes);
+
+        assert_eq!(waves(&nodes, &plan), [strings(&["c", "A", "b"])]);
+        assert!(plan.failed.is_empty());
+    }
+
+    #[test]
+    fn after_and_before_split_waves() {
+        let nodes = [
+            Node {
+                after: strings(&["base"]),
+                ..node("addon")
+            },
+            node("base"),
+            Node {
+                before: strings(&["BASE", "not installed"]),
+                ..node("early")
+            },
+        ];
+
+        let plan = resolve(&nodes);
+
+        assert_eq!(
+            waves(&nodes, &plan),
+            [strings(&["early"]), strings(&["base"]), strings(&["addon"])]
+        );
+        assert!(plan.failed.is_empty());
+    }
+
+    #[test]
+    fn requires_orders_after_the_dependency() {
+        let nodes = [
+            Node {
+                requires: strings(&["lib"]),
+                ..node("plugin")
+            },
+            node("lib"),
+        ];
+
+        let plan = resolve(&nodes);
+
+        assert_eq!(
+            waves(&nodes, &plan),
+            [strings(&["lib"]), strings(&["plugin"])]
+        );
+    }
+
+    #[test]
+    fn missing_requires_fail_dependents() {
+        let nodes = [
+            Node {
+                requires: strings(&["lib", "other"]),
+                ..node("plugin")
+            },
+            Node {
+                requires: strings(&["plugin"]),
+                ..node("addon")
+            },
+            Node {
+                after: strings(&["plugin"]),
+                ..node("soft")
+            },
+        ];
+
+        let plan = resolve(&nodes);
+
+        assert_eq!(waves(&nodes, &plan), [strings(&["soft"])]);
+        assert_eq!(
+            failed(&nodes, &plan),
+            [
+                (
+                    "addon".to_owned(),
+                    "required plugin plugin cannot be loaded".to_owned()
+                ),
+                (
+                    "plugin".to_owned(),
+                    "required plugin(s) missing: lib, other".to_owned()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn cycles_fail_every_member() {
+        let nodes = [
+            Node {
+                requires: strings(&["c"]),
+                ..node("a")
+            },
+            Node {
+                requires: strings(&["a"]),
+                ..node("b")
+            },
+            Node {
+                after: strings(&["b"]),
+                ..node("c")
+            },
+            Node {
+                requires: strings(&["b"]),
+                ..node("dependent")
+            },
+            Node {
+                after: strings(&["a"]),
+                ..node("soft")
+            },
+            node("free"),
+        ];
+
+        let plan = resolve(&nodes);
+
+        // the cycle is only found once nothing else is left to load
+        assert_eq!(
+            waves(&nodes, &plan),
+            [strings(&["free"]), strings(&["soft"])]
+        );
+
+        let cycle = "dependency cycle: b -> c -> a -> b".to_owned();
+        assert_eq!(
+            failed(&nodes, &plan),
+            [
+                ("a".to_owned(), cycle.clone()),
+                ("b".to_owned(), cycle.clone()),
+                ("c".to_owned(), cycle),
+                (
+                    "dependent".to_owned(),
+                    "required plugin b cannot be loaded".to_owned()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn hard_edge_supersedes_soft() {
+        let nodes = [
+            Node {
+                after: strings(&["lib"]),
+                requires: strings(&["lib"]),
+                ..node("plugin")
+            },
+            Node {
+                requires: strings(&["missing"]),
+                ..node("lib")
+            },
+        ];
+
+        let plan = resolve(&nodes);
+
+        assert!(plan.waves.is_empty());
+        assert_eq!(
+            failed(&nodes, &plan),
+            [
+                (
+                    "lib".to_owned(),
+                    "required plugin(s) missing: missing".to_owned()
+                ),
+                (
+                    "plugin".to_owned(),
+                    "required plugin lib cannot be loaded".to_owned()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn soft_cycle_is_a_cycle() {
+        let nodes = [
+            Node {
+                after: strings(&["b"]),
+                ..node("a")
+            },
+            Node {
+                after: strings(&["a"]),
+                ..node("b")
+            },
+        ];
+
+        let plan = resolve(&nodes);
+
+        assert!(plan.waves.is_empty());
+        assert_eq!(plan.failed.len(), 2);
+        assert!(
+            plan.failed
+                .iter()
+                .all(|(_, e)| matches!(e, OrderError::Cycle(c) if c.len() == 2))
+        );
+    }
+}
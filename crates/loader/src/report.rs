@@ -0,0 +1,174 @@
+use std::time::{Duration, Instant};
+
+use sayuri::sync::Mutex;
+use shared::pipe::commands::{LoadStatus, LoadSummary, PluginTiming};
+use tracing::warn;
+use unicase::UniCase;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Phase {
+    LoadLibrary,
+    Loaded,
+    Init,
+    Done,
+}
+
+struct Entry {
+    name: String,
+    phase: Phase,
+    /// When the current phase started
+    since: Instant,
+    load: Option<Duration>,
+    init: Option<Duration>,
+    status: Option<LoadStatus>,
+    /// How many deadlines have passed in the current phase
+    overdue: u32,
+}
+
+/// Timings of every plugin being loaded, shared between the plugin threads and the watchdog
+pub struct Tracker {
+    started: Instant,
+    entries: Mutex<Vec<Entry>>,
+}
+
+impl Tracker {
+    pub fn new() -> Self {
+        Self {
+            started: Instant::now(),
+            entries: Mutex::default(),
+        }
+    }
+
+    /// LoadLibrary is about to be called
+    pub fn start(&self, name: &str) {
+        self.entries.lock().push(Entry {
+            name: name.to_owned(),
+            phase: Phase::LoadLibrary,
+            since: Instant::now(),
+            load: None,
+            init: None,
+            status: None,
+            overdue: 0,
+        });
+    }
+
+    /// LoadLibrary returned
+    pub fn loaded(&self, name: &str) {
+        self.update(name, |e| {
+            e.load = Some(e.since.elapsed());
+            e.phase = Phase::Loaded;
+            e.since = Instant::now();
+        });
+    }
+
+    /// Init is about to be called
+    pub fn init(&self, name: &str) {
+        self.update(name, |e| {
+            e.phase = Phase::Init;
+            e.since = Instant::now();
+            e.overdue = 0;
+        });
+    }
+
+    /// Returns how long LoadLibrary and Init took
+    pub fn finish(&self, name: &str, status: LoadStatus) -> (Option<Duration>, Option<Duration>) {
+        let mut timings = (None, None);
+        self.update(name, |e| {
+            match e.phase {
+                Phase::LoadLibrary => e.load = Some(e.since.elapsed()),
+                Phase::Init => e.init = Some(e.since.elapsed()),
+                Phase::Loaded | Phase::Done => (),
+            }
+
+            e.phase = Phase::Done;
+            e.status = Some(status);
+            timings = (e.load, e.init);
+        });
+
+        timings
+    }
+
+    /// Warn about plugins which have been in the same phase for longer than `deadline`,
+    /// again each time another `deadline` passes
+    pub fn check(&self, deadline: Duration) {
+        let mut entries = self.entries.lock();
+
+        for e in entries.iter_mut() {
+            let doing = match e.phase {
+                Phase::LoadLibrary => "loading (in DllMain)",
+                Phase::Init => "running Init",
+                Phase::Loaded | Phase::Done => continue,
+            };
+
+            let elapsed = e.since.elapsed();
+            let overdue = (elapsed.as_millis() / deadline.as_millis().max(1)) as u32;
+            if overdue > e.overdue {
+                e.overdue = overdue;
+                warn!(
+                    plugin = %e.name,
+                    "Plugin {} has been {doing} for {}s and may be hung; loading waits until it finishes",
+                    e.name,
+                    elapsed.as_secs()
+                );
+            }
+        }
+    }
+
+    /// Whether plugins which require this one may load: its LoadLibrary returned and Init
+    /// didn't fail. A plugin whose thread exited counts as loaded, since its dll stays loaded
+    pub fn is_loaded(&self, name: &str) -> bool {
+        self.entries
+            .lock()
+            .iter()
+            .find(|e| UniCase::new(e.name.as_str()) == UniCase::new(name))
+            .is_some_and(|e| match &e.status {
+                Some(status) => matches!(status, LoadStatus::Loaded | LoadStatus::Exited),
+                None => matches!(e.phase, Phase::Loaded | Phase::Init),
+            })
+    }
+
+    /// The plugin threads of a wave have all ended, so any plugin which never finished
+    /// had its thread exit out from under it. Returns their names
+    pub fn abandon_unfinished(&self) -> Vec<String> {
+        let mut abandoned = Vec::new();
+        for e in self.entries.lock().iter_mut() {
+            if e.phase != Phase::Done {
+                warn!(plugin = %e.name, "Plugin {}'s load thread exited before it finished", e.name);
+                e.phase = Phase::Done;
+                e.status = Some(LoadStatus::Exited);
+                abandoned.push(e.name.clone());
+            }
+        }
+
+        abandoned
+    }
+
+    pub fn summary(&self) -> LoadSummary {
+        let plugins = self
+            .entries
+            .lock()
+            .iter()
+            .map(|e| PluginTiming {
+                name: e.name.clone(),
+                status: e.status.clone().unwrap_or(LoadStatus::Exited),
+                load: e.load,
+                init: e.init,
+            })
+            .collect();
+
+        LoadSummary {
+            plugins,
+            total: self.started.elapsed(),
+        }
+    }
+
+    fn update(&self, name: &str, f: impl FnOnce(&mut Entry)) {
+        let mut entries = self.entries.lock();
+        if let Some(e) = entries
+            .iter_mut()
+            .find(|e| UniCase::new(e.name.as_str()) == UniCase::new(name))
+        {
+            f(e);
+        }
+    }
+}
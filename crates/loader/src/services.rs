@@ -0,0 +1,235 @@
+//! Named, versioned interfaces plugins publish for each other through the api
+//!
+//! A plugin's `Init` blocks every plugin ordered after it, so a lookup from inside `Init` only
+//! waits while a plugin which may still publish the interface is loading
+
+use std::{
+    sync::{Condvar, Mutex, MutexGuard, PoisonError},
+    time::{Duration, Instant},
+};
+
+use tracing::{trace, warn};
+use unicase::UniCase;
+
+use crate::{SERVICES, logging};
+
+#[derive(Default)]
+pub struct Registry {
+    state: Mutex<State>,
+    /// Notified whenever a service is published or a plugin finished loading
+    changed: Condvar,
+}
+
+#[derive(Default)]
+struct State {
+    services: Vec<Service>,
+    /// Plugins whose load started but whose Init hasn't returned yet
+    loading: Vec<String>,
+    /// Loading plugins blocked in [`wait`], once for each call
+    waiting: Vec<String>,
+}
+
+struct Service {
+    name: String,
+    version: u32,
+    interface: usize,
+    /// The module handle of the plugin which published it
+    module: usize,
+    owner: String,
+    /// Plugins which looked it up, and may still hold on to the interface
+    users: Vec<String>,
+}
+
+impl State {
+    /// Look up a service for `user`, if it is known which plugin is asking
+    fn find(&mut self, name: &str, version: u32, user: Option<&str>) -> Option<usize> {
+        let service = self
+            .services
+            .iter_mut()
+            .find(|s| s.name == name && s.version == version)?;
+
+        if let Some(user) = user
+            && UniCase::new(user) != UniCase::new(service.owner.as_str())
+            && !service
+                .users
+                .iter()
+                .any(|u| UniCase::new(u.as_str()) == UniCase::new(user))
+        {
+            service.users.push(user.to_owned());
+        }
+
+        Some(service.interface)
+    }
+
+    fn is_loading(&self, plugin: &str) -> bool {
+        self.loading
+            .iter()
+            .any(|l| UniCase::new(l.as_str()) == UniCase::new(plugin))
+    }
+
+    /// Whether a plugin other than `caller` may still publish something before `caller`'s
+    /// Init returns. Plugins ordered after it can't start until then, and plugins which are
+    /// themselves waiting won't publish anything
+    fn may_publish_before(&self, caller: &str) -> bool {
+        self.loading.iter().any(|l| {
+            UniCase::new(l.as_str()) != UniCase::new(caller)
+                && !self
+                    .waiting
+                    .iter()
+                    .any(|w| UniCase::new(w.as_str()) == UniCase::new(l.as_str()))
+        })
+    }
+}
+
+impl Registry {
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Record that a plugin started loading. Plugins started in parallel are recorded before any of
+/// them runs, so a plugin waiting on a service knows about all of them
+pub fn starting(plugin: &str) {
+    let mut state = SERVICES.state();
+    if !state.is_loading(plugin) {
+        state.loading.push(plugin.to_owned());
+    }
+}
+
+/// Record that a plugin's Init returned, or that it never will
+pub fn finished(plugin: &str) {
+    SERVICES
+        .state()
+        .loading
+        .retain(|l| UniCase::new(l.as_str()) != UniCase::new(plugin));
+
+    SERVICES.changed.notify_all();
+}
+
+/// Publish `interface` as version `version` of `name`. Fails if another plugin already
+/// published that version; a plugin may replace its own
+pub fn publish(module: usize, owner: &str, name: &str, version: u32, interface: usize) -> bool {
+    let mut state = SERVICES.state();
+
+    if let Some(service) = state
+        .services
+        .iter_mut()
+        .find(|s| s.name == name && s.version == version)
+    {
+        if service.module != module {
+            warn!(
+                plugin = %owner,
+                "Plugin {owner} cannot publish service {name} v{version}; {} already did",
+                service.owner
+            );
+            return false;
+        }
+
+        service.interface = interface;
+    } else {
+        state.services.push(Service {
+            name: name.to_owned(),
+            version,
+            interface,
+            module,
+            owner: owner.to_owned(),
+            users: Vec::new(),
+        });
+    }
+
+    trace!(plugin = %owner, service = name, version, "published service");
+
+    drop(state);
+    SERVICES.changed.notify_all();
+
+    true
+}
+
+/// Withdraw every service the plugin with this module published, before it is unloaded
+pub fn remove(module: usize) {
+    let mut state = SERVICES.state();
+
+    for service in state.services.iter().filter(|s| s.module == module) {
+        if !service.users.is_empty() {
+            warn!(
+                plugin = %service.owner,
+                "Withdrawing service {} v{} of plugin {}, which {} looked up. Using the old interface will crash the game; they must look it up again",
+                service.name,
+                service.version,
+                service.owner,
+                service.users.join(", ")
+            );
+        }
+    }
+
+    state.services.retain(|s| s.module != module);
+}
+
+/// The interface published as version `version` of `name`, if any. Only lookups from a
+/// plugin's load are known to be from that plugin
+pub fn get(name: &str, version: u32) -> Option<usize> {
+    let user = logging::attributed();
+    SERVICES.state().find(name, version, user.as_deref())
+}
+
+/// Like [`get`], but wait for it to be published, up to `timeout` or forever if None
+///
+/// When called from `caller`'s Init, this gives up as soon as no plugin which could still
+/// publish it before Init returns is loading, since waiting longer would only deadlock
+pub fn wait(caller: &str, name: &str, version: u32, timeout: Option<Duration>) -> Option<usize> {
+    let deadline = timeout.map(|t| Instant::now() + t);
+
+    let mut state = SERVICES.state();
+    let in_init = state.is_loading(caller);
+
+    if in_init {
+        state.waiting.push(caller.to_owned());
+        // plugins waiting on this one re-check whether anyone may still publish theirs
+        SERVICES.changed.notify_all();
+    }
+
+    let interface = loop {
+        if let Some(interface) = state.find(name, version, Some(caller)) {
+            break Some(interface);
+        }
+
+        if in_init && !state.may_publish_before(caller) {
+            warn!(
+                plugin = %caller,
+                "Plugin {caller} waited for service {name} v{version} in Init, but no plugin loaded before it published it. Add its provider to `after` for this plugin in config.toml, or look it up later"
+            );
+            break None;
+        }
+
+        state = match deadline {
+            Some(deadline) => {
+                let Some(left) = deadline.checked_duration_since(Instant::now()) else {
+                    trace!(plugin = %caller, service = name, version, "timed out waiting for service");
+                    break None;
+                };
+
+                SERVICES
+                    .changed
+                    .wait_timeout(state, left)
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .0
+            }
+
+            None => SERVICES
+                .changed
+                .wait(state)
+                .unwrap_or_else(PoisonError::into_inner),
+        };
+    };
+
+    if in_init
+        && let Some(idx) = state
+            .waiting
+            .iter()
+            .position(|w| UniCase::new(w.as_str()) == UniCase::new(caller))
+    {
+        state.waiting.remove(idx);
+    }
+
+    interface
+}
@@ -0,0 +1,119 @@
+//! Copies of plugin dlls, so the originals are never locked by the game
+//!
+//! Each game process gets its own session folder, `.cache/<pid>/`. Inside it a copy is
+//! stored by its hash, so an unchanged dll is only copied once
+
+use std::{
+    fs, iter,
+    os::windows::ffi::OsStrExt as _,
+    path::{Path, PathBuf},
+    process,
+    sync::LazyLock,
+};
+
+use eyre::{Context as _, OptionExt as _, Result};
+use sayuri::sync::Mutex;
+use shared::plugins::{CACHE_DIR, hash};
+use tracing::trace;
+use windows::{
+    Win32::{
+        Foundation::HMODULE,
+        System::LibraryLoader::{
+            AddDllDirectory, LOAD_LIBRARY_SEARCH_DEFAULT_DIRS, LOAD_LIBRARY_SEARCH_DLL_LOAD_DIR,
+            LoadLibraryExW, RemoveDllDirectory,
+        },
+    },
+    core::PCWSTR,
+};
+
+/// Held while a plugin's dir is added to the dll search path. The added dir applies to every
+/// load in the process, so copies are loaded one at a time, or one plugin's imports could
+/// resolve to another plugin's helper dlls
+static SEARCH_DIR: LazyLock<Mutex<()>> = LazyLock::new(Mutex::default);
+
+/// Copy the `image` read from a plugin dll at `path` to `.cache/<pid>/<hash>/`. The file
+/// name is kept, so other plugins can still find the module by name. Returns the path of the copy
+///
+/// The image is what the checks ran on, so the copy is exactly what was checked even if the
+/// original changed since
+pub fn copy(plugins_dir: &Path, path: &Path, image: &[u8]) -> Result<PathBuf> {
+    let dir = plugins_dir
+        .join(CACHE_DIR)
+        .join(process::id().to_string())
+        .join(&hash(image)[..16]);
+
+    let copy = dir.join(
+        path.file_name()
+            .ok_or_eyre("plugin path has no file name")?,
+    );
+
+    // an unchanged dll was already copied, and may still be loaded from there
+    if !copy.exists() {
+        fs::create_dir_all(&dir).with_context(|| format!("failed to create {}", dir.display()))?;
+        fs::write(&copy, image)
+            .with_context(|| format!("failed to copy plugin to {}", copy.display()))?;
+    }
+
+    trace!(from = %path.display(), to = %copy.display(), "shadow copied plugin");
+
+    Ok(copy)
+}
+
+/// Remove the session folders of earlier game processes. Folders of a game which is still
+/// running can't be removed since their dlls are loaded, so they are left for next time
+pub fn clean_stale(plugins_dir: &Path) {
+    let Ok(sessions) = fs::read_dir(plugins_dir.join(CACHE_DIR)) else {
+        return;
+    };
+
+    let current = process::id().to_string();
+
+    for session in sessions.filter_map(|e| e.ok()) {
+        if session.file_name() == current.as_str() {
+            continue;
+        }
+
+        let path = session.path();
+        match fs::remove_dir_all(&path) {
+            Ok(()) => trace!(path = %path.display(), "removed stale shadow copies"),
+            Err(e) => trace!(path = %path.display(), "failed to remove stale shadow copies: {e}"),
+        }
+    }
+}
+
+/// Load a copy, resolving its imports from the folder of the `original` dll so helper dlls
+/// shipped next to it are still found
+///
+/// Unlike loading the original, the current dir and PATH are not searched; see
+/// [`LoadPath::ShadowCopy`](shared::plugins::imports::LoadPath::ShadowCopy)
+pub fn load(copy: PCWSTR, original: &Path) -> windows::core::Result<HMODULE> {
+    let dir = original
+        .parent()
+        .unwrap_or(original)
+        .as_os_str()
+        .encode_wide()
+        .chain(iter::once(0))
+        .collect::<Vec<_>>();
+
+    let _guard = SEARCH_DIR.lock();
+
+    // SAFETY: dir is a valid null terminated string
+    let cookie = unsafe { AddDllDirectory(PCWSTR::from_raw(dir.as_ptr())) };
+
+    // SAFETY: Standard function, and the caller's string is formatted properly
+    let module = unsafe {
+        LoadLibraryExW(
+            copy,
+            None,
+            LOAD_LIBRARY_SEARCH_DLL_LOAD_DIR | LOAD_LIBRARY_SEARCH_DEFAULT_DIRS,
+        )
+    };
+
+    // imports are resolved by now, so the dir doesn't need to stay in every search
+    if !cookie.is_null() {
+        // SAFETY: the cookie came from AddDllDirectory
+        _ = unsafe { RemoveDllDirectory(cookie) };
+    }
+
+    module
+}
@@ -1,19 +1,42 @@
 use std::{
-    mem,
+    collections::VecDeque,
+    env,
+    ffi::c_void,
+    iter, mem,
+    os::windows::ffi::OsStrExt as _,
+    ptr,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
     thread::{self, JoinHandle},
 };
 
-use windows::{Win32::Foundation::HMODULE, core::Free};
+use sayuri::sync::Mutex;
+use shared::{pipe::commands::PluginMetadata, plugins::Version};
+use tracing::warn;
+use windows::{
+    Win32::{
+        Foundation::HMODULE,
+        Storage::FileSystem::{
+            GetFileVersionInfoSizeW, GetFileVersionInfoW, VS_FIXEDFILEINFO, VerQueryValueW,
+        },
+    },
+    core::{Free, PCWSTR, w},
+};
 
 /// Container for a loaded plugin. Frees itself on drop
-#[derive(Default)]
-pub struct Plugin(pub HMODULE);
+pub struct Plugin {
+    pub name: String,
+    pub metadata: PluginMetadata,
+    pub module: HMODULE,
+}
 unsafe impl Send for Plugin {}
 
 impl Drop for Plugin {
     fn drop(&mut self) {
         unsafe {
-            self.0.free();
+            self.module.free();
         }
     }
 }
@@ -42,3 +65,81 @@ impl Drop for ThreadManager {
         }
     }
 }
+
+pub type Job = Box<dyn FnOnce() + Send>;
+
+/// Run jobs one at a time, in order, on a single worker thread. Returns once all of them ran
+///
+/// A job may end the worker with ExitThread, like a plugin's DllMain or Init can.
+/// The remaining jobs then continue on a new worker
+pub fn run_in_order(jobs: Vec<Job>) {
+    let queue = Arc::new(Mutex::new(VecDeque::from(jobs)));
+
+    loop {
+        let finished = Arc::new(AtomicBool::new(false));
+
+        let mut m = ThreadManager::new();
+        m.spawn({
+            let queue = queue.clone();
+            let finished = finished.clone();
+            move || {
+                loop {
+                    let Some(job) = queue.lock().pop_front() else {
+                        break;
+                    };
+
+                    job();
+                }
+
+                finished.store(true, Ordering::Relaxed);
+            }
+        });
+        drop(m);
+
+        if finished.load(Ordering::Relaxed) {
+            break;
+        }
+
+        warn!(
+            "A plugin exited the loader's thread; starting a new one for the {} plugins left",
+            queue.lock().len()
+        );
+    }
+}
+
+/// The version of the game exe we're running in, from its version resource
+pub fn game_version() -> Option<Version> {
+    let exe = env::current_exe().ok()?;
+    let exe = exe
+        .as_os_str()
+        .encode_wide()
+        .chain(iter::once(0))
+        .collect::<Vec<_>>();
+    let exe = PCWSTR::from_raw(exe.as_ptr());
+
+    // SAFETY: exe is a valid null terminated string
+    let size = unsafe { GetFileVersionInfoSizeW(exe, None) };
+    if size == 0 {
+        return None;
+    }
+
+    let mut data = vec![0u8; size as usize];
+    // SAFETY: data is exactly as large as the size we were told
+    unsafe { GetFileVersionInfoW(exe, None, size, data.as_mut_ptr().cast()).ok()? };
+
+    let mut info = ptr::null_mut::<c_void>();
+    let mut len = 0;
+    // SAFETY: data holds the version info, and "\" is the root block holding VS_FIXEDFILEINFO
+    let found = unsafe { VerQueryValueW(data.as_ptr().cast(), w!("\\"), &mut info, &mut len) };
+    if !found.as_bool() || info.is_null() || (len as usize) < size_of::<VS_FIXEDFILEINFO>() {
+        return None;
+    }
+
+    // SAFETY: checked above that it points to a large enough buffer inside data
+    let info = unsafe { info.cast::<VS_FIXEDFILEINFO>().read_unaligned() };
+
+    let (ms, ls) = (info.dwFileVersionMS, info.dwFileVersionLS);
+    Some(Version::new(
+        [ms >> 16, ms & 0xffff, ls >> 16, ls & 0xffff].map(u64::from),
+    ))
+}
@@ -1,21 +1,175 @@
-use std::path::PathBuf;
-use std::{fs, sync::LazyLock};
+mod edit;
+mod layers;
+mod migrate;
+mod schema;
 
-use eyre::{Report, Result};
+use std::path::{Path, PathBuf};
+use std::{
+    collections::BTreeMap,
+    fmt::{self, Display},
+    fs,
+    sync::LazyLock,
+};
+
+use eyre::{Report, Result, bail, eyre};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use tracing::error;
+use tracing::{error, info, level_filters::LevelFilter};
 use unicase::UniCase;
 
 use crate::paths::get_bg3_plugins_dir;
 
-#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub use edit::{ConfigEditor, default_config, write_atomic};
+pub use layers::{Layer, Origin, Origins, set_cli_overrides};
+pub use migrate::{CONFIG_VERSION, Migration};
+pub use schema::{Problem, Severity, check, schema};
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 #[serde(default)]
 pub struct Config {
+    /// Version of the config schema. Used to upgrade older configs, do not edit
+    pub config_version: u32,
     pub core: Core,
     pub log: Log,
+    /// Named plugin sets, e.g. one for coop and one for solo play.
+    /// Select one with [core]active_profile
+    pub profiles: BTreeMap<String, Profile>,
+    /// Per plugin options. Each key is the plugins filename without extension
+    pub plugins: BTreeMap<String, PluginConfig>,
+    /// Which layer set each value. Only filled in for configs read from disk
+    #[serde(skip)]
+    pub origins: Origins,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+impl Config {
+    /// Check the values of an already deserialized config for problems serde can't catch
+    pub fn validate(&self) -> Result<()> {
+        match self.problems().into_iter().next() {
+            Some((_, e)) => Err(e),
+            None => Ok(()),
+        }
+    }
+
+    /// Every problem [`Config::validate`] would report, with the key path each one is about
+    pub fn problems(&self) -> Vec<(Vec<String>, Report)> {
+        let mut problems = Vec::new();
+
+        if let Err(e) = self.log.validate() {
+            problems.push((vec!["log".into(), "level".into()], e));
+        }
+
+        if let Some(name) = &self.core.active_profile
+            && !self.profiles.contains_key(name)
+        {
+            problems.push((
+                vec!["core".into(), "active_profile".into()],
+                eyre!("[core]active_profile: profile `{name}` does not exist in [profiles]"),
+            ));
+        }
+
+        for (name, profile) in &self.profiles {
+            if let Some(level) = &profile.log_level
+                && let Err(e) = validate_level(&format!("[profiles.{name}]log_level"), level)
+            {
+                problems.push((vec!["profiles".into(), name.clone(), "log_level".into()], e));
+            }
+        }
+
+        for (name, plugin) in &self.plugins {
+            if let Some(level) = &plugin.log_level
+                && level.parse::<LevelFilter>().is_err()
+            {
+                problems.push((
+                    vec!["plugins".into(), name.clone(), "log_level".into()],
+                    eyre!("[plugins.{name}]log_level: `{level}` is not a valid log level"),
+                ));
+            }
+        }
+
+        problems
+    }
+
+    /// The profile selected by [core]active_profile, if any
+    pub fn active_profile(&self) -> Option<(&str, &Profile)> {
+        let name = self.core.active_profile.as_deref()?;
+        self.profiles.get(name).map(|p| (name, p))
+    }
+
+    /// Whether a plugin should be skipped, taking the active profile into account
+    ///
+    /// The active profile's rules take precedence over [core]disabled_plugins
+    pub fn is_plugin_disabled(&self, name: &str) -> bool {
+        let Some((_, profile)) = self.active_profile() else {
+            return self.is_plugin_disabled_globally(name);
+        };
+
+        if contains_plugin(&profile.disabled_plugins, name) {
+            return true;
+        }
+
+        if !profile.enabled_plugins.is_empty() {
+            return !contains_plugin(&profile.enabled_plugins, name);
+        }
+
+        self.is_plugin_disabled_globally(name)
+    }
+
+    /// Disable a plugin for this launch. The active profile's rules take precedence over
+    /// [core], so it is disabled in the active profile if there is one
+    pub fn disable_plugin(&mut self, name: &str) {
+        let profile = self
+            .core
+            .active_profile
+            .as_deref()
+            .and_then(|name| self.profiles.get_mut(name));
+
+        let list = match profile {
+            Some(profile) => &mut profile.disabled_plugins,
+            None => &mut self.core.disabled_plugins,
+        };
+
+        if !contains_plugin(list, name) {
+            list.push(name.to_owned());
+        }
+    }
+
+    /// [core]disabled_plugins is a shorthand for `enabled = false` in [plugins.<name>]
+    fn is_plugin_disabled_globally(&self, name: &str) -> bool {
+        self.core.is_plugin_disabled(name) || self.plugin(name).is_some_and(|p| !p.enabled)
+    }
+
+    /// The [plugins.<name>] table for a plugin, if it has one
+    pub fn plugin(&self, name: &str) -> Option<&PluginConfig> {
+        let name = UniCase::new(name);
+        self.plugins
+            .iter()
+            .find(|(n, _)| UniCase::new(n.as_str()) == name)
+            .map(|(_, p)| p)
+    }
+
+    /// The log level to use, taking the active profile into account
+    pub fn log_level(&self) -> &str {
+        self.active_profile()
+            .and_then(|(_, p)| p.log_level.as_deref())
+            .unwrap_or(&self.log.level)
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            config_version: CONFIG_VERSION,
+            core: Core::default(),
+            log: Log::default(),
+            profiles: BTreeMap::new(),
+            plugins: BTreeMap::new(),
+            origins: Origins::default(),
+        }
+    }
+}
+
+/// Core loader options
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 #[serde(default)]
 pub struct Core {
     /// Whether plugins are globally enabled or not
@@ -26,11 +180,31 @@ pub struct Core {
     /// Which plugins to disable.
     /// Each entry is the plugins filename without extension
     /// Except for those in this list, all plugins are enabled by default
-    /// e.g. FooBar.dll should have an entry for "FooBar"
-    #[serde(alias = "disabled")]
+    /// e.g. FooBar.dll or FooBar/FooBar.dll should have an entry for "FooBar"
     pub disabled_plugins: Vec<String>,
     /// Whether to show cli window
     pub cli: bool,
+    /// Warn about any plugin still loading or running Init after this many milliseconds.
+    /// 0 disables the warning
+    pub init_deadline_ms: u64,
+    /// How plugins are started.
+    /// Override it for single plugins with load_mode in [plugins.<name>]
+    pub load_mode: LoadMode,
+    /// Whether plugins must match the hashes approved in plugins.lock.
+    /// Approve plugins with `yabg3nml plugins approve`.
+    /// When on, plugins are loaded from copies like with shadow_copy, so the dll that
+    /// loads is the one which was checked
+    pub trust: TrustMode,
+    /// Load plugins from copies in Plugins/.cache, so the originals can be updated or
+    /// deleted while the game runs.
+    /// Copies from earlier sessions are removed the next time the game starts
+    pub shadow_copy: bool,
+    /// Developer mode: reload a plugin whenever its dll changes. Implies shadow_copy.
+    /// Only plugins which export Deinit or Shutdown, to clean up before they are unloaded,
+    /// are reloaded, unless reload_without_deinit is set for them in [plugins.<name>]
+    pub hot_reload: bool,
+    /// Which profile from [profiles] to use. Leave unset to not use a profile
+    pub active_profile: Option<String>,
 }
 
 impl Default for Core {
@@ -41,28 +215,211 @@ impl Default for Core {
             install_root: r"C:\Program Files (x86)\Steam\steamapps\common\Baldurs Gate 3".into(),
             disabled_plugins: Vec::new(),
             cli: false,
+            init_deadline_ms: 10_000,
+            load_mode: LoadMode::Parallel,
+            trust: TrustMode::Off,
+            shadow_copy: false,
+            hot_reload: false,
+            active_profile: None,
         }
     }
 }
 
 impl Core {
     pub fn is_plugin_disabled(&self, name: &str) -> bool {
-        let name = UniCase::new(name);
-        self.disabled_plugins
-            .iter()
-            .any(|p| UniCase::new(p) == name)
+        contains_plugin(&self.disabled_plugins, name)
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// A named set of plugin rules, e.g. one for coop and one for solo play
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(default)]
+pub struct Profile {
+    /// If not empty, only these plugins are loaded.
+    /// Each entry is the plugins filename without extension
+    pub enabled_plugins: Vec<String>,
+    /// Which plugins to disable while this profile is active.
+    /// Each entry is the plugins filename without extension
+    pub disabled_plugins: Vec<String>,
+    /// Overrides [log]level while this profile is active
+    pub log_level: Option<String>,
+}
+
+/// Options for a single plugin
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(default)]
+pub struct PluginConfig {
+    /// Whether this plugin is loaded
+    pub enabled: bool,
+    /// Plugins with a lower load order are started first. Plugins with the same
+    /// load order are started in alphabetical order. Dependencies take precedence over this
+    pub load_order: i32,
+    /// How long to wait before loading this plugin, in milliseconds
+    pub delay_ms: u64,
+    /// Only load this plugin into this game exe; "vulkan" or "dx11"
+    pub only_for_exe: Option<GameExe>,
+    /// Start this plugin only after these plugins' Init has returned, if they are present.
+    /// Each entry is the plugins filename without extension, or the id from its plugin.toml
+    pub after: Vec<String>,
+    /// Start these plugins only after this plugin's Init has returned, if they are present
+    pub before: Vec<String>,
+    /// Like `after`, but this plugin is not loaded at all if any of these are missing or fail
+    pub requires: Vec<String>,
+    /// Overrides [core]load_mode for this plugin
+    pub load_mode: Option<LoadMode>,
+    /// Level of this plugin's own log, logs/plugins/<name>.log, e.g. "debug".
+    /// Defaults to the level of the main log
+    pub log_level: Option<String>,
+    /// With [core]hot_reload, reload this plugin even though it exports no Deinit or Shutdown.
+    /// Anything it left running, like hooks or threads, will likely crash the game once it
+    /// is unloaded
+    pub reload_without_deinit: bool,
+    /// Free-form settings for the plugin itself
+    #[schemars(with = "serde_json::Map<String, serde_json::Value>")]
+    pub settings: toml::Table,
+}
+
+impl Default for PluginConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            load_order: 0,
+            delay_ms: 0,
+            only_for_exe: None,
+            after: Vec::new(),
+            before: Vec::new(),
+            requires: Vec::new(),
+            load_mode: None,
+            log_level: None,
+            reload_without_deinit: false,
+            settings: toml::Table::new(),
+        }
+    }
+}
+
+/// The game executables
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum GameExe {
+    /// bg3.exe
+    Vulkan,
+    /// bg3_dx11.exe
+    Dx11,
+}
+
+impl GameExe {
+    /// Which game exe a path points to, going by its filename
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let name = UniCase::new(path.file_name()?.to_str()?);
+
+        if name == UniCase::new("bg3.exe") {
+            Some(Self::Vulkan)
+        } else if name == UniCase::new("bg3_dx11.exe") {
+            Some(Self::Dx11)
+        } else {
+            None
+        }
+    }
+}
+
+impl Display for GameExe {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let exe = match self {
+            Self::Vulkan => "vulkan",
+            Self::Dx11 => "dx11",
+        };
+
+        write!(f, "{exe}")
+    }
+}
+
+/// How plugins are started
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum LoadMode {
+    /// Every plugin gets its own thread, so plugins start at the same time
+    #[default]
+    Parallel,
+    /// Plugins start one at a time in load order, each after the last one's Init returned
+    Sequential,
+}
+
+impl Display for LoadMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mode = match self {
+            Self::Parallel => "parallel",
+            Self::Sequential => "sequential",
+        };
+
+        write!(f, "{mode}")
+    }
+}
+
+/// How plugins are checked against the hashes in plugins.lock
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum TrustMode {
+    /// Plugins are not checked
+    #[default]
+    Off,
+    /// Unapproved or changed plugins are loaded with a warning
+    Warn,
+    /// Only approved, unchanged plugins are loaded
+    Enforce,
+}
+
+impl Display for TrustMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mode = match self {
+            Self::Off => "off",
+            Self::Warn => "warn",
+            Self::Enforce => "enforce",
+        };
+
+        write!(f, "{mode}")
+    }
+}
+
+/// Case insensitive check whether a plugin name is in the list
+fn contains_plugin(list: &[String], name: &str) -> bool {
+    let name = UniCase::new(name);
+    list.iter().any(|p| UniCase::new(p) == name)
+}
+
+/// Logging options. Each plugin's logs also go to logs/plugins/<name>.log;
+/// set their level with log_level in [plugins.<name>]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 #[serde(default)]
 pub struct Log {
-    /// configure logger level; also settlable through env var YABG3NML_LOG
+    /// Logger level, e.g. "info" or "debug"; also settable through env var YABG3NML_LOG
     pub level: String,
-    /// whether to display log targets
+    /// Whether to display log targets
     pub target: bool,
 }
 
+impl Log {
+    fn validate(&self) -> Result<()> {
+        validate_level("[log]level", &self.level)
+    }
+}
+
+fn validate_level(key: &str, level: &str) -> Result<()> {
+    // a directive is either a bare target, or `<target>=<level>`. Bare targets can't be
+    // checked, but the level of the latter can
+    for directive in level.split(',').map(str::trim) {
+        let level = directive
+            .rsplit_once('=')
+            .map(|(_, l)| l)
+            .unwrap_or(directive);
+
+        if directive.contains('=') && level.parse::<LevelFilter>().is_err() {
+            bail!("{key}: `{directive}` does not have a valid log level");
+        }
+    }
+
+    Ok(())
+}
+
 impl Default for Log {
     fn default() -> Self {
         Self {
@@ -75,33 +432,43 @@ impl Default for Log {
 pub enum ConfigState {
     Exists(Config),
     New(Config),
+    /// An older config was upgraded to the current version
+    Migrated {
+        config: Config,
+        migration: Migration,
+        /// Where the original config was backed up to
+        backup: PathBuf,
+    },
 }
 
 impl ConfigState {
     pub fn get(&self) -> &Config {
         match self {
-            Self::Exists(config) | Self::New(config) => config,
+            Self::Exists(config) | Self::New(config) | Self::Migrated { config, .. } => config,
         }
     }
 }
 
+/// Path to the config file inside the plugins dir
+pub fn config_path() -> Result<PathBuf> {
+    Ok(get_bg3_plugins_dir()?.join("config.toml"))
+}
+
 pub fn get_config() -> Result<&'static ConfigState> {
     static CONFIG: LazyLock<Result<ConfigState>> = LazyLock::new(|| {
-        let path = get_bg3_plugins_dir()?.join("config.toml");
+        let path = config_path()?;
 
         let mut new = false;
         if !path.exists() {
-            let json = toml::to_string_pretty(&Config::default())?;
-
-            if let Err(e) = fs::write(&path, json) {
+            if let Err(e) = write_atomic(&path, &default_config()?) {
                 error!("failed to save config: {e}");
-                return Err(e.into());
+                return Err(e);
             }
 
             new = true;
         }
 
-        let config = match fs::read_to_string(path) {
+        let config = match fs::read_to_string(&path) {
             Ok(v) => v,
             Err(e) => {
                 error!("failed to read config: {e}");
@@ -109,23 +476,137 @@ pub fn get_config() -> Result<&'static ConfigState> {
             }
         };
 
-        match toml::from_str::<Config>(&config) {
-            Ok(v) => {
-                let state = if new {
-                    ConfigState::New(v)
-                } else {
-                    ConfigState::Exists(v)
-                };
+        let Parsed {
+            config,
+            document,
+            migration,
+        } = parse_config(&config)?;
+
+        let state = if let Some(migration) = migration {
+            let backup = backup_path(&path, migration.from);
 
-                Ok(state)
+            if let Err(e) = fs::copy(&path, &backup) {
+                error!(backup = %backup.display(), "failed to back up config: {e}");
+                return Err(e.into());
             }
 
-            Err(e) => {
-                error!("failed to deserialize config: {e}");
-                Err(e.into())
+            if let Err(e) = write_atomic(&path, &document.to_string()) {
+                error!("failed to save migrated config: {e}");
+                return Err(e);
             }
-        }
+
+            info!(
+                from = migration.from,
+                to = migration.to,
+                backup = %backup.display(),
+                "migrated config"
+            );
+
+            ConfigState::Migrated {
+                config,
+                migration,
+                backup,
+            }
+        } else if new {
+            ConfigState::New(config)
+        } else {
+            ConfigState::Exists(config)
+        };
+
+        Ok(state)
     });
 
     CONFIG.as_ref().map_err(|e| Report::new(&**e))
 }
+
+/// Read a fresh copy of the config from disk, bypassing the cache in [`get_config`]
+///
+/// Older configs are migrated in memory only; nothing is written back to disk.
+/// Env var and cli overrides are applied on top, same as in [`get_config`]
+pub fn read_config(path: &Path) -> Result<Config> {
+    let data = fs::read_to_string(path)?;
+    let Parsed { config, .. } = parse_config(&data)?;
+    Ok(config)
+}
+
+struct Parsed {
+    config: Config,
+    /// The migrated document the config was deserialized from, comments included
+    document: toml_edit::DocumentMut,
+    migration: Option<Migration>,
+}
+
+fn parse_config(data: &str) -> Result<Parsed> {
+    let mut document = match data.parse::<toml_edit::DocumentMut>() {
+        Ok(v) => v,
+        Err(e) => {
+            error!("failed to deserialize config: {e}");
+            return Err(e.into());
+        }
+    };
+
+    let migration = match migrate::migrate(document.as_table_mut()) {
+        Ok(v) => v,
+        Err(e) => {
+            error!("failed to migrate config: {e}");
+            return Err(e);
+        }
+    };
+
+    let file = match toml::from_str::<toml::Table>(&document.to_string()) {
+        Ok(v) => v,
+        Err(e) => {
+            error!("failed to deserialize config: {e}");
+            return Err(e.into());
+        }
+    };
+
+    let (table, origins) = match layers::resolve(file) {
+        Ok(v) => v,
+        Err(e) => {
+            error!("failed to apply config overrides: {e}");
+            return Err(e);
+        }
+    };
+
+    let mut config = deserialize(table)?;
+    config.origins = origins;
+
+    Ok(Parsed {
+        config,
+        document,
+        migration,
+    })
+}
+
+/// Deserialize and validate a config from its final table
+fn deserialize(table: toml::Table) -> Result<Config> {
+    let config = match toml::Value::Table(table).try_into::<Config>() {
+        Ok(v) => v,
+        Err(e) => {
+            error!("failed to deserialize config: {e}");
+            return Err(e.into());
+        }
+    };
+
+    if let Err(e) = config.validate() {
+        error!("invalid config: {e}");
+        return Err(e);
+    }
+
+    Ok(config)
+}
+
+/// Find a free backup filename for a config of `version`, e.g. `config.toml.v0.bak`
+fn backup_path(path: &Path, version: u32) -> PathBuf {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+
+    let mut backup = path.with_file_name(format!("{name}.v{version}.bak"));
+    let mut n = 1;
+    while backup.exists() {
+        backup = path.with_file_name(format!("{name}.v{version}.{n}.bak"));
+        n += 1;
+    }
+
+    backup
+}
@@ -0,0 +1,418 @@
+use std::{
+    fs::{self, File},
+    io::Write as _,
+    path::{Path, PathBuf},
+};
+
+use eyre::{Context as _, Result, bail};
+use toml_edit::{Array, DocumentMut, Item, Table, TableLike, Value, value};
+use tracing::info;
+use unicase::UniCase;
+
+use super::{
+    Config, backup_path, deserialize,
+    migrate::migrate,
+    schema::{describe, schema},
+    validate_level,
+};
+
+/// Examples for tables, since keys which are unset by default have no line to document.
+/// Every other comment in the default config comes from the doc comments on [`Config`]
+static EXAMPLES: &[(&str, &str)] = &[
+    (
+        "core",
+        "active_profile = \"coop\"  # which profile from [profiles] to use; unset by default",
+    ),
+    (
+        "profiles",
+        "[profiles.coop]
+enabled_plugins = []   # if not empty, only these plugins are loaded
+disabled_plugins = []  # plugins to disable while this profile is active
+log_level = \"debug\"    # overrides [log]level while this profile is active",
+    ),
+    (
+        "plugins",
+        "[plugins.FooBar]
+enabled = true          # whether this plugin is loaded
+load_order = 0          # lower starts first; ties start in alphabetical order
+delay_ms = 0            # how long to wait before loading this plugin
+only_for_exe = \"dx11\"   # only load into \"vulkan\" (bg3.exe) or \"dx11\" (bg3_dx11.exe)
+after = []              # start after these plugins' Init returned, if present
+before = []             # start before these plugins, if present
+requires = []           # like after, but don't load at all if any are missing
+load_mode = \"parallel\"  # overrides [core]load_mode for this plugin
+log_level = \"debug\"     # level of its own log, logs/plugins/FooBar.log
+settings = {}           # free-form settings for the plugin itself",
+    ),
+];
+
+/// The default config, with every key documented
+pub fn default_config() -> Result<String> {
+    let data = toml::to_string_pretty(&Config::default())?;
+    let mut doc = data.parse::<DocumentMut>()?;
+    let schema = schema();
+
+    for (mut key, item) in doc.as_table_mut().iter_mut() {
+        let name = key.get().to_owned();
+
+        let mut docs = describe(&schema, &[&name]).unwrap_or_default();
+        if let Some((_, example)) = EXAMPLES.iter().find(|(table, _)| *table == name) {
+            docs.push_str("\n\n");
+            docs.push_str(example);
+        }
+
+        let Some(table) = item.as_table_mut() else {
+            key.leaf_decor_mut().set_prefix(comment(&docs));
+            continue;
+        };
+
+        // header comments need a blank line to separate them from the previous table
+        table
+            .decor_mut()
+            .set_prefix(format!("\n{}", comment(&docs)));
+        // show empty tables too, so the header comment has something to attach to
+        table.set_implicit(false);
+
+        for (mut key, _) in table.iter_mut() {
+            if let Some(docs) = describe(&schema, &[&name, key.get()]) {
+                key.leaf_decor_mut().set_prefix(comment(&docs));
+            }
+        }
+    }
+
+    Ok(doc.to_string())
+}
+
+/// Turn docs into toml comment lines
+fn comment(docs: &str) -> String {
+    docs.lines()
+        .map(|l| {
+            if l.is_empty() {
+                "#\n".to_owned()
+            } else {
+                format!("# {l}\n")
+            }
+        })
+        .collect()
+}
+
+/// Edits config.toml in place, keeping the user's comments and formatting intact
+pub struct ConfigEditor {
+    path: PathBuf,
+    doc: DocumentMut,
+    /// Version the file was migrated from when opened. It is backed up on the first save
+    migrated_from: Option<u32>,
+}
+
+impl ConfigEditor {
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+
+        let data = fs::read_to_string(&path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        let mut doc = data.parse::<DocumentMut>()?;
+
+        // edits are written against the current schema, so older files must be upgraded first.
+        // That only happens in memory, so opening the editor never touches the file
+        let migrated_from = migrate(doc.as_table_mut())?.map(|migration| migration.from);
+
+        Ok(Self {
+            path,
+            doc,
+            migrated_from,
+        })
+    }
+
+    /// Add a plugin to disabled_plugins in [profiles.<profile>], or in [core] if None. Pass the
+    /// active profile, since its rules take precedence over [core]
+    ///
+    /// Returns whether anything changed
+    pub fn disable_plugin(&mut self, profile: Option<&str>, name: &str) -> Result<bool> {
+        let list = match profile {
+            Some(profile) => self.profile_array(profile, "disabled_plugins")?,
+            None => self.array("core", "disabled_plugins")?,
+        };
+
+        if list.iter().any(|v| {
+            v.as_str()
+                .is_some_and(|v| UniCase::new(v) == UniCase::new(name))
+        }) {
+            return Ok(false);
+        }
+
+        push(list, name);
+
+        Ok(true)
+    }
+
+    /// Remove a plugin from [core]disabled_plugins, and clear `enabled = false` in [plugins.<name>]
+    ///
+    /// Profiles are left alone. Returns whether anything changed
+    pub fn enable_plugin(&mut self, name: &str) -> Result<bool> {
+        let mut changed = false;
+
+        if let Some(list) = self
+            .doc
+            .get_mut("core")
+            .and_then(|c| c.get_mut("disabled_plugins"))
+            .and_then(Item::as_array_mut)
+        {
+            let before = list.len();
+            list.retain(|v| {
+                v.as_str()
+                    .is_none_or(|v| UniCase::new(v) != UniCase::new(name))
+            });
+            changed |= list.len() != before;
+        }
+
+        let plugin = self
+            .doc
+            .get_mut("plugins")
+            .and_then(Item::as_table_like_mut)
+            .and_then(|plugins| {
+                plugins
+                    .iter_mut()
+                    .find(|(k, _)| UniCase::new(k.get()) == UniCase::new(name))
+                    .map(|(_, v)| v)
+            })
+            .and_then(Item::as_table_like_mut);
+
+        if let Some(plugin) = plugin
+            && plugin.get("enabled").and_then(Item::as_bool) == Some(false)
+        {
+            set(plugin, "enabled", true.into());
+            changed = true;
+        }
+
+        Ok(changed)
+    }
+
+    pub fn set_install_root(&mut self, path: &Path) -> Result<()> {
+        let root = path.to_string_lossy().into_owned();
+        set(self.table("core")?, "install_root", root.into());
+
+        Ok(())
+    }
+
+    pub fn set_log_level(&mut self, level: &str) -> Result<()> {
+        validate_level("[log]level", level)?;
+
+        set(self.table("log")?, "level", level.into());
+
+        Ok(())
+    }
+
+    /// Validate the edited config, then atomically replace the file on disk
+    ///
+    /// If the file was migrated when opened, the original is backed up first, like a migration
+    /// on startup does
+    pub fn save(&mut self) -> Result<()> {
+        let data = self.doc.to_string();
+
+        // never write out a config the loader would refuse to load. Overrides are
+        // left out, since they don't end up in the file
+        deserialize(toml::from_str(&data)?)?;
+
+        if let Some(from) = self.migrated_from.take() {
+            let backup = backup_path(&self.path, from);
+            fs::copy(&self.path, &backup)
+                .with_context(|| format!("failed to back up config to {}", backup.display()))?;
+
+            info!(from, backup = %backup.display(), "migrated config");
+        }
+
+        write_atomic(&self.path, &data)
+    }
+
+    /// Get or create a top level table
+    fn table(&mut self, name: &str) -> Result<&mut Table> {
+        let item = self.doc.entry(name).or_insert_with(toml_edit::table);
+
+        match item.as_table_mut() {
+            Some(table) => Ok(table),
+            None => bail!("[{name}] is not a table"),
+        }
+    }
+
+    /// Get or create an array in a top level table
+    fn array(&mut self, table: &str, key: &str) -> Result<&mut Array> {
+        let item = self
+            .table(table)?
+            .entry(key)
+            .or_insert_with(|| value(Array::new()));
+
+        match item.as_array_mut() {
+            Some(array) => Ok(array),
+            None => bail!("[{table}]{key} is not an array"),
+        }
+    }
+
+    /// Get or create an array in a [profiles.<name>] table
+    fn profile_array(&mut self, profile: &str, key: &str) -> Result<&mut Array> {
+        let profiles = self.table("profiles")?;
+        // only the profile's own header is written if [profiles] is new
+        if profiles.is_empty() {
+            profiles.set_implicit(true);
+        }
+
+        let Some(table) = profiles
+            .entry(profile)
+            .or_insert_with(toml_edit::table)
+            .as_table_like_mut()
+        else {
+            bail!("[profiles.{profile}] is not a table");
+        };
+
+        match table
+            .entry(key)
+            .or_insert_with(|| value(Array::new()))
+            .as_array_mut()
+        {
+            Some(array) => Ok(array),
+            None => bail!("[profiles.{profile}]{key} is not an array"),
+        }
+    }
+}
+
+/// Set a value, keeping the comments around the old one
+fn set(table: &mut dyn TableLike, key: &str, mut new: Value) {
+    if let Some(old) = table.get(key).and_then(Item::as_value) {
+        *new.decor_mut() = old.decor().clone();
+    }
+
+    table.insert(key, Item::Value(new));
+}
+
+/// Append to an array, matching the layout of multi-line arrays
+fn push(array: &mut Array, item: &str) {
+    let prefix = array
+        .iter()
+        .last()
+        .and_then(|v| v.decor().prefix())
+        .and_then(|p| p.as_str())
+        // the first item of a single line array has no prefix, unlike the ones after it
+        .filter(|p| p.contains('\n'))
+        .map(ToOwned::to_owned);
+
+    match prefix {
+        Some(prefix) => array.push_formatted(Value::from(item).decorated(prefix, "")),
+        None => array.push(item),
+    }
+}
+
+/// Write a file such that readers only ever see the old or the new contents
+pub fn write_atomic(path: &Path, contents: &str) -> Result<()> {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let tmp = path.with_file_name(format!("{name}.tmp"));
+
+    let mut file = File::create(&tmp)?;
+    file.write_all(contents.as_bytes())?;
+    file.sync_all()?;
+    drop(file);
+
+    if let Err(e) = fs::rename(&tmp, path) {
+        _ = fs::remove_file(&tmp);
+        return Err(e.into());
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, process};
+
+    use super::*;
+
+    #[test]
+    fn default_config_documents_every_key() {
+        let data = default_config().unwrap();
+        let doc = data.parse::<DocumentMut>().unwrap();
+
+        let comment = |prefix: Option<&toml_edit::RawString>| {
+            prefix
+                .and_then(|p| p.as_str())
+                .is_some_and(|p| p.trim_start().starts_with('#'))
+        };
+
+        for (key, item) in doc.as_table().iter() {
+            match item.as_table() {
+                Some(table) => {
+                    assert!(comment(table.decor().prefix()), "[{key}]");
+
+                    for (name, _) in table.iter() {
+                        let prefix = table.key(name).unwrap().leaf_decor().prefix();
+                        assert!(comment(prefix), "[{key}]{name}");
+                    }
+                }
+
+                None => {
+                    let prefix = doc.as_table().key(key).unwrap().leaf_decor().prefix();
+                    assert!(comment(prefix), "{key}");
+                }
+            }
+        }
+
+        assert!(data.contains("# \"enforce\" - Only approved, unchanged plugins are loaded\n"));
+        assert!(data.contains("# [plugins.FooBar]\n"));
+
+        // and it is still the default config
+        let config = toml::from_str::<Config>(&data).unwrap();
+        assert_eq!(
+            config.core.init_deadline_ms,
+            Config::default().core.init_deadline_ms
+        );
+    }
+
+    #[test]
+    fn save_backs_up_migrated_config() {
+        let dir = env::temp_dir().join(format!("yabg3nml-edit-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let path = dir.join("config.toml");
+        let original = "[core]\ndisabled = [\"FooBar\"]\n";
+        fs::write(&path, original).unwrap();
+
+        let mut editor = ConfigEditor::open(&path).unwrap();
+        editor.disable_plugin(None, "Baz").unwrap();
+
+        // nothing is written until the edit is saved
+        let untouched = fs::read_to_string(&path);
+        let backed_up = dir.join("config.toml.v0.bak").exists();
+
+        editor.save().unwrap();
+
+        let backup = fs::read_to_string(dir.join("config.toml.v0.bak"));
+        let saved = fs::read_to_string(&path);
+        _ = fs::remove_dir_all(&dir);
+
+        assert_eq!(untouched.unwrap(), original);
+        assert!(!backed_up);
+        assert_eq!(backup.unwrap(), original);
+        let saved = saved.unwrap();
+        assert!(
+            saved.contains("disabled_plugins = [\"FooBar\", \"Baz\"]"),
+            "{saved}"
+        );
+    }
+
+    #[test]
+    fn disable_plugin_in_profile() {
+        let mut editor = ConfigEditor {
+            path: PathBuf::new(),
+            doc: "[core]\nactive_profile = \"coop\"\n\n[profiles.coop]\nenabled_plugins = [\"FooBar\"]\n"
+                .parse()
+                .unwrap(),
+            migrated_from: None,
+        };
+
+        assert!(editor.disable_plugin(Some("coop"), "FooBar").unwrap());
+        assert!(!editor.disable_plugin(Some("coop"), "foobar").unwrap());
+        assert!(editor.disable_plugin(Some("solo"), "Baz").unwrap());
+
+        let config = deserialize(toml::from_str(&editor.doc.to_string()).unwrap()).unwrap();
+        assert!(config.is_plugin_disabled("FooBar"));
+        assert!(config.core.disabled_plugins.is_empty());
+        assert_eq!(config.profiles["solo"].disabled_plugins, ["Baz"]);
+    }
+}
@@ -0,0 +1,302 @@
+use std::{
+    collections::BTreeMap,
+    env,
+    fmt::{self, Display},
+    sync::OnceLock,
+};
+
+use eyre::{Result, bail, eyre};
+use toml::{Table, Value};
+use tracing::{trace, trace_span, warn};
+use unicase::UniCase;
+
+use super::Config;
+
+/// Env vars of the form `YABG3NML_<SECTION>__<KEY>` override config values,
+/// e.g. `YABG3NML_CORE__INSTALL_ROOT`
+const ENV_PREFIX: &str = "YABG3NML_";
+
+/// Env vars from before layering existed, and the key each one sets
+const ENV_ALIASES: &[(&str, &str)] = &[("YABG3NML_LOG", "log.level")];
+
+/// `--set key=value` overrides, applied above every other layer
+static CLI_OVERRIDES: OnceLock<Vec<Override>> = OnceLock::new();
+
+/// Where a config value came from. Later layers override earlier ones
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Layer {
+    Default,
+    File,
+    Env,
+    Cli,
+}
+
+#[derive(Debug, Clone)]
+pub struct Origin {
+    pub layer: Layer,
+    /// The env var or cli flag which set the value
+    pub source: Option<String>,
+}
+
+impl Display for Origin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let layer = match self.layer {
+            Layer::Default => "default",
+            Layer::File => "config.toml",
+            Layer::Env => "env",
+            Layer::Cli => "cli",
+        };
+
+        match &self.source {
+            Some(source) => write!(f, "{layer} ({source})"),
+            None => write!(f, "{layer}"),
+        }
+    }
+}
+
+/// The origin of every value in a config, by dotted key path, e.g. `core.cli`
+#[derive(Debug, Clone, Default)]
+pub struct Origins(BTreeMap<String, Origin>);
+
+impl Origins {
+    pub fn get(&self, key: &str) -> Option<&Origin> {
+        self.0.get(key)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Origin)> {
+        self.0.iter().map(|(k, o)| (k.as_str(), o))
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Override {
+    key: Vec<String>,
+    value: Value,
+    origin: Origin,
+}
+
+/// Set the `--set key=value` overrides for this process. Must be called before the config is first read
+pub fn set_cli_overrides(overrides: &[String]) -> Result<()> {
+    let overrides = overrides
+        .iter()
+        .map(|o| {
+            let Some((key, value)) = o.split_once('=') else {
+                bail!("`{o}` is not in the form key=value");
+            };
+
+            Ok(Override {
+                key: split_key(key.trim(), ".")?,
+                value: parse_value(value.trim()),
+                origin: Origin {
+                    layer: Layer::Cli,
+                    source: Some(format!("--set {o}")),
+                },
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    CLI_OVERRIDES
+        .set(overrides)
+        .map_err(|_| eyre!("cli overrides were already set"))
+}
+
+/// Stack the defaults, the config file, env vars and cli overrides, in that order
+pub(super) fn resolve(file: Table) -> Result<(Table, Origins)> {
+    let span = trace_span!("layers");
+    let _guard = span.enter();
+
+    let mut table = Table::try_from(Config::default())?;
+    let mut origins = Origins::default();
+
+    let default = Origin {
+        layer: Layer::Default,
+        source: None,
+    };
+    record(
+        &mut origins,
+        &mut Vec::new(),
+        &Value::Table(table.clone()),
+        &default,
+    );
+
+    let file_origin = Origin {
+        layer: Layer::File,
+        source: None,
+    };
+    merge(
+        &mut table,
+        &mut origins,
+        &mut Vec::new(),
+        file,
+        &file_origin,
+    );
+
+    for o in env_overrides()?
+        .iter()
+        .chain(CLI_OVERRIDES.get().into_iter().flatten())
+    {
+        trace!(key = o.key.join("."), origin = %o.origin, "applying override");
+        set(&mut table, &mut origins, &o.key, o.value.clone(), &o.origin)?;
+    }
+
+    Ok((table, origins))
+}
+
+fn env_overrides() -> Result<Vec<Override>> {
+    let mut overrides = Vec::new();
+
+    // env::vars panics on a single non-unicode variable, even one which isn't ours
+    for (name, value) in env::vars_os() {
+        let (name, value) = match (name.into_string(), value.into_string()) {
+            (Ok(name), Ok(value)) => (name, value),
+
+            (name, _) => {
+                let name = name.unwrap_or_else(|n| n.to_string_lossy().into_owned());
+                if name.starts_with(ENV_PREFIX) {
+                    warn!(name, "ignoring env var which is not valid unicode");
+                }
+
+                continue;
+            }
+        };
+
+        let key = if let Some((_, key)) = ENV_ALIASES.iter().find(|(alias, _)| *alias == name) {
+            split_key(key, ".")?
+        } else if let Some(key) = name.strip_prefix(ENV_PREFIX)
+            && key.contains("__")
+        {
+            split_key(&key.to_lowercase(), "__")?
+        } else {
+            continue;
+        };
+
+        overrides.push(Override {
+            key,
+            value: parse_value(&value),
+            origin: Origin {
+                layer: Layer::Env,
+                source: Some(name),
+            },
+        });
+    }
+
+    // keep the order stable; env::vars_os order is unspecified
+    overrides.sort_by(|a, b| a.origin.source.cmp(&b.origin.source));
+
+    Ok(overrides)
+}
+
+fn split_key(key: &str, separator: &str) -> Result<Vec<String>> {
+    let key = key
+        .split(separator)
+        .map(ToOwned::to_owned)
+        .collect::<Vec<_>>();
+
+    if key.iter().any(String::is_empty) {
+        bail!("`{}` is not a valid config key", key.join("."));
+    }
+
+    Ok(key)
+}
+
+/// Values are read as toml, so `true`, `5` and `["a", "b"]` work. Anything which
+/// isn't valid toml is taken as a plain string, so paths don't need quoting
+fn parse_value(raw: &str) -> Value {
+    toml::from_str::<Table>(&format!("v = {raw}"))
+        .ok()
+        .and_then(|mut t| t.remove("v"))
+        .unwrap_or_else(|| Value::String(raw.to_owned()))
+}
+
+/// Find an existing key case insensitively, so that e.g. env vars can reach `[plugins.FooBar]`
+fn find_key(table: &Table, key: &str) -> String {
+    table
+        .keys()
+        .find(|k| UniCase::new(k.as_str()) == UniCase::new(key))
+        .cloned()
+        .unwrap_or_else(|| key.to_owned())
+}
+
+fn merge(
+    table: &mut Table,
+    origins: &mut Origins,
+    path: &mut Vec<String>,
+    other: Table,
+    origin: &Origin,
+) {
+    for (key, value) in other {
+        let key = find_key(table, &key);
+        path.push(key.clone());
+
+        match (table.get_mut(&key), value) {
+            (Some(Value::Table(existing)), Value::Table(value)) => {
+                merge(existing, origins, path, value, origin);
+            }
+
+            (_, value) => {
+                record(origins, path, &value, origin);
+                table.insert(key, value);
+            }
+        }
+
+        path.pop();
+    }
+}
+
+fn set(
+    table: &mut Table,
+    origins: &mut Origins,
+    key: &[String],
+    value: Value,
+    origin: &Origin,
+) -> Result<()> {
+    let mut path = Vec::new();
+    let mut table = table;
+
+    let (last, parents) = key.split_last().expect("keys are never empty");
+    for part in parents {
+        let part = find_key(table, part);
+        path.push(part.clone());
+
+        let next = table
+            .entry(part)
+            .or_insert_with(|| Value::Table(Table::new()));
+
+        let Value::Table(next) = next else {
+            bail!(
+                "cannot set `{}`: `{}` is not a table",
+                key.join("."),
+                path.join(".")
+            );
+        };
+
+        table = next;
+    }
+
+    let mut other = Table::new();
+    other.insert(last.clone(), value);
+    merge(table, origins, &mut path, other, origin);
+
+    Ok(())
+}
+
+/// Mark every leaf value under `path` as coming from `origin`
+fn record(origins: &mut Origins, path: &mut Vec<String>, value: &Value, origin: &Origin) {
+    match value {
+        Value::Table(table) => {
+            // a table replaced wholesale may have had leaves which are now gone
+            let prefix = format!("{}.", path.join("."));
+            origins.0.retain(|k, _| !k.starts_with(&prefix));
+
+            for (key, value) in table {
+                path.push(key.clone());
+                record(origins, path, value, origin);
+                path.pop();
+            }
+        }
+
+        _ => {
+            origins.0.insert(path.join("."), origin.clone());
+        }
+    }
+}
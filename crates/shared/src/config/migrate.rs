@@ -0,0 +1,207 @@
+use eyre::{Result, bail};
+use toml_edit::{Item, Key, Table, value};
+use tracing::{debug, trace_span};
+
+/// The current config schema version. Bump this and add a step to [`MIGRATIONS`]
+/// whenever a change to `Config` would otherwise break older config files
+pub const CONFIG_VERSION: u32 = 1;
+
+/// A single upgrade step. Mutates the document's root table in place and returns a
+/// human readable description of every change it made. Comments and formatting
+/// should be kept wherever possible
+type Step = fn(&mut Table) -> Vec<String>;
+
+/// Index `n` upgrades a config from version `n` to `n + 1`
+static MIGRATIONS: &[Step] = &[v0_to_v1];
+
+const _: () = assert!(MIGRATIONS.len() == CONFIG_VERSION as usize);
+
+/// Describes an upgrade which was applied to a config file
+#[derive(Debug, Clone)]
+pub struct Migration {
+    pub from: u32,
+    pub to: u32,
+    pub changes: Vec<String>,
+}
+
+/// Upgrade the config table to [`CONFIG_VERSION`] one version at a time
+///
+/// Returns `None` if the config is already up to date
+pub fn migrate(table: &mut Table) -> Result<Option<Migration>> {
+    let span = trace_span!("migrate");
+    let _guard = span.enter();
+
+    let from = version(table)?;
+
+    if from == CONFIG_VERSION {
+        return Ok(None);
+    }
+
+    let mut changes = Vec::new();
+    for (version, step) in MIGRATIONS.iter().enumerate().skip(from as usize) {
+        let step_changes = step(table);
+        debug!(from = version, to = version + 1, changes = ?step_changes, "applied config migration");
+        changes.extend(step_changes);
+    }
+
+    table.insert("config_version", value(i64::from(CONFIG_VERSION)));
+    changes.push(format!("set config_version to {CONFIG_VERSION}"));
+
+    Ok(Some(Migration {
+        from,
+        to: CONFIG_VERSION,
+        changes,
+    }))
+}
+
+/// The schema version of a config, checking that this version of the tool can read it
+pub fn version(table: &Table) -> Result<u32> {
+    // configs from before versioning was introduced have no version key
+    let version = match table.get("config_version") {
+        None => 0,
+        Some(v) => match v.as_integer() {
+            Some(v) => match u32::try_from(v) {
+                Ok(v) => v,
+                Err(_) => bail!("config_version {v} is not a valid version"),
+            },
+            None => bail!("config_version must be an integer, found {}", v.type_name()),
+        },
+    };
+
+    if version > CONFIG_VERSION {
+        bail!(
+            "config_version {version} is newer than this version of the tool supports ({CONFIG_VERSION}). Please update the tool, or restore an older config"
+        );
+    }
+
+    Ok(version)
+}
+
+/// Returns the `[section]` table, if it exists and is a table
+fn section<'a>(table: &'a mut Table, name: &str) -> Option<&'a mut Table> {
+    table.get_mut(name).and_then(Item::as_table_mut)
+}
+
+/// Rename a key in place, keeping its position and any comments attached to it
+fn rename(table: &mut Table, from: &str, to: &str) {
+    let keys = table.iter().map(|(k, _)| k.to_owned()).collect::<Vec<_>>();
+
+    // a table keeps insertion order, so everything is reinserted in the old order
+    let entries = keys
+        .iter()
+        .filter_map(|k| table.remove_entry(k))
+        .collect::<Vec<_>>();
+
+    for (key, item) in entries {
+        let key = if key.get() == from {
+            Key::new(to).with_leaf_decor(key.leaf_decor().clone())
+        } else {
+            key
+        };
+
+        table.insert_formatted(&key, item);
+    }
+}
+
+/// v0 -> v1
+///
+/// `[core]disabled` was renamed to `[core]disabled_plugins`
+fn v0_to_v1(table: &mut Table) -> Vec<String> {
+    let mut changes = Vec::new();
+
+    let Some(core) = section(table, "core") else {
+        return changes;
+    };
+
+    if !core.contains_key("disabled") {
+        return changes;
+    }
+
+    if core.contains_key("disabled_plugins") {
+        core.remove("disabled");
+        changes.push(
+            "removed [core]disabled because [core]disabled_plugins already exists".to_owned(),
+        );
+    } else {
+        rename(core, "disabled", "disabled_plugins");
+        changes.push("renamed [core]disabled to [core]disabled_plugins".to_owned());
+    }
+
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use toml_edit::DocumentMut;
+
+    use super::*;
+
+    const V0: &str = r#"# my config
+[core]
+enabled = true
+# plugins I don't want right now
+disabled = ["FooBar", "Baz"] # keep Baz off, it crashes
+cli = false
+
+[log]
+level = "debug" # noisy
+"#;
+
+    #[test]
+    fn v0_keeps_comments_and_values() {
+        let mut doc = V0.parse::<DocumentMut>().unwrap();
+
+        let migration = migrate(doc.as_table_mut()).unwrap().unwrap();
+        assert_eq!((migration.from, migration.to), (0, CONFIG_VERSION));
+
+        let migrated = doc.to_string();
+        assert_eq!(
+            migrated,
+            r#"config_version = 1
+# my config
+[core]
+enabled = true
+# plugins I don't want right now
+disabled_plugins = ["FooBar", "Baz"] # keep Baz off, it crashes
+cli = false
+
+[log]
+level = "debug" # noisy
+"#
+        );
+
+        // the `disabled` alias is gone, so the value must have moved
+        let core = &doc["core"];
+        assert!(core.get("disabled").is_none());
+        let disabled = core["disabled_plugins"].as_array().unwrap();
+        let disabled = disabled
+            .iter()
+            .filter_map(|v| v.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(disabled, ["FooBar", "Baz"]);
+
+        // and it is up to date now
+        assert!(migrate(doc.as_table_mut()).unwrap().is_none());
+    }
+
+    #[test]
+    fn v0_keeps_existing_disabled_plugins() {
+        let mut doc = "[core]\ndisabled = [\"Old\"]\ndisabled_plugins = [\"New\"]\n"
+            .parse::<DocumentMut>()
+            .unwrap();
+
+        migrate(doc.as_table_mut()).unwrap();
+
+        assert!(doc["core"].get("disabled").is_none());
+        assert_eq!(doc["core"]["disabled_plugins"][0].as_str(), Some("New"));
+    }
+
+    #[test]
+    fn rejects_newer_versions() {
+        let mut doc = format!("config_version = {}\n", CONFIG_VERSION + 1)
+            .parse::<DocumentMut>()
+            .unwrap();
+
+        assert!(migrate(doc.as_table_mut()).is_err());
+    }
+}
@@ -0,0 +1,541 @@
+use std::{
+    fmt::{self, Display},
+    ops::Range,
+};
+
+use schemars::schema_for;
+use serde_json::{Map, Value as Json};
+use toml_edit::{Document, Item, TableLike, Value};
+
+use super::{CONFIG_VERSION, Config, migrate};
+
+/// JSON Schema for config.toml, generated from [`Config`]
+pub fn schema() -> Json {
+    serde_json::to_value(schema_for!(Config)).expect("schema is always valid json")
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// The config will load, but probably doesn't do what was intended
+    Warning,
+    /// The config will fail to load
+    Error,
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let severity = match self {
+            Self::Warning => "warning",
+            Self::Error => "error",
+        };
+
+        write!(f, "{severity}")
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Problem {
+    pub severity: Severity,
+    /// 1 based
+    pub line: usize,
+    /// 1 based, in chars
+    pub column: usize,
+    pub message: String,
+}
+
+impl Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}: {}: {}",
+            self.line, self.column, self.severity, self.message
+        )
+    }
+}
+
+/// Check a config file against the schema and [`Config::validate`], reporting every problem
+/// instead of only the first
+///
+/// Env var and cli overrides are not applied; this only checks the file itself
+pub fn check(data: &str) -> Vec<Problem> {
+    let mut checker = Checker {
+        data,
+        problems: Vec::new(),
+    };
+
+    let doc = match Document::parse(data) {
+        Ok(doc) => doc,
+        Err(e) => {
+            let span = e.span().unwrap_or_default();
+            checker.push(Severity::Error, span, e.message().to_owned());
+            return checker.problems;
+        }
+    };
+
+    let root = doc.as_table();
+
+    match migrate::version(root) {
+        Ok(version) if version < CONFIG_VERSION => {
+            let span = root
+                .get_key_value("config_version")
+                .and_then(|(k, _)| k.span())
+                .unwrap_or_default();
+
+            checker.push(
+                Severity::Warning,
+                span,
+                format!(
+                    "config_version {version} is outdated and will be upgraded to {CONFIG_VERSION} on next start. Keys are checked against version {CONFIG_VERSION}"
+                ),
+            );
+        }
+
+        Ok(_) => (),
+
+        Err(e) => {
+            let span = root
+                .get("config_version")
+                .and_then(Item::span)
+                .unwrap_or_default();
+            checker.push(Severity::Error, span, e.to_string());
+            return checker.problems;
+        }
+    }
+
+    let schema = schema();
+    checker.item(&schema, &schema, doc.as_item(), &mut Vec::new());
+
+    // values are only worth checking once the types are right
+    if checker
+        .problems
+        .iter()
+        .any(|p| p.severity == Severity::Error)
+    {
+        return checker.problems;
+    }
+
+    let config = match toml::from_str::<Config>(data) {
+        Ok(config) => config,
+        Err(e) => {
+            let span = e.span().unwrap_or_default();
+            checker.push(Severity::Error, span, e.message().to_owned());
+            return checker.problems;
+        }
+    };
+
+    for (path, e) in config.problems() {
+        let span = span_of(doc.as_item(), &path).unwrap_or_default();
+        checker.push(Severity::Error, span, e.to_string());
+    }
+
+    checker.problems.sort_by_key(|p| (p.line, p.column));
+    checker.problems
+}
+
+/// Span of the deepest item along `path` which exists in the document
+fn span_of(mut item: &Item, path: &[String]) -> Option<Range<usize>> {
+    let mut span = item.span();
+
+    for key in path {
+        let Some(next) = item.as_table_like().and_then(|t| t.get(key)) else {
+            break;
+        };
+
+        item = next;
+        span = item.span().or(span);
+    }
+
+    span
+}
+
+struct Checker<'a> {
+    data: &'a str,
+    problems: Vec<Problem>,
+}
+
+impl Checker<'_> {
+    fn push(&mut self, severity: Severity, span: Range<usize>, message: String) {
+        let before = &self.data[..span.start.min(self.data.len())];
+        let line = before.matches('\n').count() + 1;
+        let column = before
+            .rsplit_once('\n')
+            .map(|(_, l)| l)
+            .unwrap_or(before)
+            .chars()
+            .count()
+            + 1;
+
+        self.problems.push(Problem {
+            severity,
+            line,
+            column,
+            message,
+        });
+    }
+
+    /// Follow `$ref`s to the schema they point at
+    fn resolve<'s>(root: &'s Json, schema: &'s Json) -> &'s Json {
+        let Some(path) = schema.get("$ref").and_then(Json::as_str) else {
+            return schema;
+        };
+
+        path.strip_prefix('#')
+            .and_then(|p| root.pointer(p))
+            .unwrap_or(schema)
+    }
+
+    fn item(&mut self, root: &Json, schema: &Json, item: &Item, path: &mut Vec<String>) {
+        let schema = Self::resolve(root, schema);
+        let span = item.span().unwrap_or_default();
+        let key = if path.is_empty() {
+            "config".to_owned()
+        } else {
+            format!("`{}`", path.join("."))
+        };
+
+        // toml has no null, so an optional value which is present is always the non-null variant
+        if let Some(variants) = schema
+            .get("anyOf")
+            .or_else(|| schema.get("oneOf"))
+            .and_then(Json::as_array)
+        {
+            let variants = variants
+                .iter()
+                .map(|v| Self::resolve(root, v))
+                .filter(|v| v.get("type").and_then(Json::as_str) != Some("null"))
+                .collect::<Vec<_>>();
+
+            for variant in &variants {
+                let len = self.problems.len();
+                self.item(root, variant, item, path);
+
+                if self.problems[len..]
+                    .iter()
+                    .all(|p| p.severity != Severity::Error)
+                {
+                    return;
+                }
+
+                self.problems.truncate(len);
+            }
+
+            let message = match consts(root, schema) {
+                Some(allowed) => format!("{key} must be one of {}", allowed.join(", ")),
+                None => format!("{key} does not match any allowed form"),
+            };
+
+            self.push(Severity::Error, span, message);
+            return;
+        }
+
+        if let Some(expected) = schema.get("type") {
+            let actual = json_type(item);
+
+            let allowed = match expected {
+                Json::String(t) => vec![t.as_str()],
+                Json::Array(types) => types.iter().filter_map(Json::as_str).collect(),
+                _ => Vec::new(),
+            };
+
+            if !allowed.is_empty() && !allowed.iter().any(|t| type_matches(t, actual)) {
+                let expected = allowed
+                    .iter()
+                    .filter(|&&t| t != "null")
+                    .copied()
+                    .collect::<Vec<_>>()
+                    .join(" or ");
+
+                self.push(
+                    Severity::Error,
+                    span,
+                    format!("{key} must be {expected}, found {actual}"),
+                );
+
+                return;
+            }
+        }
+
+        if let Some(expected) = schema.get("const")
+            && let Some(actual) = item.as_str()
+            && expected.as_str() != Some(actual)
+        {
+            self.push(
+                Severity::Error,
+                span.clone(),
+                format!("{key} must be {expected}"),
+            );
+        }
+
+        if let Some(value) = item.as_integer() {
+            self.integer(schema, value, span, &key);
+        }
+
+        if let Some(table) = item.as_table_like() {
+            self.table(root, schema, table, path);
+        }
+
+        if let Some(array) = item.as_array()
+            && let Some(items) = schema.get("items")
+        {
+            for (i, value) in array.iter().enumerate() {
+                path.push(i.to_string());
+                self.item(root, items, &Item::Value(value.clone()), path);
+                path.pop();
+            }
+        }
+
+        if let Some(array) = item.as_array_of_tables()
+            && let Some(items) = schema.get("items")
+        {
+            for (i, table) in array.iter().enumerate() {
+                path.push(i.to_string());
+                self.item(root, items, &Item::Table(table.clone()), path);
+                path.pop();
+            }
+        }
+    }
+
+    fn integer(&mut self, schema: &Json, value: i64, span: Range<usize>, key: &str) {
+        let (mut min, mut max) = match schema.get("format").and_then(Json::as_str) {
+            Some("int32") => (i64::from(i32::MIN), i64::from(i32::MAX)),
+            Some("uint32") => (0, i64::from(u32::MAX)),
+            Some("uint64") => (0, i64::MAX),
+            _ => (i64::MIN, i64::MAX),
+        };
+
+        if let Some(m) = schema.get("minimum").and_then(Json::as_i64) {
+            min = min.max(m);
+        }
+
+        if let Some(m) = schema.get("maximum").and_then(Json::as_i64) {
+            max = max.min(m);
+        }
+
+        if !(min..=max).contains(&value) {
+            let message = if max == i64::MAX {
+                format!("{key} must be at least {min}, found {value}")
+            } else {
+                format!("{key} must be between {min} and {max}, found {value}")
+            };
+
+            self.push(Severity::Error, span, message);
+        }
+    }
+
+    fn table(&mut self, root: &Json, schema: &Json, table: &dyn TableLike, path: &mut Vec<String>) {
+        let empty = Map::new();
+        let properties = schema
+            .get("properties")
+            .and_then(Json::as_object)
+            .unwrap_or(&empty);
+
+        let additional = schema.get("additionalProperties");
+
+        for (key, item) in table.iter() {
+            path.push(key.to_owned());
+
+            match (properties.get(key), additional) {
+                (Some(schema), _) => self.item(root, schema, item, path),
+
+                // free-form, anything goes
+                (None, Some(Json::Bool(true))) => (),
+
+                (None, Some(Json::Bool(false))) => {
+                    let span = key_span(table, key, item);
+                    self.push(
+                        Severity::Error,
+                        span,
+                        format!("unknown key `{}`", path.join(".")),
+                    );
+                }
+
+                (None, Some(schema)) => self.item(root, schema, item, path),
+
+                // serde ignores unknown keys, but they're most likely typos
+                (None, None) => {
+                    let span = key_span(table, key, item);
+                    self.push(
+                        Severity::Warning,
+                        span,
+                        format!("unknown key `{}` will be ignored", path.join(".")),
+                    );
+                }
+            }
+
+            path.pop();
+        }
+    }
+}
+
+/// The doc comment of the key at `path`. Keys which only take documented values list them
+/// too, e.g. `"warn" - unapproved or changed plugins are loaded with a warning`
+pub(super) fn describe(root: &Json, path: &[&str]) -> Option<String> {
+    let mut schema = root;
+    for key in path {
+        schema = Checker::resolve(root, schema)
+            .get("properties")?
+            .get(*key)?;
+    }
+
+    // a field's own doc comment wins over the one on its type
+    let resolved = Checker::resolve(root, schema);
+    let mut docs = schema
+        .get("description")
+        .or_else(|| resolved.get("description"))?
+        .as_str()?
+        .to_owned();
+
+    let values = documented_values(root, resolved);
+    let width = values
+        .iter()
+        .map(|(v, _)| v.len())
+        .max()
+        .unwrap_or_default();
+    for (value, value_docs) in values {
+        docs.push_str(&format!("\n{value:<width$} - {value_docs}"));
+    }
+
+    Some(docs)
+}
+
+/// Each constant a schema allows along with its doc comment, if it has one
+fn documented_values(root: &Json, schema: &Json) -> Vec<(String, String)> {
+    let schema = Checker::resolve(root, schema);
+
+    if let Some(value) = schema.get("const") {
+        return schema
+            .get("description")
+            .and_then(Json::as_str)
+            .map(|docs| (value.to_string(), docs.replace('\n', " ")))
+            .into_iter()
+            .collect();
+    }
+
+    schema
+        .get("anyOf")
+        .or_else(|| schema.get("oneOf"))
+        .and_then(Json::as_array)
+        .into_iter()
+        .flatten()
+        .flat_map(|v| documented_values(root, v))
+        .collect()
+}
+
+/// Every value a schema allows, if it only allows a fixed set
+fn consts(root: &Json, schema: &Json) -> Option<Vec<String>> {
+    let schema = Checker::resolve(root, schema);
+
+    if let Some(value) = schema.get("const") {
+        return Some(vec![value.to_string()]);
+    }
+
+    if let Some(values) = schema.get("enum").and_then(Json::as_array) {
+        return Some(values.iter().map(ToString::to_string).collect());
+    }
+
+    let variants = schema
+        .get("anyOf")
+        .or_else(|| schema.get("oneOf"))
+        .and_then(Json::as_array)?;
+
+    let mut allowed = Vec::new();
+    for variant in variants {
+        let variant = Checker::resolve(root, variant);
+        if variant.get("type").and_then(Json::as_str) == Some("null") {
+            continue;
+        }
+
+        allowed.extend(consts(root, variant)?);
+    }
+
+    Some(allowed)
+}
+
+fn key_span(table: &dyn TableLike, key: &str, item: &Item) -> Range<usize> {
+    table
+        .get_key_value(key)
+        .and_then(|(k, _)| k.span())
+        .or_else(|| item.span())
+        .unwrap_or_default()
+}
+
+/// The json schema type name of a toml item
+fn json_type(item: &Item) -> &'static str {
+    match item {
+        Item::None => "null",
+        Item::Table(_) | Item::Value(Value::InlineTable(_)) => "object",
+        Item::ArrayOfTables(_) | Item::Value(Value::Array(_)) => "array",
+        Item::Value(Value::String(_)) => "string",
+        Item::Value(Value::Integer(_)) => "integer",
+        Item::Value(Value::Float(_)) => "number",
+        Item::Value(Value::Boolean(_)) => "boolean",
+        Item::Value(Value::Datetime(_)) => "datetime",
+    }
+}
+
+fn type_matches(expected: &str, actual: &str) -> bool {
+    expected == actual || (expected == "number" && actual == "integer")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn problems(data: &str) -> Vec<String> {
+        check(data).iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn valid_config_has_no_problems() {
+        let data = "config_version = 1\n[core]\nload_mode = \"sequential\"\n[plugins.FooBar]\nafter = [\"Baz\"]\n";
+
+        assert!(problems(data).is_empty());
+    }
+
+    #[test]
+    fn unknown_key() {
+        let data = "config_version = 1\n[core]\nenabled = true\n  enabeld = false\n";
+
+        assert_eq!(
+            problems(data),
+            ["4:3: warning: unknown key `core.enabeld` will be ignored"]
+        );
+    }
+
+    #[test]
+    fn wrong_type() {
+        let data =
+            "config_version = 1\n[core]\ncli = \"yes\"\n[plugins.FooBar]\nload_order = 1.5\n";
+
+        assert_eq!(
+            problems(data),
+            [
+                "3:7: error: `core.cli` must be boolean, found string",
+                "5:14: error: `plugins.FooBar.load_order` must be integer, found number",
+            ]
+        );
+    }
+
+    #[test]
+    fn bad_enum_value() {
+        let data = "config_version = 1\n[core]\ntrust = \"strict\"\n[plugins.FooBar]\nload_mode = \"serial\"\n";
+
+        assert_eq!(
+            problems(data),
+            [
+                "3:9: error: `core.trust` must be one of \"off\", \"warn\", \"enforce\"",
+                "5:13: error: `plugins.FooBar.load_mode` must be one of \"parallel\", \"sequential\"",
+            ]
+        );
+    }
+
+    #[test]
+    fn invalid_value() {
+        let data = "config_version = 1\n[log]\nlevel = \"loader=loud\"\n";
+
+        assert_eq!(
+            problems(data),
+            ["3:9: error: [log]level: `loader=loud` does not have a valid log level"]
+        );
+    }
+}
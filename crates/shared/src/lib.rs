@@ -2,6 +2,7 @@ pub mod backtrace;
 pub mod config;
 pub mod paths;
 pub mod pipe;
+pub mod plugins;
 pub mod popup;
 pub mod thread_data;
 pub mod utils;
@@ -3,21 +3,24 @@ pub mod commands;
 use std::{
     cell::RefCell,
     convert::Infallible,
+    future::poll_fn,
     io::{self, ErrorKind},
     ops::ControlFlow,
     os::windows::prelude::AsRawHandle as _,
     rc::Rc,
     sync::LazyLock,
+    task::Poll,
 };
 
-use commands::{Command, Receive};
+use commands::{Command, Control, Receive};
 use eyre::Result;
-use serde::Serialize;
+use serde::{Serialize, de::DeserializeOwned};
 use tokio::{
     net::windows::named_pipe::{
         ClientOptions, NamedPipeClient, NamedPipeServer, PipeMode, ServerOptions,
     },
     runtime::{Builder, Runtime},
+    sync::mpsc::{self, UnboundedReceiver, UnboundedSender},
 };
 use tracing::{error, trace, trace_span};
 use windows::Win32::{
@@ -51,7 +54,7 @@ impl Client {
     pub fn new() -> io::Result<Self> {
         let fut = async {
             ClientOptions::new()
-                .read(false)
+                .read(true)
                 .write(true)
                 .pipe_mode(PipeMode::Byte)
                 .open(PIPE)
@@ -63,19 +66,7 @@ impl Client {
     }
 
     pub fn send<T: Serialize>(&self, command: T) -> io::Result<()> {
-        let mut buf = Vec::with_capacity(4096);
-        // <len><uninit>
-        // ^---^ <-- zeroed data
-        buf.resize(size_of::<usize>(), 0);
-
-        // <len><message>
-        //      ^-------^ <-- add message here
-        serde_json::to_writer(&mut buf, &command)?;
-
-        let data_len = buf.len() - size_of::<usize>();
-        // <len><message>
-        // ^---^ <-- copy len to here
-        buf[..size_of::<usize>()].copy_from_slice(&data_len.to_be_bytes());
+        let buf = frame(&command)?;
 
         let fut = async {
             let size = buf.len();
@@ -107,20 +98,94 @@ impl Client {
         RUNTIME.block_on(fut)?;
         Ok(())
     }
+
+    /// Block until the host sends a message. None if the host disconnected
+    pub fn recv<T: DeserializeOwned>(&self) -> io::Result<Option<T>> {
+        let mut len = [0; size_of::<usize>()];
+        if !self.read_exact(&mut len)? {
+            return Ok(None);
+        }
+
+        let mut data = vec![0; usize::from_be_bytes(len)];
+        if !self.read_exact(&mut data)? {
+            return Ok(None);
+        }
+
+        Ok(Some(serde_json::from_slice(&data)?))
+    }
+
+    /// Returns false if the pipe closed before buf was filled
+    fn read_exact(&self, buf: &mut [u8]) -> io::Result<bool> {
+        let fut = async {
+            let mut pos = 0;
+
+            while pos < buf.len() {
+                self.pipe.readable().await?;
+
+                match self.pipe.try_read(&mut buf[pos..]) {
+                    Ok(0) => return Ok(false),
+                    Ok(n) => pos += n,
+                    Err(e) if e.kind() == ErrorKind::WouldBlock => continue,
+                    Err(e) if e.kind() == ErrorKind::BrokenPipe => return Ok(false),
+                    Err(e) => return Err(e),
+                }
+            }
+
+            Ok(true)
+        };
+
+        RUNTIME.block_on(fut)
+    }
+}
+
+/// Encode a message as `<len:usize><json message>`
+fn frame<T: Serialize>(command: &T) -> io::Result<Vec<u8>> {
+    let mut buf = Vec::with_capacity(4096);
+    // <len><uninit>
+    // ^---^ <-- zeroed data
+    buf.resize(size_of::<usize>(), 0);
+
+    // <len><message>
+    //      ^-------^ <-- add message here
+    serde_json::to_writer(&mut buf, command)?;
+
+    let data_len = buf.len() - size_of::<usize>();
+    // <len><message>
+    // ^---^ <-- copy len to here
+    buf[..size_of::<usize>()].copy_from_slice(&data_len.to_be_bytes());
+
+    Ok(buf)
+}
+
+/// Sends [`Control`] messages to the connected loader
+#[derive(Clone)]
+pub struct Controller(UnboundedSender<Control>);
+
+impl Controller {
+    /// Messages sent while no loader is connected are dropped
+    pub fn send(&self, control: Control) {
+        _ = self.0.send(control);
+    }
 }
 
 pub struct Server {
     buf: Vec<u8>,
     tbuf: Box<[u8]>,
     msg_len: Option<usize>,
+    controller: Controller,
+    controls: UnboundedReceiver<Control>,
 }
 
 impl Default for Server {
     fn default() -> Self {
+        let (tx, rx) = mpsc::unbounded_channel();
+
         Self {
             buf: Vec::with_capacity(4096),
             tbuf: vec![0; 4096].into_boxed_slice(),
             msg_len: None,
+            controller: Controller(tx),
+            controls: rx,
         }
     }
 }
@@ -130,6 +195,11 @@ impl Server {
         Self::default()
     }
 
+    /// A handle to send messages to the loader while [`Server::recv_all`] runs
+    pub fn controller(&self) -> Controller {
+        self.controller.clone()
+    }
+
     pub fn recv_all(
         &mut self,
         cb: impl Fn(Receive),
@@ -218,7 +288,7 @@ impl Server {
                 // https://github.com/rust-lang/rust-clippy/issues/13879
                 #[allow(clippy::multiple_unsafe_ops_per_block)]
                 unsafe {
-                    self.connect(&mut sa, &mut process_cmd).await?;
+                    self.connect(&mut sa, &first, &mut process_cmd).await?;
                 }
 
                 // reset state in case it early exited
@@ -236,12 +306,13 @@ impl Server {
     async unsafe fn connect(
         &mut self,
         sa: *mut SECURITY_ATTRIBUTES,
+        unauthed: &RefCell<bool>,
         process_cmd: &mut impl FnMut(&NamedPipeServer, Command) -> ControlFlow<()>,
     ) -> Result<(), io::Error> {
         let server = unsafe {
             ServerOptions::new()
                 .access_inbound(true)
-                .access_outbound(false)
+                .access_outbound(true)
                 .reject_remote_clients(true)
                 .pipe_mode(PipeMode::Byte)
                 .create_with_security_attributes_raw(PIPE, sa.cast())
@@ -261,9 +332,37 @@ impl Server {
             return Ok(());
         }
 
+        // anything sent while nobody was connected was meant for a previous loader
+        while self.controls.try_recv().is_ok() {}
+
         loop {
-            if server.readable().await.is_err() {
-                break;
+            let control = poll_fn(|cx| {
+                if let Poll::Ready(Some(control)) = self.controls.poll_recv(cx) {
+                    return Poll::Ready(Ok(Some(control)));
+                }
+
+                server.poll_read_ready(cx).map_ok(|()| None)
+            })
+            .await;
+
+            match control {
+                Ok(Some(control)) => {
+                    if *unauthed.borrow() {
+                        trace!(?control, "client is not authed yet, dropping control");
+                        continue;
+                    }
+
+                    if let Err(e) = write_all(&server, &frame(&control)?).await {
+                        error!(%e, ?control, "failed to send control");
+                        break;
+                    }
+
+                    continue;
+                }
+
+                Ok(None) => (),
+
+                Err(_) => break,
             }
 
             match server.try_read(&mut self.tbuf) {
@@ -332,3 +431,19 @@ impl Server {
         Ok(())
     }
 }
+
+async fn write_all(server: &NamedPipeServer, buf: &[u8]) -> io::Result<()> {
+    let mut pos = 0;
+
+    while pos < buf.len() {
+        server.writable().await?;
+
+        match server.try_write(&buf[pos..]) {
+            Ok(n) => pos += n,
+            Err(e) if e.kind() == ErrorKind::WouldBlock => continue,
+            Err(e) => return Err(e),
+        }
+    }
+
+    Ok(())
+}
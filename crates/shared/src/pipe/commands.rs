@@ -1,4 +1,10 @@
-use std::{collections::HashMap, fmt::Display};
+use std::{
+    collections::HashMap,
+    fmt::{self, Display},
+    iter,
+    path::PathBuf,
+    time::Duration,
+};
 
 use serde::{Deserialize, Serialize};
 use tracing::level_filters::LevelFilter;
@@ -11,6 +17,64 @@ pub enum Request {
 #[derive(Debug, Serialize, Deserialize)]
 pub enum Receive {
     Log(LogMsg),
+    /// The loader started loading plugins into a game process. Everything after this
+    /// until [`Receive::LoadComplete`] belongs to that process
+    LoadStarted {
+        pid: u32,
+    },
+    /// A plugin dll was found in the plugins dir
+    PluginDiscovered {
+        name: String,
+        path: PathBuf,
+    },
+    /// A plugin was not loaded, e.g. because it is disabled or failed a check
+    PluginSkipped {
+        name: String,
+        reason: String,
+    },
+    /// LoadLibrary is about to be called for a plugin
+    PluginLoading {
+        name: String,
+    },
+    /// A plugin loaded and its Init returned
+    PluginLoaded {
+        name: String,
+        metadata: PluginMetadata,
+        /// How long LoadLibrary took, including the plugin's DllMain
+        load: Duration,
+        /// How long Init took, if the plugin has one
+        init: Option<Duration>,
+    },
+    /// LoadLibrary or Init failed
+    PluginFailed {
+        name: String,
+        error: String,
+    },
+    /// The plugin's load thread exited before it finished, e.g. through ExitThread in Init.
+    /// Its dll stays loaded, so it counts as loaded
+    PluginExited {
+        name: String,
+    },
+    /// Every plugin is done loading
+    LoadComplete(LoadSummary),
+}
+
+/// What the loader knows about a plugin, from its manifest and plugin data
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PluginMetadata {
+    /// e.g. `FooBar by Cherry v1.0.0 (FooBar/FooBar.dll)`
+    pub display: String,
+    pub id: Option<String>,
+    pub version: Option<String>,
+}
+
+/// Sent from the host to the loader
+#[derive(Debug, Serialize, Deserialize)]
+pub enum Control {
+    /// Reload a plugin by name, or every plugin if None. Only done in hot reload mode
+    Reload { name: Option<String> },
+    /// Log at this level from now on, after [log]level or a plugin's log_level changed
+    LogLevel { level: Level },
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -34,6 +98,9 @@ impl From<Receive> for Command {
 #[derive(Debug, Serialize, Deserialize)]
 pub struct LogMsg {
     pub level: Level,
+    /// The plugin the record is about, or which emitted it
+    #[serde(default)]
+    pub plugin: Option<String>,
     pub target: Option<String>,
     pub filename: Option<String>,
     pub line_number: Option<u32>,
@@ -48,7 +115,7 @@ pub struct Span {
 }
 
 impl Display for Span {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "{}", self.name)
     }
 }
@@ -101,3 +168,88 @@ impl TryFrom<&[u8]> for LogMsg {
         serde_json::from_slice(value)
     }
 }
+
+/// How long each plugin took to load, in load order
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct LoadSummary {
+    pub plugins: Vec<PluginTiming>,
+    /// Wall-clock time from the first plugin starting to the last one finishing
+    pub total: Duration,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PluginTiming {
+    pub name: String,
+    pub status: LoadStatus,
+    /// How long LoadLibrary took, including the plugin's DllMain
+    pub load: Option<Duration>,
+    /// How long Init took, if the plugin has one
+    pub init: Option<Duration>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum LoadStatus {
+    Loaded,
+    Failed(String),
+    /// The plugin's thread exited without returning, e.g. through ExitThread in Init
+    Exited,
+}
+
+impl Display for LoadStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Loaded => write!(f, "loaded"),
+            Self::Failed(_) => write!(f, "failed"),
+            Self::Exited => write!(f, "exited"),
+        }
+    }
+}
+
+impl Display for LoadSummary {
+    /// A table with one row per plugin
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let ms = |d: Option<Duration>| match d {
+            Some(d) => format!("{}ms", d.as_millis()),
+            None => "-".to_owned(),
+        };
+
+        let rows = self
+            .plugins
+            .iter()
+            .map(|p| [p.name.clone(), p.status.to_string(), ms(p.load), ms(p.init)])
+            .collect::<Vec<_>>();
+
+        let header = ["plugin", "status", "load", "init"].map(ToOwned::to_owned);
+
+        let mut widths = header.each_ref().map(String::len);
+        for row in &rows {
+            for (width, cell) in widths.iter_mut().zip(row) {
+                *width = (*width).max(cell.len());
+            }
+        }
+
+        write!(
+            f,
+            "Load summary: {} plugin(s) in {}ms",
+            self.plugins.len(),
+            self.total.as_millis()
+        )?;
+
+        for row in iter::once(&header).chain(&rows) {
+            let [name, status, load, init] = row;
+            let [w0, w1, w2, w3] = widths;
+            write!(
+                f,
+                "\n  {name:<w0$}  {status:<w1$}  {load:>w2$}  {init:>w3$}"
+            )?;
+        }
+
+        for p in &self.plugins {
+            if let LoadStatus::Failed(e) = &p.status {
+                write!(f, "\n{} failed: {e}", p.name)?;
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,205 @@
+//! Finding plugin dlls in the plugins dir
+//!
+//! A plugin is either a dll directly in the plugins dir, or a folder holding
+//! the plugin dll together with anything it ships with:
+//!
+//! ```text
+//! Plugins/
+//!   Simple.dll
+//!   FooBar/
+//!     FooBar.dll     <- entry point; same name as the folder
+//!     helper.dll     <- never loaded as a plugin
+//!     data/
+//! ```
+//!
+//! The entry point of a folder is the dll named after the folder. If there is
+//! none, a folder with exactly one dll uses that one. Otherwise the folder is skipped
+//!
+//! Either kind may come with a [`Manifest`]: `FooBar/plugin.toml` in a folder,
+//! or `Simple.plugin.toml` next to a top level dll
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+#[cfg(test)]
+mod fixtures;
+pub mod imports;
+mod manifest;
+pub mod pe;
+mod trust;
+mod version;
+
+use eyre::Result;
+use tracing::{trace, warn};
+use unicase::UniCase;
+
+pub use manifest::{MANIFEST_NAME, MANIFEST_SUFFIX, Manifest};
+pub use trust::{Approval, LOCK_NAME, Lockfile, Trust, hash, lock_path};
+pub use version::{Version, VersionReq};
+
+/// Holds copies of plugins loaded with [core]shadow_copy, in the plugins dir
+pub const CACHE_DIR: &str = ".cache";
+
+/// Holds the folders the loader keeps for plugins through the api, in the plugins dir. A dot
+/// folder, so it can't hide a plugin with the same name
+pub const PLUGIN_FILES_DIR: &str = ".yabg3nml";
+
+/// Holds each plugin's own config file, `<name>.toml`, in [`PLUGIN_FILES_DIR`]
+pub const CONFIG_DIR: &str = "config";
+
+/// Holds a data folder for each plugin, in [`PLUGIN_FILES_DIR`]
+pub const DATA_DIR: &str = "data";
+
+/// Folders in the plugins dir which never hold plugins
+const RESERVED_DIRS: &[&str] = &["logs", CACHE_DIR];
+
+#[derive(Debug, Clone)]
+pub struct PluginFile {
+    /// The dll's filename without extension. Used to refer to the plugin in the config
+    pub name: String,
+    /// Path to the plugin dll
+    pub path: PathBuf,
+    /// The plugin's own folder, if it has one
+    pub dir: Option<PathBuf>,
+    /// Path to the plugin's manifest, if it has one
+    pub manifest: Option<PathBuf>,
+}
+
+/// Find every plugin in the plugins dir, top level dlls first, then by name
+pub fn find_plugins(plugins_dir: &Path) -> Result<Vec<PluginFile>> {
+    let mut found = Vec::new();
+
+    for entry in fs::read_dir(plugins_dir)? {
+        let Ok(entry) = entry else {
+            warn!(?entry, "skipping unreadable dir entry");
+            continue;
+        };
+
+        let path = entry.path();
+
+        let plugin = if path.is_dir() {
+            if is_reserved(&path) {
+                continue;
+            }
+
+            let Some(dll) = entry_point(&path) else {
+                continue;
+            };
+
+            PluginFile {
+                name: stem(&dll),
+                manifest: manifest::manifest_path(&dll, Some(&path)),
+                path: dll,
+                dir: Some(path),
+            }
+        } else if is_dll(&path) {
+            PluginFile {
+                name: stem(&path),
+                manifest: manifest::manifest_path(&path, None),
+                path,
+                dir: None,
+            }
+        } else {
+            continue;
+        };
+
+        found.push(plugin);
+    }
+
+    // top level dlls sort first, so they win name clashes regardless of dir order
+    found.sort_by_key(|p| (p.dir.is_some(), UniCase::new(p.name.clone())));
+
+    let mut plugins = Vec::<PluginFile>::new();
+    for plugin in found {
+        // names are how the config refers to plugins, so they have to be unique
+        if let Some(existing) = plugins
+            .iter()
+            .find(|p| UniCase::new(&p.name) == UniCase::new(&plugin.name))
+        {
+            warn!(
+                plugin = %plugin.name,
+                path = %plugin.path.display(),
+                existing = %existing.path.display(),
+                "skipping plugin with the same name as another plugin"
+            );
+            continue;
+        }
+
+        trace!(plugin = %plugin.name, path = %plugin.path.display(), "found plugin");
+
+        plugins.push(plugin);
+    }
+
+    Ok(plugins)
+}
+
+/// The dll to load from a plugin folder, if it has a clear entry point
+pub fn entry_point(dir: &Path) -> Option<PathBuf> {
+    let dlls = match fs::read_dir(dir) {
+        Ok(read_dir) => read_dir
+            .filter_map(|e| e.ok())
+            .map(|e| e.path())
+            .filter(|p| is_dll(p))
+            .collect::<Vec<_>>(),
+
+        Err(e) => {
+            warn!(dir = %dir.display(), "failed to read plugin folder: {e}");
+            return None;
+        }
+    };
+
+    let folder = dir.file_name().unwrap_or_default().to_string_lossy();
+
+    if let Some(dll) = dlls
+        .iter()
+        .find(|p| UniCase::new(stem(p)) == UniCase::new(folder.to_string()))
+    {
+        return Some(dll.clone());
+    }
+
+    match &*dlls {
+        [] => {
+            trace!(dir = %dir.display(), "folder has no dlls");
+            None
+        }
+
+        [dll] => Some(dll.clone()),
+
+        _ => {
+            warn!(
+                dir = %dir.display(),
+                "skipping plugin folder with several dlls and none named {folder}.dll; rename the plugin's dll to match the folder"
+            );
+            None
+        }
+    }
+}
+
+fn is_reserved(dir: &Path) -> bool {
+    let name = dir.file_name().unwrap_or_default().to_string_lossy();
+
+    // dot folders are ours, or hidden for a reason
+    name.starts_with('.')
+        || RESERVED_DIRS
+            .iter()
+            .any(|r| UniCase::new(*r) == UniCase::new(&*name))
+}
+
+fn is_dll(path: &Path) -> bool {
+    path.is_file()
+        && path
+            .extension()
+            .unwrap_or_default()
+            .eq_ignore_ascii_case("dll")
+}
+
+fn stem(path: &Path) -> String {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    if stem.is_empty() {
+        "<unknown>".to_owned()
+    } else {
+        stem.into_owned()
+    }
+}
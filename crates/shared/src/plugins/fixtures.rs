@@ -0,0 +1,322 @@
+//! Minimal PE images for tests, so the checks run against real headers without binary
+//! files in the repo
+//!
+//! The image has a `.text` section with a few `ret`s to point code at, and a `.rdata`
+//! section holding every table
+
+use pelite::image::{
+    IMAGE_DIRECTORY_ENTRY_COM_DESCRIPTOR, IMAGE_DIRECTORY_ENTRY_EXPORT,
+    IMAGE_DIRECTORY_ENTRY_IMPORT, IMAGE_DIRECTORY_ENTRY_TLS, IMAGE_FILE_DLL,
+    IMAGE_FILE_EXECUTABLE_IMAGE, IMAGE_FILE_MACHINE_AMD64, IMAGE_SCN_CNT_CODE,
+    IMAGE_SCN_CNT_INITIALIZED_DATA, IMAGE_SCN_MEM_EXECUTE, IMAGE_SCN_MEM_READ,
+};
+
+const FILE_ALIGNMENT: u32 = 0x200;
+const SECTION_ALIGNMENT: u32 = 0x1000;
+const IMAGE_BASE: u64 = 0x1_8000_0000;
+
+const TEXT_RVA: u32 = 0x1000;
+const TEXT_SIZE: u32 = 0x10;
+const RDATA_RVA: u32 = 0x2000;
+
+pub struct TestPe {
+    pub bits32: bool,
+    pub machine: u16,
+    pub dll: bool,
+    pub entry_point: bool,
+    /// Names of functions exported into `.text`
+    pub exports: Vec<&'static str>,
+    /// Names of exported data in `.rdata`, which is not executable
+    pub data_exports: Vec<&'static str>,
+    /// Names of the dlls it imports
+    pub imports: Vec<&'static str>,
+    pub tls_callbacks: bool,
+    /// Has a CLR header, like a .NET assembly
+    pub dotnet: bool,
+}
+
+impl TestPe {
+    /// A 64-bit plugin with a DllMain which exports Init
+    pub fn plugin() -> Self {
+        Self {
+            bits32: false,
+            machine: IMAGE_FILE_MACHINE_AMD64,
+            dll: true,
+            entry_point: true,
+            exports: vec!["Init"],
+            data_exports: Vec::new(),
+            imports: Vec::new(),
+            tls_callbacks: false,
+            dotnet: false,
+        }
+    }
+
+    /// A 64-bit dll which does nothing but import `imports`
+    pub fn importing(imports: &[&'static str]) -> Self {
+        Self {
+            exports: Vec::new(),
+            imports: imports.to_vec(),
+            ..Self::plugin()
+        }
+    }
+
+    pub fn build(&self) -> Vec<u8> {
+        let mut rdata = Rdata::default();
+        let mut dirs = [(0u32, 0u32); 16];
+
+        if !self.exports.is_empty() || !self.data_exports.is_empty() {
+            dirs[IMAGE_DIRECTORY_ENTRY_EXPORT] = self.export_table(&mut rdata);
+        }
+
+        if !self.imports.is_empty() {
+            dirs[IMAGE_DIRECTORY_ENTRY_IMPORT] = self.import_table(&mut rdata);
+        }
+
+        if self.tls_callbacks {
+            dirs[IMAGE_DIRECTORY_ENTRY_TLS] = tls_directory(&mut rdata);
+        }
+
+        if self.dotnet {
+            // only its presence matters
+            let rva = rdata.put(&[0; 72]);
+            dirs[IMAGE_DIRECTORY_ENTRY_COM_DESCRIPTOR] = (rva, 72);
+        }
+
+        // give it something in case it is empty
+        rdata.put(&[0; 8]);
+
+        let rdata_raw = align(rdata.0.len() as u32, FILE_ALIGNMENT);
+        let size_of_image = RDATA_RVA + align(rdata_raw, SECTION_ALIGNMENT);
+
+        let mut image = vec![0u8; FILE_ALIGNMENT as usize];
+
+        // dos header, pointing at the nt headers right after it
+        image[..2].copy_from_slice(b"MZ");
+        image[0x3c..0x40].copy_from_slice(&0x40u32.to_le_bytes());
+
+        let mut headers = Vec::new();
+        headers.extend_from_slice(b"PE\0\0");
+
+        let optional_size: u16 = if self.bits32 { 224 } else { 240 };
+        let mut characteristics = IMAGE_FILE_EXECUTABLE_IMAGE;
+        if self.dll {
+            characteristics |= IMAGE_FILE_DLL;
+        }
+
+        // file header
+        headers.extend_from_slice(&self.machine.to_le_bytes());
+        headers.extend_from_slice(&2u16.to_le_bytes());
+        headers.extend_from_slice(&[0; 12]);
+        headers.extend_from_slice(&optional_size.to_le_bytes());
+        headers.extend_from_slice(&characteristics.to_le_bytes());
+
+        // optional header
+        let entry = if self.entry_point { TEXT_RVA } else { 0 };
+        headers.extend_from_slice(&(if self.bits32 { 0x10bu16 } else { 0x20b }).to_le_bytes());
+        headers.extend_from_slice(&[14, 0]);
+        headers.extend_from_slice(&FILE_ALIGNMENT.to_le_bytes());
+        headers.extend_from_slice(&rdata_raw.to_le_bytes());
+        headers.extend_from_slice(&0u32.to_le_bytes());
+        headers.extend_from_slice(&entry.to_le_bytes());
+        headers.extend_from_slice(&TEXT_RVA.to_le_bytes());
+        if self.bits32 {
+            headers.extend_from_slice(&RDATA_RVA.to_le_bytes());
+            headers.extend_from_slice(&0x1000_0000u32.to_le_bytes());
+        } else {
+            headers.extend_from_slice(&IMAGE_BASE.to_le_bytes());
+        }
+        headers.extend_from_slice(&SECTION_ALIGNMENT.to_le_bytes());
+        headers.extend_from_slice(&FILE_ALIGNMENT.to_le_bytes());
+        // os, image and subsystem versions
+        for version in [6u16, 0, 0, 0, 6, 0] {
+            headers.extend_from_slice(&version.to_le_bytes());
+        }
+        headers.extend_from_slice(&0u32.to_le_bytes());
+        headers.extend_from_slice(&size_of_image.to_le_bytes());
+        headers.extend_from_slice(&FILE_ALIGNMENT.to_le_bytes());
+        headers.extend_from_slice(&0u32.to_le_bytes());
+        // windows gui subsystem, no dll characteristics
+        headers.extend_from_slice(&2u16.to_le_bytes());
+        headers.extend_from_slice(&0u16.to_le_bytes());
+        // stack and heap sizes
+        for size in [0x10_0000u64, 0x1000, 0x10_0000, 0x1000] {
+            if self.bits32 {
+                headers.extend_from_slice(&(size as u32).to_le_bytes());
+            } else {
+                headers.extend_from_slice(&size.to_le_bytes());
+            }
+        }
+        headers.extend_from_slice(&0u32.to_le_bytes());
+        headers.extend_from_slice(&16u32.to_le_bytes());
+        for (rva, size) in dirs {
+            headers.extend_from_slice(&rva.to_le_bytes());
+            headers.extend_from_slice(&size.to_le_bytes());
+        }
+
+        section(
+            &mut headers,
+            b".text",
+            TEXT_RVA,
+            FILE_ALIGNMENT,
+            FILE_ALIGNMENT,
+            IMAGE_SCN_CNT_CODE | IMAGE_SCN_MEM_EXECUTE | IMAGE_SCN_MEM_READ,
+        );
+        section(
+            &mut headers,
+            b".rdata",
+            RDATA_RVA,
+            rdata_raw,
+            FILE_ALIGNMENT * 2,
+            IMAGE_SCN_CNT_INITIALIZED_DATA | IMAGE_SCN_MEM_READ,
+        );
+
+        image[0x40..0x40 + headers.len()].copy_from_slice(&headers);
+
+        // .text is nothing but `ret`
+        let mut text = vec![0u8; FILE_ALIGNMENT as usize];
+        text[..TEXT_SIZE as usize].fill(0xc3);
+        image.extend_from_slice(&text);
+
+        rdata.0.resize(rdata_raw as usize, 0);
+        image.extend_from_slice(&rdata.0);
+
+        image
+    }
+
+    fn export_table(&self, rdata: &mut Rdata) -> (u32, u32) {
+        let mut exports = self
+            .exports
+            .iter()
+            .enumerate()
+            .map(|(i, &name)| (name, TEXT_RVA + i as u32))
+            .collect::<Vec<_>>();
+
+        for &name in &self.data_exports {
+            let rva = rdata.put(&[0; 8]);
+            exports.push((name, rva));
+        }
+
+        // names are looked up by binary search
+        exports.sort_by_key(|&(name, _)| name);
+
+        let dll_name = rdata.put(b"test.dll\0");
+        let names = exports
+            .iter()
+            .map(|(name, _)| rdata.put(&[name.as_bytes(), b"\0"].concat()))
+            .collect::<Vec<_>>();
+
+        let functions = rdata.put(
+            &exports
+                .iter()
+                .flat_map(|(_, rva)| rva.to_le_bytes())
+                .collect::<Vec<_>>(),
+        );
+        let name_table = rdata.put(
+            &names
+                .iter()
+                .flat_map(|n| n.to_le_bytes())
+                .collect::<Vec<_>>(),
+        );
+        let ordinals = rdata.put(
+            &(0..exports.len() as u16)
+                .flat_map(u16::to_le_bytes)
+                .collect::<Vec<_>>(),
+        );
+
+        let count = exports.len() as u32;
+        let mut dir = Vec::new();
+        dir.extend_from_slice(&[0; 12]);
+        dir.extend_from_slice(&dll_name.to_le_bytes());
+        dir.extend_from_slice(&1u32.to_le_bytes());
+        dir.extend_from_slice(&count.to_le_bytes());
+        dir.extend_from_slice(&count.to_le_bytes());
+        dir.extend_from_slice(&functions.to_le_bytes());
+        dir.extend_from_slice(&name_table.to_le_bytes());
+        dir.extend_from_slice(&ordinals.to_le_bytes());
+
+        (rdata.put(&dir), dir.len() as u32)
+    }
+
+    fn import_table(&self, rdata: &mut Rdata) -> (u32, u32) {
+        // every dll gets an empty thunk list; only the names matter
+        let thunk_size = if self.bits32 { 4 } else { 8 };
+
+        let descriptors = self
+            .imports
+            .iter()
+            .map(|name| {
+                let name = rdata.put(&[name.as_bytes(), b"\0"].concat());
+                let lookup = rdata.put(&vec![0; thunk_size]);
+                let address = rdata.put(&vec![0; thunk_size]);
+                (lookup, name, address)
+            })
+            .collect::<Vec<_>>();
+
+        let mut dir = Vec::new();
+        for (lookup, name, address) in descriptors {
+            dir.extend_from_slice(&lookup.to_le_bytes());
+            dir.extend_from_slice(&[0; 8]);
+            dir.extend_from_slice(&name.to_le_bytes());
+            dir.extend_from_slice(&address.to_le_bytes());
+        }
+        dir.extend_from_slice(&[0; 20]);
+
+        (rdata.put(&dir), dir.len() as u32)
+    }
+}
+
+/// The contents of `.rdata`
+#[derive(Default)]
+struct Rdata(Vec<u8>);
+
+impl Rdata {
+    /// Append 8 byte aligned data, returning its rva
+    fn put(&mut self, data: &[u8]) -> u32 {
+        self.0.resize(self.0.len().next_multiple_of(8), 0);
+        let rva = RDATA_RVA + self.0.len() as u32;
+        self.0.extend_from_slice(data);
+        rva
+    }
+}
+
+/// A 64-bit tls directory with a single callback into `.text`
+fn tls_directory(rdata: &mut Rdata) -> (u32, u32) {
+    let va = |rva: u32| IMAGE_BASE + u64::from(rva);
+
+    let raw = rdata.put(&[0; 8]);
+    let index = rdata.put(&[0; 4]);
+    let callbacks = rdata.put(&[va(TEXT_RVA).to_le_bytes(), [0; 8]].concat());
+
+    let mut dir = Vec::new();
+    dir.extend_from_slice(&va(raw).to_le_bytes());
+    dir.extend_from_slice(&va(raw + 8).to_le_bytes());
+    dir.extend_from_slice(&va(index).to_le_bytes());
+    dir.extend_from_slice(&va(callbacks).to_le_bytes());
+    dir.extend_from_slice(&[0; 8]);
+
+    (rdata.put(&dir), dir.len() as u32)
+}
+
+fn section(
+    headers: &mut Vec<u8>,
+    name: &[u8],
+    rva: u32,
+    raw_size: u32,
+    raw_offset: u32,
+    characteristics: u32,
+) {
+    let mut padded = [0u8; 8];
+    padded[..name.len()].copy_from_slice(name);
+
+    headers.extend_from_slice(&padded);
+    headers.extend_from_slice(&raw_size.to_le_bytes());
+    headers.extend_from_slice(&rva.to_le_bytes());
+    headers.extend_from_slice(&raw_size.to_le_bytes());
+    headers.extend_from_slice(&raw_offset.to_le_bytes());
+    headers.extend_from_slice(&[0; 12]);
+    headers.extend_from_slice(&characteristics.to_le_bytes());
+}
+
+fn align(value: u32, to: u32) -> u32 {
+    value.next_multiple_of(to)
+}
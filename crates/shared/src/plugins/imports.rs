@@ -0,0 +1,428 @@
+use std::{
+    collections::HashSet,
+    fmt::{self, Display},
+    path::{Path, PathBuf},
+};
+
+use pelite::pe64::{Pe, PeFile};
+use unicase::UniCase;
+
+/// Api set names resolve inside the os loader, not to files on disk
+const API_SET_PREFIXES: &[&str] = &["api-ms-win-", "ext-ms-"];
+
+/// What the import resolver needs to know about the machine. Implemented over the
+/// real filesystem by the loader, and over anything else for checking a layout offline
+pub trait ImportEnv {
+    /// Whether a dll with this name is already loaded in the process. Loaded dlls
+    /// satisfy imports by name, wherever they are
+    fn is_loaded(&self, name: &str) -> bool;
+    fn exists(&self, path: &Path) -> bool;
+    fn read(&self, path: &Path) -> Option<Vec<u8>>;
+    /// System32, then the Windows dir
+    fn system_dirs(&self) -> Vec<PathBuf>;
+    fn current_dir(&self) -> Option<PathBuf>;
+    /// The dirs in PATH, in order
+    fn path_dirs(&self) -> Vec<PathBuf>;
+    /// The dir of the game exe
+    fn exe_dir(&self) -> Option<PathBuf>;
+}
+
+#[derive(Debug, Clone)]
+pub struct MissingDll {
+    pub name: String,
+    /// The chain of dlls importing it, starting with the plugin
+    pub needed_by: Vec<String>,
+}
+
+impl MissingDll {
+    /// Where to get well known dlls from
+    pub fn hint(&self) -> Option<&'static str> {
+        let name = self.name.to_ascii_lowercase();
+
+        let hint = if name.starts_with("vcruntime")
+            || name.starts_with("msvcp")
+            || name.starts_with("concrt")
+            || name.starts_with("vcomp")
+        {
+            "it is part of the Microsoft Visual C++ Redistributable; install the latest x64 version"
+        } else if name.starts_with("d3dx")
+            || name.starts_with("xinput1_")
+            || name.starts_with("d3dcompiler_4")
+        {
+            "it is part of the DirectX End-User Runtime"
+        } else {
+            return None;
+        };
+
+        Some(hint)
+    }
+}
+
+impl Display for MissingDll {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} (needed by {})",
+            self.name,
+            self.needed_by.join(" -> ")
+        )?;
+
+        if let Some(hint) = self.hint() {
+            write!(f, "; {hint}")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// How a plugin is loaded, which decides where windows searches for its imports
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum LoadPath {
+    /// From the plugin's own path, with LOAD_WITH_ALTERED_SEARCH_PATH
+    Original,
+    /// From a shadow copy, with LOAD_LIBRARY_SEARCH_DLL_LOAD_DIR and
+    /// LOAD_LIBRARY_SEARCH_DEFAULT_DIRS, and the plugin's own dir added with AddDllDirectory
+    ShadowCopy,
+}
+
+/// The dirs windows searches for a plugin's imports when it is loaded through `load`
+pub fn search_order(plugin: &Path, load: LoadPath, env: &impl ImportEnv) -> Vec<PathBuf> {
+    let mut dirs = Vec::new();
+
+    match load {
+        LoadPath::Original => {
+            dirs.extend(plugin.parent().map(Path::to_path_buf));
+            dirs.extend(env.system_dirs());
+            dirs.extend(env.current_dir());
+            dirs.extend(env.path_dirs());
+
+            // the game's own dir is only searched because it's usually the current dir, but
+            // it's where the game's dlls are, so look there regardless
+            dirs.extend(env.exe_dir());
+        }
+
+        // the copy's dir comes first, but the copy is alone in it. The current dir and PATH
+        // are never searched, and of the system dirs only System32 is
+        LoadPath::ShadowCopy => {
+            dirs.extend(env.exe_dir());
+            dirs.extend(plugin.parent().map(Path::to_path_buf));
+            dirs.extend(env.system_dirs().into_iter().take(1));
+        }
+    }
+
+    let mut seen = Vec::new();
+    dirs.retain(|d| {
+        let key = d.as_os_str().to_ascii_lowercase();
+        if seen.contains(&key) {
+            false
+        } else {
+            seen.push(key);
+            true
+        }
+    });
+
+    dirs
+}
+
+/// Find every dll a plugin imports, directly or through dlls it ships with, which
+/// can't be found in `search` (the dirs windows looks in, in order)
+///
+/// Dlls found outside the plugin's own dirs are assumed to be complete, so system
+/// dlls are not walked
+pub fn find_missing(
+    plugin: &Path,
+    image: &[u8],
+    search: &[PathBuf],
+    env: &impl ImportEnv,
+) -> Vec<MissingDll> {
+    let plugin_dir = plugin.parent().unwrap_or(Path::new(""));
+    let name = plugin
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .into_owned();
+
+    let mut resolver = Resolver {
+        plugin_dir,
+        search,
+        env,
+        visited: HashSet::from([UniCase::new(name.clone())]),
+        missing: Vec::new(),
+    };
+
+    resolver.walk(image, &mut vec![name]);
+    resolver.missing
+}
+
+struct Resolver<'a, E> {
+    plugin_dir: &'a Path,
+    search: &'a [PathBuf],
+    env: &'a E,
+    visited: HashSet<UniCase<String>>,
+    missing: Vec<MissingDll>,
+}
+
+impl<E: ImportEnv> Resolver<'_, E> {
+    fn walk(&mut self, image: &[u8], chain: &mut Vec<String>) {
+        for import in imports(image) {
+            if is_api_set(&import) || !self.visited.insert(UniCase::new(import.clone())) {
+                continue;
+            }
+
+            if self.env.is_loaded(&import) {
+                continue;
+            }
+
+            let Some(path) = self
+                .search
+                .iter()
+                .map(|dir| dir.join(&import))
+                .find(|p| self.env.exists(p))
+            else {
+                self.missing.push(MissingDll {
+                    name: import,
+                    needed_by: chain.clone(),
+                });
+
+                continue;
+            };
+
+            // only dlls shipped with the plugin are likely to be missing something themselves
+            if path.starts_with(self.plugin_dir)
+                && let Some(image) = self.env.read(&path)
+            {
+                chain.push(import);
+                self.walk(&image, chain);
+                chain.pop();
+            }
+        }
+    }
+}
+
+/// Names of the dlls an image imports. Delay loaded imports are left out, since they
+/// don't stop the dll from loading
+fn imports(image: &[u8]) -> Vec<String> {
+    let Ok(file) = PeFile::from_bytes(image) else {
+        return Vec::new();
+    };
+
+    let Ok(imports) = file.imports() else {
+        return Vec::new();
+    };
+
+    imports
+        .iter()
+        .filter_map(|desc| desc.dll_name().ok())
+        .filter_map(|name| name.to_str().ok())
+        .map(ToOwned::to_owned)
+        .collect()
+}
+
+fn is_api_set(name: &str) -> bool {
+    let name = name.to_ascii_lowercase();
+    API_SET_PREFIXES.iter().any(|p| name.starts_with(p))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::plugins::fixtures::TestPe;
+
+    /// A machine made of the files in `files`
+    #[derive(Default)]
+    struct FakeEnv {
+        files: HashMap<PathBuf, Vec<u8>>,
+        loaded: Vec<&'static str>,
+        current: Option<PathBuf>,
+        path: Vec<PathBuf>,
+    }
+
+    impl FakeEnv {
+        fn dll(&mut self, path: &str, imports: &[&'static str]) {
+            self.files
+                .insert(PathBuf::from(path), TestPe::importing(imports).build());
+        }
+    }
+
+    impl ImportEnv for FakeEnv {
+        fn is_loaded(&self, name: &str) -> bool {
+            self.loaded
+                .iter()
+                .any(|l| UniCase::new(*l) == UniCase::new(name))
+        }
+
+        fn exists(&self, path: &Path) -> bool {
+            self.files.contains_key(path)
+        }
+
+        fn read(&self, path: &Path) -> Option<Vec<u8>> {
+            self.files.get(path).cloned()
+        }
+
+        fn system_dirs(&self) -> Vec<PathBuf> {
+            vec!["/windows/system32".into(), "/windows".into()]
+        }
+
+        fn current_dir(&self) -> Option<PathBuf> {
+            self.current.clone()
+        }
+
+        fn path_dirs(&self) -> Vec<PathBuf> {
+            self.path.clone()
+        }
+
+        fn exe_dir(&self) -> Option<PathBuf> {
+            Some("/game/bin".into())
+        }
+    }
+
+    fn missing(env: &FakeEnv, plugin: &str, imports: &[&'static str]) -> Vec<String> {
+        let plugin = Path::new(plugin);
+        let image = TestPe::importing(imports).build();
+
+        find_missing(
+            plugin,
+            &image,
+            &search_order(plugin, LoadPath::Original, env),
+            env,
+        )
+        .iter()
+        .map(ToString::to_string)
+        .collect()
+    }
+
+    #[test]
+    fn search_order_follows_windows() {
+        let env = FakeEnv {
+            current: Some("/game/bin".into()),
+            path: vec!["/tools".into(), "/WINDOWS/System32".into()],
+            ..Default::default()
+        };
+
+        assert_eq!(
+            search_order(
+                Path::new("/plugins/FooBar/FooBar.dll"),
+                LoadPath::Original,
+                &env
+            ),
+            [
+                PathBuf::from("/plugins/FooBar"),
+                "/windows/system32".into(),
+                "/windows".into(),
+                "/game/bin".into(),
+                "/tools".into(),
+            ]
+        );
+
+        assert_eq!(
+            search_order(
+                Path::new("/plugins/FooBar/FooBar.dll"),
+                LoadPath::ShadowCopy,
+                &env
+            ),
+            [
+                PathBuf::from("/game/bin"),
+                "/plugins/FooBar".into(),
+                "/windows/system32".into(),
+            ]
+        );
+    }
+
+    #[test]
+    fn shadow_copies_ignore_path() {
+        let mut env = FakeEnv {
+            path: vec!["/tools".into()],
+            ..Default::default()
+        };
+        env.dll("/tools/helper.dll", &[]);
+
+        let plugin = Path::new("/plugins/FooBar/FooBar.dll");
+        let image = TestPe::importing(&["helper.dll"]).build();
+
+        let search = search_order(plugin, LoadPath::Original, &env);
+        assert!(find_missing(plugin, &image, &search, &env).is_empty());
+
+        let search = search_order(plugin, LoadPath::ShadowCopy, &env);
+        let missing = find_missing(plugin, &image, &search, &env);
+        assert_eq!(missing.len(), 1);
+        assert_eq!(missing[0].name, "helper.dll");
+    }
+
+    #[test]
+    fn everything_found() {
+        let mut env = FakeEnv {
+            loaded: vec!["KERNEL32.dll"],
+            ..Default::default()
+        };
+        env.dll("/windows/system32/user32.dll", &[]);
+        env.dll("/game/bin/bink2w64.dll", &[]);
+
+        let imports = [
+            "kernel32.dll",
+            "user32.dll",
+            "bink2w64.dll",
+            "api-ms-win-crt-runtime-l1-1-0.dll",
+        ];
+        assert!(missing(&env, "/plugins/FooBar.dll", &imports).is_empty());
+    }
+
+    #[test]
+    fn missing_direct_import() {
+        let env = FakeEnv::default();
+
+        assert_eq!(
+            missing(
+                &env,
+                "/plugins/FooBar.dll",
+                &["vcruntime140_1.dll", "foo.dll"]
+            ),
+            [
+                "vcruntime140_1.dll (needed by FooBar.dll); it is part of the Microsoft Visual C++ Redistributable; install the latest x64 version",
+                "foo.dll (needed by FooBar.dll)",
+            ]
+        );
+    }
+
+    #[test]
+    fn walks_shipped_dlls_only() {
+        let mut env = FakeEnv::default();
+        // shipped with the plugin, so whatever it needs is checked too
+        env.dll(
+            "/plugins/FooBar/helper.dll",
+            &["FooBar.dll", "inner.dll", "shared.dll"],
+        );
+        env.dll("/plugins/FooBar/inner.dll", &["deep.dll", "helper.dll"]);
+        // a system dll is assumed to be complete
+        env.dll("/windows/system32/system.dll", &["not-checked.dll"]);
+
+        assert_eq!(
+            missing(
+                &env,
+                "/plugins/FooBar/FooBar.dll",
+                &["helper.dll", "system.dll", "shared.dll"]
+            ),
+            [
+                "deep.dll (needed by FooBar.dll -> helper.dll -> inner.dll)",
+                "shared.dll (needed by FooBar.dll -> helper.dll)",
+            ]
+        );
+    }
+
+    #[test]
+    fn not_a_pe() {
+        let env = FakeEnv::default();
+        let plugin = Path::new("/plugins/FooBar.dll");
+
+        assert!(
+            find_missing(
+                plugin,
+                b"MZ",
+                &search_order(plugin, LoadPath::Original, &env),
+                &env
+            )
+            .is_empty()
+        );
+    }
+}
@@ -0,0 +1,145 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use eyre::{Context as _, Result, bail};
+use serde::Deserialize;
+use unicase::UniCase;
+
+use super::version::{Version, VersionReq};
+
+/// Manifest filename inside a plugin folder
+pub const MANIFEST_NAME: &str = "plugin.toml";
+/// Manifest filename suffix for a dll directly in the plugins dir, e.g. `FooBar.plugin.toml`
+pub const MANIFEST_SUFFIX: &str = ".plugin.toml";
+
+/// Optional metadata a plugin ships next to its dll
+///
+/// ```toml
+/// id = "foobar"
+/// version = "1.2.0"
+/// min_loader_version = "0.4.0"
+/// game_versions = ["^4.1.1"]
+/// conflicts = ["OldFooBar"]
+///
+/// [dependencies]
+/// SomeLib = ">=1.0, <2"
+/// ```
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Manifest {
+    /// Stable name other plugins can refer to this plugin by, whatever the dll is called
+    pub id: Option<String>,
+    /// The plugin's own version. Takes precedence over the version the dll declares
+    pub version: Option<Version>,
+    /// Plugins which must be loaded first, by name or id, and which versions of them work
+    pub dependencies: BTreeMap<String, VersionReq>,
+    /// Plugins, by name or id, which this plugin refuses to load alongside
+    pub conflicts: Vec<String>,
+    /// The oldest loader this plugin works with
+    pub min_loader_version: Option<Version>,
+    /// Game versions this plugin works with. Any one of them has to match. Empty means all
+    pub game_versions: Vec<VersionReq>,
+}
+
+impl Manifest {
+    /// Read and validate a manifest
+    pub fn load(path: &Path) -> Result<Self> {
+        let data = fs::read_to_string(path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+
+        let manifest = toml::from_str::<Self>(&data)
+            .with_context(|| format!("failed to parse {}", path.display()))?;
+
+        manifest
+            .validate()
+            .with_context(|| format!("invalid manifest {}", path.display()))?;
+
+        Ok(manifest)
+    }
+
+    fn validate(&self) -> Result<()> {
+        if let Some(id) = &self.id {
+            let valid = !id.is_empty()
+                && id
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'));
+
+            if !valid {
+                bail!(
+                    "id `{id}` must be non-empty and only contain ascii letters, digits, `_`, `-` and `.`"
+                );
+            }
+        }
+
+        // plugin names and ids both compare case insensitively
+        if let Some(dep) = self.dependencies.keys().find(|d| {
+            self.conflicts
+                .iter()
+                .any(|c| UniCase::new(c.as_str()) == UniCase::new(d.as_str()))
+        }) {
+            bail!("`{dep}` is both a dependency and a conflict");
+        }
+
+        Ok(())
+    }
+
+    /// Check the loader and game versions this plugin declared it works with
+    pub fn check_compat(&self, loader: &Version, game: Option<&Version>) -> Result<()> {
+        if let Some(min) = &self.min_loader_version
+            && loader < min
+        {
+            bail!("requires loader version {min} or newer, but this is {loader}");
+        }
+
+        if let Some(game) = game
+            && !self.game_versions.is_empty()
+            && !self.game_versions.iter().any(|r| r.matches(game))
+        {
+            let supported = self
+                .game_versions
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(" or ");
+
+            bail!("supports game version {supported}, but the game is {game}");
+        }
+
+        Ok(())
+    }
+}
+
+/// Where the manifest for a plugin dll would be, if it has one
+pub(super) fn manifest_path(dll: &Path, dir: Option<&Path>) -> Option<PathBuf> {
+    let path = match dir {
+        Some(dir) => dir.join(MANIFEST_NAME),
+        None => {
+            let stem = dll.file_stem()?.to_string_lossy();
+            dll.with_file_name(format!("{stem}{MANIFEST_SUFFIX}"))
+        }
+    };
+
+    path.is_file().then_some(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dependency_conflicts_ignore_case() {
+        let manifest = toml::from_str::<Manifest>(
+            "conflicts = [\"foobar\"]\n[dependencies]\nFooBar = \"1\"\n",
+        )
+        .unwrap();
+
+        let e = manifest.validate().unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "`FooBar` is both a dependency and a conflict"
+        );
+    }
+}
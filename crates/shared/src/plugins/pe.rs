@@ -0,0 +1,382 @@
+use std::fmt::{self, Display};
+
+use pelite::{
+    Error as PeError, PeFile, Wrap,
+    image::{
+        IMAGE_DIRECTORY_ENTRY_COM_DESCRIPTOR, IMAGE_FILE_DLL, IMAGE_FILE_MACHINE_AMD64,
+        IMAGE_FILE_MACHINE_I386, IMAGE_SCN_MEM_EXECUTE,
+    },
+    pe64::{Pe, PeFile as PeFile64},
+};
+
+const IMAGE_FILE_MACHINE_ARM64: u16 = 0xaa64;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// The plugin can be loaded, but may not behave as intended
+    Warning,
+    /// Loading the plugin would fail or crash, so it is skipped
+    Error,
+}
+
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+/// Check that a dll image is something the game can load as a plugin, without loading it
+///
+/// Returns every problem found; the image is fine to load if none of them are errors
+pub fn validate(image: &[u8]) -> Vec<Diagnostic> {
+    let mut diagnostics = Diagnostics(Vec::new());
+
+    if !image.starts_with(b"MZ") {
+        diagnostics.error("is not a dll; it does not start with a PE header. The file may be mislabeled or an incomplete download");
+        return diagnostics.0;
+    }
+
+    let file = match PeFile::from_bytes(image) {
+        Ok(Wrap::T64(file)) => file,
+
+        Ok(Wrap::T32(_)) => {
+            diagnostics.error(
+                "is a 32-bit dll, but the game is 64-bit. Use the plugin's 64-bit (x64) build",
+            );
+            return diagnostics.0;
+        }
+
+        Err(e) => {
+            diagnostics.error(format!(
+                "is corrupted; its PE headers are invalid ({e}). Try downloading it again"
+            ));
+            return diagnostics.0;
+        }
+    };
+
+    match file.file_header().Machine {
+        IMAGE_FILE_MACHINE_AMD64 => (),
+        machine => {
+            let arch = match machine {
+                IMAGE_FILE_MACHINE_ARM64 => "arm64".to_owned(),
+                IMAGE_FILE_MACHINE_I386 => "x86".to_owned(),
+                m => format!("machine type 0x{m:04x}"),
+            };
+
+            diagnostics.error(format!(
+                "is built for {arch}, but the game is x64. Use the plugin's x64 build"
+            ));
+            return diagnostics.0;
+        }
+    }
+
+    if file.file_header().Characteristics & IMAGE_FILE_DLL == 0 {
+        diagnostics.error("is an executable renamed to .dll, not a dll");
+        return diagnostics.0;
+    }
+
+    if file
+        .data_directory()
+        .get(IMAGE_DIRECTORY_ENTRY_COM_DESCRIPTOR)
+        .is_some_and(|d| d.VirtualAddress != 0)
+    {
+        diagnostics.error("is a .NET assembly; only native dlls can be loaded as plugins");
+        return diagnostics.0;
+    }
+
+    let has_entry = entry_point(file, &mut diagnostics);
+    let has_tls = tls_callbacks(file, &mut diagnostics);
+    let has_init = exports(file, &mut diagnostics);
+
+    if !has_entry && !has_tls && !has_init {
+        diagnostics.error("has no code to run: no entry point, tls callbacks or Init export");
+    }
+
+    diagnostics.0
+}
+
+struct Diagnostics(Vec<Diagnostic>);
+
+impl Diagnostics {
+    fn error(&mut self, message: impl Into<String>) {
+        self.0.push(Diagnostic {
+            severity: Severity::Error,
+            message: message.into(),
+        });
+    }
+
+    fn warning(&mut self, message: impl Into<String>) {
+        self.0.push(Diagnostic {
+            severity: Severity::Warning,
+            message: message.into(),
+        });
+    }
+}
+
+/// Whether `rva` points into a section the loader will map as executable
+fn is_executable(file: PeFile64<'_>, rva: u32) -> bool {
+    file.section_headers().iter().any(|s| {
+        let size = s.VirtualSize.max(s.SizeOfRawData);
+        (s.VirtualAddress..s.VirtualAddress.saturating_add(size)).contains(&rva)
+            && s.Characteristics & IMAGE_SCN_MEM_EXECUTE != 0
+    })
+}
+
+/// Returns whether the dll has an entry point (DllMain)
+fn entry_point(file: PeFile64<'_>, diagnostics: &mut Diagnostics) -> bool {
+    let entry = file.optional_header().AddressOfEntryPoint;
+
+    // a dll without DllMain is allowed to have none
+    if entry == 0 {
+        return false;
+    }
+
+    if !is_executable(file, entry) {
+        diagnostics.error(format!(
+            "is corrupted; its entry point 0x{entry:x} is not in an executable section"
+        ));
+    }
+
+    true
+}
+
+/// Returns whether the dll has tls callbacks, which run on load like DllMain
+fn tls_callbacks(file: PeFile64<'_>, diagnostics: &mut Diagnostics) -> bool {
+    let tls = match file.tls() {
+        Ok(tls) => tls,
+        Err(PeError::Null) => return false,
+        Err(e) => {
+            diagnostics.error(format!("is corrupted; its tls directory is invalid ({e})"));
+            return false;
+        }
+    };
+
+    match tls.callbacks() {
+        Ok(callbacks) => !callbacks.is_empty(),
+        Err(e) => {
+            diagnostics.error(format!("is corrupted; its tls callbacks are invalid ({e})"));
+            false
+        }
+    }
+}
+
+/// Returns whether the dll exports a usable Init
+fn exports(file: PeFile64<'_>, diagnostics: &mut Diagnostics) -> bool {
+    const NO_INIT: &str = "does not export Init, so it will be loaded but never initialized. If it is meant to be a plugin, it may be built for a different mod loader";
+
+    let exports = match file.exports() {
+        Ok(exports) => exports,
+        Err(PeError::Null) => {
+            diagnostics.warning(NO_INIT);
+            return false;
+        }
+        Err(e) => {
+            diagnostics.error(format!("is corrupted; its export table is invalid ({e})"));
+            return false;
+        }
+    };
+
+    let by = match exports.by() {
+        Ok(by) => by,
+        Err(e) => {
+            diagnostics.error(format!("is corrupted; its export table is invalid ({e})"));
+            return false;
+        }
+    };
+
+    let size = file.optional_header().SizeOfImage;
+    if let Some(rva) = by.functions().iter().find(|&&rva| rva != 0 && rva >= size) {
+        diagnostics.error(format!(
+            "is corrupted; it exports an address 0x{rva:x} outside the image"
+        ));
+        return false;
+    }
+
+    match by.name("Init") {
+        Ok(init) => match init.symbol() {
+            Some(rva) if is_executable(file, rva) => true,
+
+            Some(rva) => {
+                diagnostics.error(format!(
+                    "exports Init at 0x{rva:x}, which is not in an executable section"
+                ));
+                false
+            }
+
+            // GetProcAddress follows forwards
+            None => true,
+        },
+
+        Err(_) => {
+            diagnostics.warning(NO_INIT);
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plugins::fixtures::TestPe;
+
+    fn messages(image: &[u8]) -> Vec<(Severity, String)> {
+        validate(image)
+            .into_iter()
+            .map(|d| (d.severity, d.message))
+            .collect()
+    }
+
+    fn single_error(image: &[u8]) -> String {
+        match &messages(image)[..] {
+            [(Severity::Error, message)] => message.clone(),
+            other => panic!("expected a single error, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn plugin_is_valid() {
+        assert!(messages(&TestPe::plugin().build()).is_empty());
+    }
+
+    #[test]
+    fn not_mz() {
+        let mut image = TestPe::plugin().build();
+        image[..2].copy_from_slice(b"PK");
+
+        assert!(single_error(&image).starts_with("is not a dll"));
+        assert!(single_error(b"").starts_with("is not a dll"));
+    }
+
+    #[test]
+    fn corrupted_headers() {
+        let image = TestPe::plugin().build();
+
+        assert!(single_error(&image[..0x100]).starts_with("is corrupted"));
+    }
+
+    #[test]
+    fn bits32() {
+        let image = TestPe {
+            bits32: true,
+            machine: IMAGE_FILE_MACHINE_I386,
+            ..TestPe::plugin()
+        }
+        .build();
+
+        assert!(single_error(&image).starts_with("is a 32-bit dll"));
+    }
+
+    #[test]
+    fn other_machine() {
+        let image = TestPe {
+            machine: IMAGE_FILE_MACHINE_ARM64,
+            ..TestPe::plugin()
+        }
+        .build();
+
+        assert!(single_error(&image).starts_with("is built for arm64"));
+    }
+
+    #[test]
+    fn exe_not_dll() {
+        let image = TestPe {
+            dll: false,
+            ..TestPe::plugin()
+        }
+        .build();
+
+        assert!(single_error(&image).starts_with("is an executable"));
+    }
+
+    #[test]
+    fn dotnet() {
+        let image = TestPe {
+            dotnet: true,
+            ..TestPe::plugin()
+        }
+        .build();
+
+        assert!(single_error(&image).starts_with("is a .NET assembly"));
+    }
+
+    #[test]
+    fn missing_init_warns() {
+        let image = TestPe {
+            exports: vec!["Other"],
+            ..TestPe::plugin()
+        }
+        .build();
+
+        assert!(matches!(
+            &messages(&image)[..],
+            [(Severity::Warning, m)] if m.starts_with("does not export Init")
+        ));
+
+        // without any export table at all, too
+        let image = TestPe {
+            exports: Vec::new(),
+            ..TestPe::plugin()
+        }
+        .build();
+
+        assert!(matches!(
+            &messages(&image)[..],
+            [(Severity::Warning, m)] if m.starts_with("does not export Init")
+        ));
+    }
+
+    #[test]
+    fn init_not_executable() {
+        let image = TestPe {
+            exports: Vec::new(),
+            data_exports: vec!["Init"],
+            ..TestPe::plugin()
+        }
+        .build();
+
+        assert!(single_error(&image).starts_with("exports Init at 0x2"));
+    }
+
+    #[test]
+    fn tls_callbacks_count_as_code() {
+        let image = TestPe {
+            entry_point: false,
+            exports: Vec::new(),
+            tls_callbacks: true,
+            ..TestPe::plugin()
+        }
+        .build();
+
+        assert!(matches!(
+            &messages(&image)[..],
+            [(Severity::Warning, m)] if m.starts_with("does not export Init")
+        ));
+    }
+
+    #[test]
+    fn no_code() {
+        let image = TestPe {
+            entry_point: false,
+            exports: Vec::new(),
+            ..TestPe::plugin()
+        }
+        .build();
+
+        let messages = messages(&image);
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0].0, Severity::Warning);
+        assert_eq!(
+            messages[1],
+            (
+                Severity::Error,
+                "has no code to run: no entry point, tls callbacks or Init export".to_owned()
+            )
+        );
+    }
+}
@@ -0,0 +1,207 @@
+use std::{collections::BTreeMap, fs, io, path::Path};
+
+use eyre::{Context as _, Result};
+use serde::{Deserialize, Serialize};
+use unicase::UniCase;
+
+use crate::config::write_atomic;
+
+/// Holds the approved plugin hashes, in the plugins dir
+pub const LOCK_NAME: &str = "plugins.lock";
+
+const HEADER: &str =
+    "# Plugins approved to load when [core]trust is enabled, and their SHA-256 hashes
+# Managed by `yabg3nml plugins approve` and `yabg3nml plugins revoke`
+";
+
+/// The plugins approved to load, and the hashes they were approved with
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Lockfile {
+    #[serde(default)]
+    pub plugins: BTreeMap<String, Approval>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Approval {
+    /// The dll approved, relative to the plugins dir, e.g. `FooBar/FooBar.dll`
+    pub path: String,
+    pub sha256: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Trust {
+    Approved,
+    /// Not in the lock file
+    Unapproved,
+    /// The dll is not the one which was approved
+    Changed {
+        approved: String,
+        actual: String,
+    },
+    /// A dll at another path was approved under this name, e.g. before a different dll
+    /// was renamed to it
+    Moved {
+        approved: String,
+        actual: String,
+    },
+}
+
+impl Lockfile {
+    /// Read the lock file from the plugins dir. A missing one approves nothing
+    pub fn load(plugins_dir: &Path) -> Result<Self> {
+        let path = plugins_dir.join(LOCK_NAME);
+
+        let data = match fs::read_to_string(&path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(e).with_context(|| format!("failed to read {}", path.display())),
+        };
+
+        toml::from_str(&data).with_context(|| format!("{} is invalid", path.display()))
+    }
+
+    pub fn save(&self, plugins_dir: &Path) -> Result<()> {
+        let data = format!("{HEADER}\n{}", toml::to_string_pretty(self)?);
+        write_atomic(&plugins_dir.join(LOCK_NAME), &data)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Approval> {
+        self.key(name).map(|k| &self.plugins[k])
+    }
+
+    /// Whether `image`, read from `path` (see [`lock_path`]), is the dll approved for plugin `name`
+    pub fn check(&self, name: &str, path: &str, image: &[u8]) -> Trust {
+        let Some(approval) = self.get(name) else {
+            return Trust::Unapproved;
+        };
+
+        if !same_path(&approval.path, path) {
+            return Trust::Moved {
+                approved: approval.path.clone(),
+                actual: path.to_owned(),
+            };
+        }
+
+        let actual = hash(image);
+        if actual.eq_ignore_ascii_case(&approval.sha256) {
+            Trust::Approved
+        } else {
+            Trust::Changed {
+                approved: approval.sha256.clone(),
+                actual,
+            }
+        }
+    }
+
+    /// Approve `image` for plugin `name`, replacing any earlier approval
+    pub fn approve(&mut self, name: &str, path: String, image: &[u8]) {
+        self.revoke(name);
+        self.plugins.insert(
+            name.to_owned(),
+            Approval {
+                path,
+                sha256: hash(image),
+            },
+        );
+    }
+
+    /// Returns whether the plugin was approved
+    pub fn revoke(&mut self, name: &str) -> bool {
+        match self.key(name).cloned() {
+            Some(key) => self.plugins.remove(&key).is_some(),
+            None => false,
+        }
+    }
+
+    /// Plugin names are case insensitive, like the files they come from
+    fn key(&self, name: &str) -> Option<&String> {
+        self.plugins
+            .keys()
+            .find(|k| UniCase::new(k.as_str()) == UniCase::new(name))
+    }
+}
+
+/// A plugin's path as stored in plugins.lock, e.g. `FooBar/FooBar.dll`. Paths are case
+/// insensitive, so `path` may differ in case from `plugins_dir`
+pub fn lock_path(plugins_dir: &Path, path: &Path) -> String {
+    let mut components = path.components();
+    let inside = plugins_dir.components().all(|dir| {
+        components.next().is_some_and(|c| {
+            UniCase::new(c.as_os_str().to_string_lossy())
+                == UniCase::new(dir.as_os_str().to_string_lossy())
+        })
+    });
+
+    let relative = if inside { components.as_path() } else { path };
+    relative.to_string_lossy().replace('\\', "/")
+}
+
+fn same_path(a: &str, b: &str) -> bool {
+    UniCase::new(a.replace('\\', "/")) == UniCase::new(b.replace('\\', "/"))
+}
+
+/// Lowercase hex SHA-256 of a dll
+pub fn hash(image: &[u8]) -> String {
+    sha256::digest(image)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check() {
+        let mut lockfile = Lockfile::default();
+        assert_eq!(
+            lockfile.check("FooBar", "FooBar/FooBar.dll", b"v1"),
+            Trust::Unapproved
+        );
+
+        lockfile.approve("FooBar", "FooBar/FooBar.dll".to_owned(), b"v1");
+
+        assert_eq!(
+            lockfile.check("foobar", "foobar/FOOBAR.dll", b"v1"),
+            Trust::Approved
+        );
+        assert_eq!(
+            lockfile.check("FooBar", "FooBar/FooBar.dll", b"v2"),
+            Trust::Changed {
+                approved: hash(b"v1"),
+                actual: hash(b"v2"),
+            }
+        );
+        assert_eq!(
+            lockfile.check("FooBar", "FooBar.dll", b"v1"),
+            Trust::Moved {
+                approved: "FooBar/FooBar.dll".to_owned(),
+                actual: "FooBar.dll".to_owned(),
+            }
+        );
+
+        assert!(lockfile.revoke("FOOBAR"));
+        assert_eq!(
+            lockfile.check("FooBar", "FooBar/FooBar.dll", b"v1"),
+            Trust::Unapproved
+        );
+    }
+
+    #[test]
+    fn lock_paths() {
+        let plugins_dir = Path::new("/Game/Plugins");
+
+        assert_eq!(
+            lock_path(plugins_dir, Path::new("/game/plugins/foobar/foobar.dll")),
+            "foobar/foobar.dll"
+        );
+        assert_eq!(
+            lock_path(plugins_dir, Path::new("/Game/Plugins/Baz.dll")),
+            "Baz.dll"
+        );
+        assert_eq!(
+            lock_path(plugins_dir, Path::new("/Other/Baz.dll")),
+            "/Other/Baz.dll"
+        );
+    }
+}
@@ -0,0 +1,335 @@
+use std::{
+    cmp::Ordering,
+    fmt::{self, Display},
+    str::FromStr,
+};
+
+use eyre::{Report, Result, bail, eyre};
+use serde::Deserialize;
+
+/// A dotted version number such as `1.2.3` or `4.1.1.6758295`
+///
+/// Missing parts count as 0, so `1.2` == `1.2.0`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Version(Vec<u64>);
+
+impl Version {
+    pub fn new(parts: impl IntoIterator<Item = u64>) -> Self {
+        Self(parts.into_iter().collect())
+    }
+
+    fn part(&self, idx: usize) -> u64 {
+        self.0.get(idx).copied().unwrap_or(0)
+    }
+}
+
+impl FromStr for Version {
+    type Err = Report;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let parts = s
+            .trim()
+            .split('.')
+            .map(|p| p.parse::<u64>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| {
+                eyre!(
+                    "`{s}` is not a valid version; expected numbers separated by dots, e.g. 1.2.3"
+                )
+            })?;
+
+        Ok(Self(parts))
+    }
+}
+
+impl TryFrom<String> for Version {
+    type Error = Report;
+
+    fn try_from(value: String) -> Result<Self> {
+        value.parse()
+    }
+}
+
+impl Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let parts = self.0.iter().map(ToString::to_string).collect::<Vec<_>>();
+        write!(f, "{}", parts.join("."))
+    }
+}
+
+impl PartialEq for Version {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Version {}
+
+impl PartialOrd for Version {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Version {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let len = self.0.len().max(other.0.len());
+        (0..len)
+            .map(|i| self.part(i).cmp(&other.part(i)))
+            .find(|o| o.is_ne())
+            .unwrap_or(Ordering::Equal)
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Op {
+    Exact,
+    Greater,
+    GreaterEq,
+    Less,
+    LessEq,
+    /// Compatible updates, like cargo. `^1.2` is `>=1.2, <2`, and `^0.2` is `>=0.2, <0.3`
+    Caret,
+}
+
+#[derive(Debug, Clone)]
+struct Comparator {
+    op: Op,
+    version: Version,
+}
+
+impl Comparator {
+    fn matches(&self, version: &Version) -> bool {
+        match self.op {
+            Op::Exact => *version == self.version,
+            Op::Greater => *version > self.version,
+            Op::GreaterEq => *version >= self.version,
+            Op::Less => *version < self.version,
+            Op::LessEq => *version <= self.version,
+            Op::Caret => *version >= self.version && *version < self.caret_bound(),
+        }
+    }
+
+    /// The first version which is no longer compatible
+    fn caret_bound(&self) -> Version {
+        let parts = &self.version.0;
+        let bump = parts
+            .iter()
+            .position(|&p| p != 0)
+            .unwrap_or(parts.len().saturating_sub(1));
+
+        let mut bound = parts[..=bump].to_vec();
+        bound[bump] += 1;
+        Version(bound)
+    }
+}
+
+impl Display for Comparator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let op = match self.op {
+            Op::Exact => "=",
+            Op::Greater => ">",
+            Op::GreaterEq => ">=",
+            Op::Less => "<",
+            Op::LessEq => "<=",
+            Op::Caret => "^",
+        };
+
+        write!(f, "{op}{}", self.version)
+    }
+}
+
+/// A version requirement such as `>=1.2, <2`, made of comma separated comparisons
+/// which must all match
+///
+/// A bare version means `^version`, like in cargo. `*` matches anything
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(try_from = "String")]
+pub struct VersionReq(Vec<Comparator>);
+
+impl VersionReq {
+    pub fn matches(&self, version: &Version) -> bool {
+        self.0.iter().all(|c| c.matches(version))
+    }
+
+    /// Whether this matches every version
+    pub fn is_any(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl FromStr for VersionReq {
+    type Err = Report;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim();
+        if s == "*" {
+            return Ok(Self::default());
+        }
+
+        let mut comparators = Vec::new();
+        for part in s.split(',') {
+            let part = part.trim();
+
+            let (op, version) = [
+                (">=", Op::GreaterEq),
+                ("<=", Op::LessEq),
+                (">", Op::Greater),
+                ("<", Op::Less),
+                ("=", Op::Exact),
+                ("^", Op::Caret),
+            ]
+            .into_iter()
+            .find_map(|(prefix, op)| part.strip_prefix(prefix).map(|v| (op, v)))
+            .unwrap_or((Op::Caret, part));
+
+            if version.trim().is_empty() {
+                bail!("`{s}` is not a valid version requirement; expected e.g. `>=1.2, <2`");
+            }
+
+            comparators.push(Comparator {
+                op,
+                version: version.parse()?,
+            });
+        }
+
+        Ok(Self(comparators))
+    }
+}
+
+impl TryFrom<String> for VersionReq {
+    type Error = Report;
+
+    fn try_from(value: String) -> Result<Self> {
+        value.parse()
+    }
+}
+
+impl Display for VersionReq {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.0.is_empty() {
+            return write!(f, "*");
+        }
+
+        let parts = self.0.iter().map(ToString::to_string).collect::<Vec<_>>();
+        write!(f, "{}", parts.join(", "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn version(s: &str) -> Version {
+        s.parse().unwrap()
+    }
+
+    fn req(s: &str) -> VersionReq {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn parse_version() {
+        let cases = [
+            ("1.2.3", Some("1.2.3")),
+            (" 4.1.1.6758295 ", Some("4.1.1.6758295")),
+            ("7", Some("7")),
+            ("", None),
+            ("1..2", None),
+            ("1.2.x", None),
+            ("v1.2", None),
+            ("-1", None),
+        ];
+
+        for (input, expected) in cases {
+            let parsed = input.parse::<Version>().ok().map(|v| v.to_string());
+            assert_eq!(parsed.as_deref(), expected, "{input:?}");
+        }
+    }
+
+    #[test]
+    fn version_ordering() {
+        let cases = [
+            ("1.2", "1.2.0", Ordering::Equal),
+            ("1", "1.0.0.0", Ordering::Equal),
+            ("1.2.3", "1.10", Ordering::Less),
+            ("2", "1.99.99", Ordering::Greater),
+            ("4.1.1.6758295", "4.1.1", Ordering::Greater),
+            ("0.0.1", "0.1", Ordering::Less),
+        ];
+
+        for (a, b, expected) in cases {
+            assert_eq!(version(a).cmp(&version(b)), expected, "{a} vs {b}");
+            assert_eq!(
+                version(b).cmp(&version(a)),
+                expected.reverse(),
+                "{b} vs {a}"
+            );
+        }
+    }
+
+    #[test]
+    fn parse_req() {
+        let cases = [
+            ("1.2", Some("^1.2")),
+            (">=1.0, <2", Some(">=1.0, <2")),
+            ("  =1.2.3 ", Some("=1.2.3")),
+            ("*", Some("*")),
+            (">= 1.0", Some(">=1.0")),
+            ("", None),
+            (">=", None),
+            ("1.0,", None),
+            ("~1.2", None),
+        ];
+
+        for (input, expected) in cases {
+            let parsed = input.parse::<VersionReq>().ok().map(|r| r.to_string());
+            assert_eq!(parsed.as_deref(), expected, "{input:?}");
+        }
+    }
+
+    #[test]
+    fn req_matches() {
+        let cases = [
+            (">=1.0, <2", "1.0", true),
+            (">=1.0, <2", "1.99.1", true),
+            (">=1.0, <2", "2.0", false),
+            (">1.0", "1.0.0", false),
+            ("<=1.0", "1", true),
+            ("=4.1.1", "4.1.1.0", true),
+            ("=4.1.1", "4.1.1.6758295", false),
+            ("*", "0.0.1", true),
+            ("1.2", "1.2", true),
+            ("1.2", "1.9", true),
+            ("1.2", "1.1.9", false),
+            ("1.2", "2.0", false),
+        ];
+
+        for (r, v, expected) in cases {
+            assert_eq!(req(r).matches(&version(v)), expected, "{r} matching {v}");
+        }
+    }
+
+    #[test]
+    fn caret_zero_versions() {
+        // like cargo, the first non-zero part is the one which may not change
+        let cases = [
+            ("^0.2", "0.2.5", true),
+            ("^0.2", "0.3", false),
+            ("^0.2", "0.1.9", false),
+            ("^0.0.3", "0.0.3", true),
+            ("^0.0.3", "0.0.4", false),
+            ("^0.0", "0.0.9", true),
+            ("^0.0", "0.1", false),
+            ("^0", "0.9.9", true),
+            ("^0", "1.0", false),
+            ("^0.0.0", "0.0.0", true),
+            ("^0.0.0", "0.0.1", false),
+        ];
+
+        for (r, v, expected) in cases {
+            assert_eq!(req(r).matches(&version(v)), expected, "{r} matching {v}");
+        }
+    }
+}
@@ -1,6 +1,7 @@
 use windows::{
     Win32::UI::WindowsAndMessaging::{
-        MB_ICONERROR, MB_ICONINFORMATION, MB_ICONWARNING, MESSAGEBOX_STYLE, MessageBoxW,
+        IDYES, MB_ICONERROR, MB_ICONINFORMATION, MB_ICONQUESTION, MB_ICONWARNING, MB_YESNO,
+        MESSAGEBOX_STYLE, MessageBoxW,
     },
     core::{HSTRING, PCWSTR},
 };
@@ -50,3 +51,16 @@ pub fn fatal_popup<T: AsRef<str>, M: AsRef<str>>(title: T, message: M) -> ! {
 pub fn warn_popup<T: AsRef<str>, M: AsRef<str>>(title: T, message: M) {
     display_popup(title, message, MessageBoxIcon::Warn);
 }
+
+/// A question popup with yes and no buttons. Returns whether yes was clicked
+pub fn yes_no_popup<T: AsRef<str>, M: AsRef<str>>(title: T, message: M) -> bool {
+    let h_title = HSTRING::from(title.as_ref());
+    let h_message = HSTRING::from(message.as_ref());
+
+    let title = PCWSTR::from_raw(h_title.as_ptr());
+    let message = PCWSTR::from_raw(h_message.as_ptr());
+
+    let result = unsafe { MessageBoxW(None, message, title, MB_YESNO | MB_ICONQUESTION) };
+
+    result == IDYES
+}
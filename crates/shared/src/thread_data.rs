@@ -1,4 +1,6 @@
-use crate::pipe::commands::Level;
+use std::slice;
+
+use crate::{config::Config, pipe::commands::Level};
 
 #[repr(C)]
 #[derive(Copy, Clone, Debug)]
@@ -7,6 +9,21 @@ pub struct ThreadData {
     pub auth: u64,
     // log data
     pub log: LogData,
+    /// the host's current config, serialized as json
+    pub config: Buffer,
+    /// inject the loader, but only report which plugins would load
+    pub safe_mode: bool,
+}
+
+impl ThreadData {
+    /// Deserialize the config the host sent along
+    ///
+    /// # Safety
+    /// `config` must point to a valid buffer in this process
+    pub unsafe fn config(&self) -> serde_json::Result<Config> {
+        let data = unsafe { self.config.as_slice() };
+        serde_json::from_slice(data)
+    }
 }
 
 #[repr(C)]
@@ -17,3 +34,19 @@ pub struct LogData {
     /// whether to enable targets
     pub target: bool,
 }
+
+/// A byte buffer written into the target process
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct Buffer {
+    pub ptr: *const u8,
+    pub len: usize,
+}
+
+impl Buffer {
+    /// # Safety
+    /// ptr and len must describe a valid allocation in this process
+    pub unsafe fn as_slice(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(self.ptr, self.len) }
+    }
+}
@@ -0,0 +1,293 @@
+//! Safe access to the services YABG3NML's loader.dll offers plugins
+//!
+//! ```no_run
+//! use yabg3nml_api::{Api, Level};
+//!
+//! #[unsafe(no_mangle)]
+//! extern "C" fn Init() {
+//!     let Some(api) = Api::get() else {
+//!         // not loaded by YABG3NML, or it's too old
+//!         return;
+//!     };
+//!
+//!     api.log(Level::Info, &format!("running on loader v{}", api.loader_version()));
+//!
+//!     if let Some(_dir) = api.data_dir() {
+//!         // keep files in it
+//!     }
+//!
+//!     api.on_shutdown(|| {
+//!         // flush files
+//!     });
+//!
+//!     // SAFETY: the overlay plugin publishes an Overlay as version 1 of "overlay"
+//!     if let Some(overlay) = unsafe { api.wait_service::<Overlay>("overlay", 1, None) } {
+//!         (overlay.add_window)(c"My plugin".as_ptr());
+//!     }
+//! }
+//!
+//! #[repr(C)]
+//! struct Overlay {
+//!     add_window: extern "C" fn(title: *const std::ffi::c_char),
+//! }
+//! ```
+//!
+//! See [`sys`] for the C ABI underneath, for plugins written in other languages
+
+pub mod sys;
+
+use std::{
+    ffi::c_void,
+    mem,
+    panic::{self, AssertUnwindSafe},
+    path::PathBuf,
+    time::Duration,
+};
+
+use windows::{
+    Win32::{
+        Foundation::HMODULE,
+        System::LibraryLoader::{
+            GET_MODULE_HANDLE_EX_FLAG_FROM_ADDRESS, GET_MODULE_HANDLE_EX_FLAG_UNCHANGED_REFCOUNT,
+            GetModuleHandleExW, GetModuleHandleW, GetProcAddress,
+        },
+    },
+    core::{PCSTR, PCWSTR, w},
+};
+
+use sys::{API_VERSION, Str, WAIT_FOREVER};
+
+/// The loader's api, for the plugin this crate is linked into
+#[derive(Copy, Clone)]
+pub struct Api {
+    raw: &'static sys::Api,
+    /// The plugin's module handle
+    module: usize,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Level {
+    Trace,
+    Debug,
+    Info,
+    Warn,
+    Error,
+}
+
+/// A plugin the loader loaded
+#[derive(Debug, Clone)]
+pub struct PluginInfo {
+    /// The dll's filename without extension, as used in config.toml
+    pub name: String,
+    /// e.g. `FooBar by Cherry v1.0.0 (FooBar/FooBar.dll)`
+    pub display: String,
+    /// The id from its plugin.toml, if any
+    pub id: Option<String>,
+    /// From its plugin.toml or plugin data, if any
+    pub version: Option<String>,
+}
+
+impl Api {
+    /// The api, or None if this plugin wasn't loaded by YABG3NML or the loader is too old
+    pub fn get() -> Option<Self> {
+        // SAFETY: Standard function, and the string is null terminated
+        let loader = unsafe { GetModuleHandleW(w!("loader.dll")) }.ok()?;
+
+        // SAFETY: Standard function, and the name is null terminated
+        let get_api = unsafe { GetProcAddress(loader, PCSTR(sys::GET_API.as_ptr().cast())) }?;
+
+        type FarProc = unsafe extern "system" fn() -> isize;
+
+        // SAFETY: the loader declares it as sys::GetApi
+        let get_api = unsafe { mem::transmute::<FarProc, sys::GetApi>(get_api) };
+
+        // SAFETY: declared by the loader with this signature
+        let raw = unsafe { get_api(API_VERSION) };
+        // SAFETY: the loader returns null or a table which is never freed
+        let raw = unsafe { raw.as_ref() }?;
+
+        Some(Self {
+            raw,
+            module: this_module() as usize,
+        })
+    }
+
+    /// The version of the api table the loader handed out
+    pub fn version(&self) -> u32 {
+        self.raw.version
+    }
+
+    /// The loader's version, e.g. `0.3.2`
+    pub fn loader_version(&self) -> &'static str {
+        // SAFETY: the loader's version is a static string
+        let bytes = unsafe { self.raw.loader_version.as_bytes() };
+        bytes
+            .and_then(|b| std::str::from_utf8(b).ok())
+            .unwrap_or_default()
+    }
+
+    /// Log a message into the YABG3NML log, under this plugin's name
+    pub fn log(&self, level: Level, message: &str) {
+        let level = match level {
+            Level::Trace => sys::LEVEL_TRACE,
+            Level::Debug => sys::LEVEL_DEBUG,
+            Level::Info => sys::LEVEL_INFO,
+            Level::Warn => sys::LEVEL_WARN,
+            Level::Error => sys::LEVEL_ERROR,
+        };
+
+        // SAFETY: message outlives the call
+        unsafe { (self.raw.log)(self.module(), level, Str::new(message)) };
+    }
+
+    /// Every loaded plugin, in the order they were loaded
+    pub fn plugins(&self) -> Vec<PluginInfo> {
+        unsafe extern "C" fn push(user: *mut c_void, plugin: *const sys::PluginInfo) {
+            // SAFETY: user is the vec below
+            let plugins = unsafe { &mut *user.cast::<Vec<PluginInfo>>() };
+            // SAFETY: the loader passes a valid plugin
+            let plugin = unsafe { &*plugin };
+
+            // SAFETY: the strings are valid during this call
+            let string =
+                |s: Str| unsafe { s.as_bytes() }.map(|b| String::from_utf8_lossy(b).into_owned());
+
+            plugins.push(PluginInfo {
+                name: string(plugin.name).unwrap_or_default(),
+                display: string(plugin.display).unwrap_or_default(),
+                id: string(plugin.id),
+                version: string(plugin.version),
+            });
+        }
+
+        let mut plugins = Vec::new();
+        // SAFETY: push matches PluginCallback, and plugins outlives the call
+        unsafe { (self.raw.plugins)((&raw mut plugins).cast(), push) };
+
+        plugins
+    }
+
+    /// The path of this plugin's own config file. Its folder exists, but the file may not
+    pub fn config_path(&self) -> Option<PathBuf> {
+        self.path(self.raw.config_path)
+    }
+
+    /// A folder this plugin may keep its data in
+    pub fn data_dir(&self) -> Option<PathBuf> {
+        self.path(self.raw.data_dir)
+    }
+
+    /// Run `f` when the game exits, or before this plugin is unloaded by hot reload.
+    /// It runs while the process is exiting, so it must be quick and must not wait on other
+    /// threads. Returns false if the loader doesn't know this plugin
+    pub fn on_shutdown(&self, f: impl FnOnce() + Send + 'static) -> bool {
+        type Callback = Box<dyn FnOnce() + Send>;
+
+        unsafe extern "C" fn call(user: *mut c_void) {
+            // SAFETY: user is the box leaked below, and the loader calls this at most once
+            let f = unsafe { Box::from_raw(user.cast::<Callback>()) };
+
+            // unwinding into the loader is undefined behavior
+            _ = panic::catch_unwind(AssertUnwindSafe(f));
+        }
+
+        let user = Box::into_raw(Box::new(Box::new(f) as Callback));
+
+        // SAFETY: call matches ShutdownCallback, and takes ownership of user
+        let registered = unsafe { (self.raw.on_shutdown)(self.module(), user.cast(), call) };
+
+        if !registered {
+            // SAFETY: the loader didn't take it, so it's still ours
+            drop(unsafe { Box::from_raw(user) });
+        }
+
+        registered
+    }
+
+    /// Publish `interface` as version `version` of the service `name`, for other plugins to
+    /// look up. Bump the version on every incompatible change to `T`; several versions may be
+    /// published side by side. It is withdrawn when this plugin is unloaded.
+    /// Returns false if another plugin already published this version
+    ///
+    /// Other plugins may be written in other languages, so `T` should be `repr(C)`
+    pub fn publish<T: Sync>(&self, name: &str, version: u32, interface: &'static T) -> bool {
+        let interface = (interface as *const T).cast();
+
+        // SAFETY: name outlives the call, and interface is valid for as long as this plugin is
+        unsafe { (self.raw.publish_service)(self.module(), Str::new(name), version, interface) }
+    }
+
+    /// The interface another plugin published as version `version` of `name`, if any
+    ///
+    /// # Safety
+    /// `T` must be the type published under this name and version. The reference dangles
+    /// once the plugin which published it is unloaded by hot reload
+    pub unsafe fn service<T: Sync>(&self, name: &str, version: u32) -> Option<&'static T> {
+        // SAFETY: name outlives the call
+        let interface = unsafe { (self.raw.get_service)(Str::new(name), version) };
+        // SAFETY: upheld by the caller
+        unsafe { interface.cast::<T>().as_ref() }
+    }
+
+    /// Like [`Api::service`], but wait up to `timeout`, or forever if None, for it to be
+    /// published. From this plugin's `Init`, it gives up as soon as no plugin which loads
+    /// before this one may still publish it; list the provider in `after` to wait for it
+    ///
+    /// # Safety
+    /// Same as [`Api::service`]
+    pub unsafe fn wait_service<T: Sync>(
+        &self,
+        name: &str,
+        version: u32,
+        timeout: Option<Duration>,
+    ) -> Option<&'static T> {
+        let timeout = timeout.map_or(WAIT_FOREVER, |t| {
+            u32::try_from(t.as_millis()).map_or(WAIT_FOREVER - 1, |ms| ms.min(WAIT_FOREVER - 1))
+        });
+
+        // SAFETY: name outlives the call
+        let interface =
+            unsafe { (self.raw.wait_service)(self.module(), Str::new(name), version, timeout) };
+        // SAFETY: upheld by the caller
+        unsafe { interface.cast::<T>().as_ref() }
+    }
+
+    fn module(&self) -> *mut c_void {
+        self.module as *mut c_void
+    }
+
+    fn path(
+        &self,
+        f: unsafe extern "C" fn(*mut c_void, *mut c_void, sys::StrCallback) -> bool,
+    ) -> Option<PathBuf> {
+        unsafe extern "C" fn set(user: *mut c_void, value: Str) {
+            // SAFETY: user is the option below
+            let path = unsafe { &mut *user.cast::<Option<PathBuf>>() };
+            // SAFETY: the string is valid during this call
+            let value = unsafe { value.as_bytes() };
+            *path = value.map(|b| String::from_utf8_lossy(b).into_owned().into());
+        }
+
+        let mut path = None::<PathBuf>;
+        // SAFETY: set matches StrCallback, and path outlives the call
+        unsafe { f(self.module(), (&raw mut path).cast(), set) };
+
+        path
+    }
+}
+
+/// The module handle of the dll this crate is linked into, which is the plugin's
+fn this_module() -> *mut c_void {
+    let mut module = HMODULE::default();
+
+    // SAFETY: Standard function. Any address inside this crate is inside the plugin's dll
+    _ = unsafe {
+        GetModuleHandleExW(
+            GET_MODULE_HANDLE_EX_FLAG_FROM_ADDRESS | GET_MODULE_HANDLE_EX_FLAG_UNCHANGED_REFCOUNT,
+            PCWSTR(this_module as *const () as *const u16),
+            &mut module,
+        )
+    };
+
+    module.0
+}
@@ -0,0 +1,145 @@
+//! The raw C ABI of the api. Everything here is `repr(C)`, so plugins written in any
+//! language can use it
+//!
+//! Get the table by calling the [`GET_API`] export of loader.dll with [`API_VERSION`].
+//! Functions taking `plugin` want the calling plugin's own module handle, which is how the
+//! loader tells plugins apart. They work from the plugin's `Init` on, not from its DllMain
+
+use std::{
+    ffi::{CStr, c_void},
+    slice,
+};
+
+/// The newest api version. Fields are only ever appended to [`Api`], so a loader
+/// supporting a newer version hands out a table which is also valid for older ones
+pub const API_VERSION: u32 = 2;
+
+/// The name of the function loader.dll exports to get the [`Api`]
+pub const GET_API: &CStr = c"YabgGetApi";
+
+/// `YabgGetApi(version) -> *const Api`. Returns null if the loader is older than `version`
+pub type GetApi = unsafe extern "C" fn(version: u32) -> *const Api;
+
+/// Called with a string only valid during the call
+pub type StrCallback = unsafe extern "C" fn(user: *mut c_void, value: Str);
+/// Called with a plugin only valid during the call
+pub type PluginCallback = unsafe extern "C" fn(user: *mut c_void, plugin: *const PluginInfo);
+pub type ShutdownCallback = unsafe extern "C" fn(user: *mut c_void);
+
+/// A `timeout_ms` which never runs out
+pub const WAIT_FOREVER: u32 = u32::MAX;
+
+pub const LEVEL_TRACE: u32 = 0;
+pub const LEVEL_DEBUG: u32 = 1;
+pub const LEVEL_INFO: u32 = 2;
+pub const LEVEL_WARN: u32 = 3;
+pub const LEVEL_ERROR: u32 = 4;
+
+/// A borrowed utf-8 string, not null terminated. `ptr` is null for a missing value
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct Str {
+    pub ptr: *const u8,
+    pub len: usize,
+}
+
+impl Str {
+    pub const NONE: Self = Self {
+        ptr: std::ptr::null(),
+        len: 0,
+    };
+
+    pub const fn new(value: &str) -> Self {
+        Self {
+            ptr: value.as_ptr(),
+            len: value.len(),
+        }
+    }
+
+    /// # Safety
+    /// `ptr` must be null, or point to `len` bytes which stay valid for `'a`
+    pub unsafe fn as_bytes<'a>(&self) -> Option<&'a [u8]> {
+        if self.ptr.is_null() {
+            return None;
+        }
+
+        // SAFETY: upheld by the caller
+        Some(unsafe { slice::from_raw_parts(self.ptr, self.len) })
+    }
+}
+
+impl From<Option<&str>> for Str {
+    fn from(value: Option<&str>) -> Self {
+        value.map(Self::new).unwrap_or(Self::NONE)
+    }
+}
+
+/// A plugin the loader loaded
+#[repr(C)]
+#[derive(Debug)]
+pub struct PluginInfo {
+    /// The dll's filename without extension, as used in config.toml
+    pub name: Str,
+    /// e.g. `FooBar by Cherry v1.0.0 (FooBar/FooBar.dll)`
+    pub display: Str,
+    /// The id from its plugin.toml, if any
+    pub id: Str,
+    /// From its plugin.toml or plugin data, if any
+    pub version: Str,
+}
+
+/// The function table. Never freed, so it may be kept for the life of the process
+#[repr(C)]
+pub struct Api {
+    /// The version of this table, at least the version asked for
+    pub version: u32,
+    /// The loader's version, e.g. `0.3.2`
+    pub loader_version: Str,
+    /// Log a message into the YABG3NML log, attributed to `plugin`. `level` is one of the
+    /// `LEVEL_` constants
+    pub log: unsafe extern "C" fn(plugin: *mut c_void, level: u32, message: Str),
+    /// Call `callback` for each loaded plugin, in the order they were loaded
+    pub plugins: unsafe extern "C" fn(user: *mut c_void, callback: PluginCallback),
+    /// Call `callback` with the path of the plugin's own config file. Its folder exists,
+    /// but the file may not. Returns false if `plugin` isn't a loaded plugin
+    pub config_path:
+        unsafe extern "C" fn(plugin: *mut c_void, user: *mut c_void, callback: StrCallback) -> bool,
+    /// Call `callback` with the path of a folder the plugin may keep its data in. It is
+    /// created if needed. Returns false if `plugin` isn't a loaded plugin
+    pub data_dir:
+        unsafe extern "C" fn(plugin: *mut c_void, user: *mut c_void, callback: StrCallback) -> bool,
+    /// Call `callback` with `user` when the game exits, or before the plugin is unloaded
+    /// by hot reload. Callbacks run in reverse order of registration, while the process is
+    /// exiting, so they must be quick and must not wait on other threads.
+    /// Returns false if `plugin` isn't a loaded plugin
+    pub on_shutdown: unsafe extern "C" fn(
+        plugin: *mut c_void,
+        user: *mut c_void,
+        callback: ShutdownCallback,
+    ) -> bool,
+    /// Since version 2. Publish `interface` as version `version` of the service `name` for
+    /// other plugins. Bump the version on every incompatible change; several versions may be
+    /// published side by side. `interface` must stay valid until the plugin is unloaded, when
+    /// it is withdrawn. Returns false if `plugin` isn't a loaded plugin, or another plugin
+    /// already published this version
+    pub publish_service: unsafe extern "C" fn(
+        plugin: *mut c_void,
+        name: Str,
+        version: u32,
+        interface: *const c_void,
+    ) -> bool,
+    /// Since version 2. The interface published as version `version` of `name`, or null
+    pub get_service: unsafe extern "C" fn(name: Str, version: u32) -> *const c_void,
+    /// Since version 2. Like `get_service`, but wait up to `timeout_ms` for it to be published,
+    /// or forever with [`WAIT_FOREVER`]. Called from the plugin's Init, it returns null as soon
+    /// as no plugin which loads before it may still publish it, instead of deadlocking
+    pub wait_service: unsafe extern "C" fn(
+        plugin: *mut c_void,
+        name: Str,
+        version: u32,
+        timeout_ms: u32,
+    ) -> *const c_void,
+}
+
+// SAFETY: the table is immutable, and loader_version points to a static string
+unsafe impl Sync for Api {}
@@ -17,6 +17,7 @@ use windows::Win32::System::{
 };
 
 use crate::{
+    bisect, crashes,
     event::Event,
     loader::run_loader,
     paths::{Bg3Exe, get_game_binary_for},
@@ -48,6 +49,13 @@ pub fn autostart() -> Result<ExitCode> {
         bg3_exe
     };
 
+    // ask before the game starts, so plugins aren't held up by the popup
+    let mut config = init.config.clone();
+    crashes::offer_quarantine(&mut config);
+    if let Err(e) = bisect::apply(&mut config) {
+        error!("failed to apply bisect selection: {e:#}");
+    }
+
     let exe: Bg3Exe = Path::new(&bg3_exe).into();
     let Some(bg3_path) = get_game_binary_for(exe, init.config) else {
         // it's not a bg3 executable; or at least, it's not named correctly
@@ -60,13 +68,14 @@ pub fn autostart() -> Result<ExitCode> {
     };
 
     trace!(mode = %exe, ?args, "launching bg3");
-    trace!(env = ?env::vars());
+    // env::vars panics on a non-unicode variable, and the game should get those too
+    trace!(env = ?env::vars_os().collect::<Vec<_>>());
 
     let cmd = Command::new(bg3_path)
         .args(args)
         // bypass IFEO on this launch
         .creation_flags(DEBUG_PROCESS.0 | DEBUG_ONLY_THIS_PROCESS.0)
-        .envs(env::vars())
+        .envs(env::vars_os())
         .spawn();
 
     let mut child = match cmd {
@@ -88,7 +97,8 @@ pub fn autostart() -> Result<ExitCode> {
         );
     }
 
-    let res = run_loader(init.config, pid, &init.loader, false, true);
+    // the args belong to the game, so safe mode can only be turned on by its sentinel file here
+    let res = run_loader(&config, pid, &init.loader, false, true);
     if let Err(e) = res {
         error!(err = %e, "run_loader failed");
         fatal_popup(
@@ -104,6 +114,10 @@ pub fn autostart() -> Result<ExitCode> {
         Ok(status) => {
             trace!(code = status.code(), "original child exit code");
 
+            if let Some(code) = status.code() {
+                crashes::record_exit(pid, code as u32);
+            }
+
             let code = status
                 .code()
                 .map(|c| ExitCode::from_raw(c as u32))
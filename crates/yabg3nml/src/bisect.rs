@@ -0,0 +1,448 @@
+//! Finding which plugin causes a problem by loading half of the suspects each launch
+//!
+//! Each launch loads [`Bisect::testing`], the first half of the suspects. Marking the
+//! run bad keeps that half as the suspects, marking it good keeps the other half,
+//! until one plugin is left
+//!
+//! A plugin can't load without the plugins it requires, so suspects are ordered after what
+//! they require, and the plugins being tested are loaded together with their requirements
+
+use std::{
+    collections::BTreeMap,
+    fmt::{self, Display},
+    fs, io, mem,
+    path::PathBuf,
+};
+
+use eyre::{Context as _, Result, bail};
+use serde::{Deserialize, Serialize};
+use shared::{
+    config::{Config, write_atomic},
+    paths::get_bg3_plugins_dir,
+    plugins::{Manifest, PluginFile, find_plugins},
+};
+use tracing::info;
+use unicase::UniCase;
+
+/// Holds the bisect state between launches, in the plugins dir
+const STATE_NAME: &str = "bisect.toml";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Bisect {
+    /// Plugins which may still be the culprit
+    pub suspects: Vec<String>,
+    /// The plugins loaded on each launch until the run is marked
+    pub testing: Vec<String>,
+    /// The plugins each plugin requires, from its [plugins.<name>]requires and its manifest's
+    /// dependencies, as of when bisecting started
+    #[serde(default)]
+    pub requires: BTreeMap<String, Vec<String>>,
+    /// How many runs were marked so far
+    pub step: u32,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Verdict {
+    /// The problem did not happen
+    Good,
+    /// The problem happened
+    Bad,
+}
+
+#[derive(Debug)]
+pub enum Progress {
+    Narrowed(Bisect),
+    /// Only one suspect is left. It is `verified` if it was loaded in a bad run on its own;
+    /// otherwise it is only what was left after every other plugin was cleared
+    Found {
+        culprit: String,
+        verified: bool,
+    },
+}
+
+impl Bisect {
+    fn new(suspects: Vec<String>, requires: BTreeMap<String, Vec<String>>, step: u32) -> Self {
+        let mut bisect = Self {
+            suspects,
+            testing: Vec::new(),
+            requires,
+            step,
+        };
+
+        // every suspect comes after the suspects it requires, so the first half never
+        // needs a plugin from the second half
+        let mut left = mem::take(&mut bisect.suspects);
+        while !left.is_empty() {
+            let idx = left
+                .iter()
+                .position(|s| {
+                    let needs = bisect.needs(s);
+                    !left.iter().any(|l| l != s && contains(&needs, l))
+                })
+                // a cycle; none of them can load anyway
+                .unwrap_or(0);
+
+            bisect.suspects.push(left.remove(idx));
+        }
+
+        let half = bisect.suspects.len().div_ceil(2);
+        bisect.testing = bisect.suspects[..half].to_vec();
+
+        bisect
+    }
+
+    /// How many more runs it takes at most to find the culprit
+    pub fn remaining(&self) -> u32 {
+        self.suspects.len().next_power_of_two().trailing_zeros()
+    }
+
+    /// The plugins loaded on each launch: the ones being tested, and every plugin they require
+    pub fn loads(&self) -> Vec<String> {
+        let mut loads = self.testing.clone();
+        for name in &self.testing {
+            for need in self.needs(name) {
+                if !contains(&loads, &need) {
+                    loads.push(need);
+                }
+            }
+        }
+
+        loads
+    }
+
+    /// Every plugin `name` requires, including what those require
+    fn needs(&self, name: &str) -> Vec<String> {
+        let mut needs = Vec::<String>::new();
+        let mut next = vec![name.to_owned()];
+
+        while let Some(name) = next.pop() {
+            let requires = self
+                .requires
+                .iter()
+                .find(|(n, _)| UniCase::new(n.as_str()) == UniCase::new(name.as_str()))
+                .map(|(_, r)| r.as_slice())
+                .unwrap_or_default();
+
+            for r in requires {
+                if !contains(&needs, r) && UniCase::new(r.as_str()) != UniCase::new(name.as_str()) {
+                    needs.push(r.clone());
+                    next.push(r.clone());
+                }
+            }
+        }
+
+        needs
+    }
+
+    /// Narrow down the suspects by how the run went
+    fn narrow(self, verdict: Verdict) -> Progress {
+        let (suspects, rest) = {
+            let (testing, rest) = self
+                .suspects
+                .into_iter()
+                .partition::<Vec<_>, _>(|s| contains(&self.testing, s));
+
+            match verdict {
+                Verdict::Bad => (testing, rest),
+                Verdict::Good => (rest, testing),
+            }
+        };
+
+        info!(?verdict, ?suspects, cleared = ?rest, "marked bisect run");
+
+        if let [culprit] = &suspects[..] {
+            return Progress::Found {
+                culprit: culprit.clone(),
+                verified: verdict == Verdict::Bad,
+            };
+        }
+
+        Progress::Narrowed(Self::new(suspects, self.requires, self.step + 1))
+    }
+
+    fn path() -> Result<PathBuf> {
+        Ok(get_bg3_plugins_dir()?.join(STATE_NAME))
+    }
+
+    fn save(&self) -> Result<()> {
+        let data = format!(
+            "# Plugin bisection in progress. Managed by `yabg3nml bisect` and the tray menu\n\n{}",
+            toml::to_string_pretty(self)?
+        );
+
+        write_atomic(&Self::path()?, &data)
+    }
+}
+
+impl Display for Bisect {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Step {}: {} suspects left, at most {} more launches",
+            self.step + 1,
+            self.suspects.len(),
+            self.remaining()
+        )?;
+        writeln!(f, "Suspects: {}", self.suspects.join(", "))?;
+        write!(f, "The next launch loads: {}", self.testing.join(", "))?;
+
+        let required = self
+            .loads()
+            .into_iter()
+            .filter(|l| !contains(&self.testing, l))
+            .collect::<Vec<_>>();
+
+        if !required.is_empty() {
+            write!(f, ", and {} since they are required", required.join(", "))?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Display for Progress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Narrowed(bisect) => write!(
+                f,
+                "{bisect}\n\nLaunch the game, then mark whether the problem happened"
+            ),
+
+            Self::Found {
+                culprit,
+                verified: true,
+            } => write!(
+                f,
+                "Found it: {culprit} causes the problem. Plugins load normally again"
+            ),
+
+            Self::Found {
+                culprit,
+                verified: false,
+            } => write!(
+                f,
+                "{culprit} is the only plugin left, but it was never tested on its own; launch with only it enabled to be sure. Plugins load normally again"
+            ),
+        }
+    }
+}
+
+/// The bisection in progress, if any
+pub fn load() -> Result<Option<Bisect>> {
+    let path = Bisect::path()?;
+
+    let data = match fs::read_to_string(&path) {
+        Ok(data) => data,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e).with_context(|| format!("failed to read {}", path.display())),
+    };
+
+    let bisect = toml::from_str(&data).with_context(|| format!("{} is invalid", path.display()))?;
+    Ok(Some(bisect))
+}
+
+/// Start bisecting every plugin which `config` would load
+pub fn start(config: &Config) -> Result<Bisect> {
+    let plugins = find_plugins(&get_bg3_plugins_dir()?)?;
+    let requires = requirements(config, &plugins);
+
+    let suspects = plugins
+        .into_iter()
+        .map(|p| p.name)
+        .filter(|name| !config.is_plugin_disabled(name))
+        .collect::<Vec<_>>();
+
+    if suspects.len() < 2 {
+        bail!(
+            "there must be at least 2 enabled plugins to bisect, but there are {}",
+            suspects.len()
+        );
+    }
+
+    let bisect = Bisect::new(suspects, requires, 0);
+    bisect.save()?;
+
+    info!(suspects = ?bisect.suspects, "started bisecting");
+
+    Ok(bisect)
+}
+
+/// Narrow down the suspects by how the last run went
+pub fn mark(verdict: Verdict) -> Result<Progress> {
+    let Some(bisect) = load()? else {
+        bail!("no bisection is in progress; start one first");
+    };
+
+    let progress = bisect.narrow(verdict);
+    match &progress {
+        Progress::Narrowed(bisect) => bisect.save()?,
+        Progress::Found { .. } => _ = reset()?,
+    }
+
+    Ok(progress)
+}
+
+/// Stop bisecting. Returns whether a bisection was in progress
+pub fn reset() -> Result<bool> {
+    match fs::remove_file(Bisect::path()?) {
+        Ok(()) => Ok(true),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Disable every plugin which isn't being tested this launch, through the same rules as
+/// [core]disabled_plugins
+pub fn apply(config: &mut Config) -> Result<()> {
+    let Some(bisect) = load()? else {
+        return Ok(());
+    };
+
+    let loads = bisect.loads();
+    let disabled = find_plugins(&get_bg3_plugins_dir()?)?
+        .into_iter()
+        .map(|p| p.name)
+        .filter(|name| !contains(&loads, name))
+        .collect::<Vec<_>>();
+
+    info!(
+        "Bisecting: loading {} of {} suspects ({})",
+        bisect.testing.len(),
+        bisect.suspects.len(),
+        bisect.testing.join(", ")
+    );
+
+    for name in &disabled {
+        config.disable_plugin(name);
+    }
+
+    Ok(())
+}
+
+/// The plugins each plugin requires by name. Requirements may name a plugin or its manifest id
+fn requirements(config: &Config, plugins: &[PluginFile]) -> BTreeMap<String, Vec<String>> {
+    let manifests = plugins
+        .iter()
+        .map(|p| {
+            // an invalid manifest stops the plugin from loading anyway
+            p.manifest
+                .as_deref()
+                .and_then(|m| Manifest::load(m).ok())
+                .unwrap_or_default()
+        })
+        .collect::<Vec<_>>();
+
+    let resolve = |r: &str| {
+        plugins
+            .iter()
+            .zip(&manifests)
+            .find(|(p, m)| {
+                UniCase::new(p.name.as_str()) == UniCase::new(r)
+                    || m.id
+                        .as_deref()
+                        .is_some_and(|id| UniCase::new(id) == UniCase::new(r))
+            })
+            .map(|(p, _)| p.name.clone())
+    };
+
+    plugins
+        .iter()
+        .zip(&manifests)
+        .filter_map(|(p, m)| {
+            let requires = config
+                .plugin(&p.name)
+                .map(|c| c.requires.as_slice())
+                .unwrap_or_default()
+                .iter()
+                .chain(m.dependencies.keys())
+                .filter_map(|r| resolve(r))
+                .collect::<Vec<_>>();
+
+            (!requires.is_empty()).then(|| (p.name.clone(), requires))
+        })
+        .collect()
+}
+
+fn contains(list: &[String], name: &str) -> bool {
+    list.iter()
+        .any(|l| UniCase::new(l.as_str()) == UniCase::new(name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|&n| n.to_owned()).collect()
+    }
+
+    fn narrowed(progress: Progress) -> Bisect {
+        match progress {
+            Progress::Narrowed(bisect) => bisect,
+            Progress::Found { culprit, .. } => panic!("found {culprit} too early"),
+        }
+    }
+
+    #[test]
+    fn remaining() {
+        for (suspects, remaining) in [(1, 0), (2, 1), (3, 2), (4, 2), (5, 3), (16, 4)] {
+            let suspects = (0..suspects).map(|i| format!("p{i}")).collect();
+            let bisect = Bisect::new(suspects, BTreeMap::new(), 0);
+
+            assert_eq!(bisect.remaining(), remaining, "{}", bisect.suspects.len());
+        }
+    }
+
+    #[test]
+    fn mark_halves_the_suspects() {
+        let bisect = Bisect::new(names(&["a", "b", "c", "d", "e"]), BTreeMap::new(), 0);
+        assert_eq!(bisect.testing, ["a", "b", "c"]);
+
+        let bisect = narrowed(bisect.narrow(Verdict::Good));
+        assert_eq!(bisect.suspects, ["d", "e"]);
+        assert_eq!(bisect.testing, ["d"]);
+        assert_eq!(bisect.step, 1);
+
+        match bisect.narrow(Verdict::Bad) {
+            Progress::Found { culprit, verified } => {
+                assert_eq!(culprit, "d");
+                assert!(verified);
+            }
+            Progress::Narrowed(_) => panic!("not found"),
+        }
+    }
+
+    #[test]
+    fn culprit_left_over_is_not_verified() {
+        let bisect = Bisect::new(names(&["a", "b"]), BTreeMap::new(), 0);
+
+        assert!(matches!(
+            bisect.narrow(Verdict::Good),
+            Progress::Found { culprit, verified: false } if culprit == "b"
+        ));
+    }
+
+    #[test]
+    fn requirements_are_tested_first_and_loaded_along() {
+        let requires = BTreeMap::from([
+            ("a".to_owned(), names(&["Lib"])),
+            ("b".to_owned(), names(&["lib"])),
+            ("c".to_owned(), names(&["b"])),
+        ]);
+
+        let bisect = Bisect::new(names(&["c", "b", "a", "Lib"]), requires, 0);
+        assert_eq!(bisect.suspects, ["Lib", "b", "c", "a"]);
+        assert_eq!(bisect.testing, ["Lib", "b"]);
+        assert_eq!(bisect.loads(), ["Lib", "b"]);
+
+        // Lib and b are cleared, but still needed to load c
+        let bisect = narrowed(bisect.narrow(Verdict::Good));
+        assert_eq!(bisect.testing, ["c"]);
+        assert_eq!(bisect.loads(), ["c", "b", "lib"]);
+        assert!(
+            bisect
+                .to_string()
+                .ends_with("The next launch loads: c, and b, lib since they are required")
+        );
+    }
+}
@@ -1,11 +1,208 @@
-use argh::FromArgs;
+use std::{env, path::Path, process};
+
+use argh::{EarlyExit, FromArgs};
+use shared::popup::fatal_popup;
+
+use crate::commands::in_console;
 
 /// A simple, non-invasive BG3 native mod loader
 #[allow(unused)]
 #[derive(Default, FromArgs)]
 pub struct Args {
+    /// plugin profile to use, overriding [core]active_profile
+    #[argh(option)]
+    pub profile: Option<String>,
+
+    /// override a config value for this session without editing config.toml,
+    /// e.g. `--set core.cli=true`. May be repeated
+    #[argh(option)]
+    pub set: Vec<String>,
+
+    /// inject the loader, but load no plugins; only report which would have loaded
+    #[argh(switch)]
+    pub safe_mode: bool,
+
+    /// show a console with the logs, like `--set core.cli=true`
+    #[argh(switch)]
+    pub cli: bool,
+
+    /// print the JSON Schema for config.toml and exit
+    #[argh(switch)]
+    pub print_config_schema: bool,
+
+    #[argh(subcommand)]
+    pub command: Option<Command>,
+
     /// binary to test inject
     #[cfg(feature = "test-injection")]
     #[argh(option)]
     pub inject: String,
 }
+
+impl Args {
+    /// Parse the command line. The tools have no console of their own, so `--help` is
+    /// printed to one and invalid arguments are shown in a popup, instead of going nowhere
+    pub fn parse() -> Self {
+        let strings = env::args_os()
+            .map(|a| a.to_string_lossy().into_owned())
+            .collect::<Vec<_>>();
+
+        let cmd = strings
+            .first()
+            .and_then(|c| Path::new(c).file_name())
+            .map_or_else(|| "yabg3nml".into(), |c| c.to_string_lossy());
+        let args = strings
+            .iter()
+            .skip(1)
+            .map(String::as_str)
+            .collect::<Vec<_>>();
+
+        match Self::from_args(&[cmd.as_ref()], &args) {
+            Ok(args) => args,
+
+            Err(EarlyExit {
+                output,
+                status: Ok(()),
+            }) => {
+                _ = in_console(|| {
+                    println!("{output}");
+                    Ok(true)
+                });
+
+                process::exit(0);
+            }
+
+            Err(EarlyExit {
+                output,
+                status: Err(()),
+            }) => fatal_popup("Invalid arguments", output),
+        }
+    }
+}
+
+#[derive(FromArgs)]
+#[argh(subcommand)]
+pub enum Command {
+    Config(ConfigCommand),
+    Plugins(PluginsCommand),
+    Bisect(BisectCommand),
+}
+
+/// inspect config.toml
+#[derive(FromArgs)]
+#[argh(subcommand, name = "config")]
+pub struct ConfigCommand {
+    #[argh(subcommand)]
+    pub command: ConfigSubcommand,
+}
+
+#[derive(FromArgs)]
+#[argh(subcommand)]
+pub enum ConfigSubcommand {
+    Show(ConfigShow),
+    Validate(ConfigValidate),
+}
+
+/// print the config as the tools see it, after env var and cli overrides
+#[derive(FromArgs)]
+#[argh(subcommand, name = "show")]
+pub struct ConfigShow {
+    /// show where each value came from
+    #[argh(switch)]
+    pub origin: bool,
+}
+
+/// check config.toml for problems, reporting all of them with their line and column
+#[derive(FromArgs)]
+#[argh(subcommand, name = "validate")]
+pub struct ConfigValidate {
+    /// config file to check instead of the one in the plugins folder
+    #[argh(positional)]
+    pub path: Option<String>,
+}
+
+/// manage which plugins are trusted to load
+#[derive(FromArgs)]
+#[argh(subcommand, name = "plugins")]
+pub struct PluginsCommand {
+    #[argh(subcommand)]
+    pub command: PluginsSubcommand,
+}
+
+#[derive(FromArgs)]
+#[argh(subcommand)]
+pub enum PluginsSubcommand {
+    Status(PluginsStatus),
+    Approve(PluginsApprove),
+    Revoke(PluginsRevoke),
+}
+
+/// list the plugins and whether they match their approval in plugins.lock
+#[derive(FromArgs)]
+#[argh(subcommand, name = "status")]
+pub struct PluginsStatus {}
+
+/// approve plugins to load when [core]trust is on, pinning their current hash in plugins.lock
+#[derive(FromArgs)]
+#[argh(subcommand, name = "approve")]
+pub struct PluginsApprove {
+    /// approve every plugin in the plugins folder
+    #[argh(switch)]
+    pub all: bool,
+
+    /// plugins to approve, by filename without extension
+    #[argh(positional)]
+    pub names: Vec<String>,
+}
+
+/// remove plugins' approval from plugins.lock
+#[derive(FromArgs)]
+#[argh(subcommand, name = "revoke")]
+pub struct PluginsRevoke {
+    /// plugins to revoke, by filename without extension
+    #[argh(positional)]
+    pub names: Vec<String>,
+}
+
+/// find which plugin causes a problem, by loading half of the suspects each game launch
+#[derive(FromArgs)]
+#[argh(subcommand, name = "bisect")]
+pub struct BisectCommand {
+    #[argh(subcommand)]
+    pub command: BisectSubcommand,
+}
+
+#[derive(FromArgs)]
+#[argh(subcommand)]
+pub enum BisectSubcommand {
+    Start(BisectStart),
+    Good(BisectGood),
+    Bad(BisectBad),
+    Status(BisectStatus),
+    Reset(BisectReset),
+}
+
+/// start bisecting every enabled plugin
+#[derive(FromArgs)]
+#[argh(subcommand, name = "start")]
+pub struct BisectStart {}
+
+/// the problem did not happen on the last launch
+#[derive(FromArgs)]
+#[argh(subcommand, name = "good")]
+pub struct BisectGood {}
+
+/// the problem happened on the last launch
+#[derive(FromArgs)]
+#[argh(subcommand, name = "bad")]
+pub struct BisectBad {}
+
+/// show the remaining suspects and which plugins the next launch loads
+#[derive(FromArgs)]
+#[argh(subcommand, name = "status")]
+pub struct BisectStatus {}
+
+/// stop bisecting and load plugins normally again
+#[derive(FromArgs)]
+#[argh(subcommand, name = "reset")]
+pub struct BisectReset {}
@@ -0,0 +1,302 @@
+use std::{
+    fs,
+    io::{self, BufRead as _},
+    path::PathBuf,
+    process,
+};
+
+use eyre::{Result, bail};
+use shared::{
+    config::{Config, Severity, check, config_path, get_config, schema},
+    paths::get_bg3_plugins_dir,
+    plugins::{LOCK_NAME, Lockfile, Trust, find_plugins, lock_path},
+};
+use toml::{Table, Value};
+use unicase::UniCase;
+
+use crate::{
+    bisect::{self, Progress, Verdict},
+    cli::{
+        BisectCommand, BisectSubcommand, Command, ConfigCommand, ConfigShow, ConfigSubcommand,
+        ConfigValidate, PluginsApprove, PluginsCommand, PluginsRevoke, PluginsSubcommand,
+    },
+    console::attach_console,
+    profile,
+};
+
+/// Run a cli subcommand instead of the tool itself
+pub fn run_command(command: &Command) -> Result<()> {
+    in_console(|| match command {
+        Command::Config(ConfigCommand { command }) => match command {
+            ConfigSubcommand::Show(show) => config_show(show),
+            ConfigSubcommand::Validate(validate) => config_validate(validate),
+        },
+
+        Command::Bisect(BisectCommand { command }) => match command {
+            BisectSubcommand::Start(_) => bisect_start(),
+            BisectSubcommand::Good(_) => bisect_mark(Verdict::Good),
+            BisectSubcommand::Bad(_) => bisect_mark(Verdict::Bad),
+            BisectSubcommand::Status(_) => bisect_status(),
+            BisectSubcommand::Reset(_) => bisect_reset(),
+        },
+
+        Command::Plugins(PluginsCommand { command }) => match command {
+            PluginsSubcommand::Status(_) => plugins_status(),
+            PluginsSubcommand::Approve(approve) => plugins_approve(approve),
+            PluginsSubcommand::Revoke(revoke) => plugins_revoke(revoke),
+        },
+    })
+}
+
+/// Print the JSON Schema for config.toml
+pub fn print_config_schema() -> Result<()> {
+    in_console(|| {
+        println!("{}", serde_json::to_string_pretty(&schema())?);
+        Ok(true)
+    })
+}
+
+/// Run `f` with stdout going to a console. Exits with code 1 if `f` reports failure
+pub fn in_console(f: impl FnOnce() -> Result<bool>) -> Result<()> {
+    let new_console = attach_console()?;
+
+    let result = f();
+
+    if let Err(e) = &result {
+        println!("error: {e}");
+    }
+
+    // otherwise the window would close before anything could be read
+    if new_console {
+        println!("\nPress enter to exit");
+        _ = io::stdin().lock().read_line(&mut String::new());
+    }
+
+    if !result? {
+        process::exit(1);
+    }
+
+    Ok(())
+}
+
+fn config_validate(validate: &ConfigValidate) -> Result<bool> {
+    let path = match &validate.path {
+        Some(path) => PathBuf::from(path),
+        None => config_path()?,
+    };
+
+    let data = fs::read_to_string(&path)?;
+    let problems = check(&data);
+
+    for problem in &problems {
+        println!("{}:{problem}", path.display());
+    }
+
+    let errors = problems
+        .iter()
+        .filter(|p| p.severity == Severity::Error)
+        .count();
+    let warnings = problems.len() - errors;
+
+    println!("{errors} error(s), {warnings} warning(s)");
+
+    Ok(errors == 0)
+}
+
+fn config_show(show: &ConfigShow) -> Result<bool> {
+    let config = get_config()?.get();
+
+    if !show.origin {
+        println!("{}", toml::to_string_pretty(config)?);
+        return Ok(true);
+    }
+
+    let mut lines = Vec::new();
+    flatten(&Table::try_from(config)?, &mut String::new(), &mut lines);
+
+    let width = lines.iter().map(|(l, _)| l.len()).max().unwrap_or_default();
+    for (line, key) in lines {
+        let origin = origin(config, &key);
+        println!("{line:width$}  # {origin}");
+    }
+
+    Ok(true)
+}
+
+/// Values which aren't in any layer's table were filled in by serde defaults
+fn origin(config: &Config, key: &str) -> String {
+    config
+        .origins
+        .get(key)
+        .map(ToString::to_string)
+        .unwrap_or_else(|| "default".to_owned())
+}
+
+/// Every leaf value as a `dotted.key = value` line, along with its key
+fn flatten(table: &Table, prefix: &mut String, lines: &mut Vec<(String, String)>) {
+    for (key, value) in table {
+        let len = prefix.len();
+        if !prefix.is_empty() {
+            prefix.push('.');
+        }
+        prefix.push_str(key);
+
+        match value {
+            Value::Table(table) if !table.is_empty() => flatten(table, prefix, lines),
+            _ => lines.push((format!("{prefix} = {value}"), prefix.clone())),
+        }
+
+        prefix.truncate(len);
+    }
+}
+
+fn plugins_status() -> Result<bool> {
+    let plugins_dir = get_bg3_plugins_dir()?;
+    let lockfile = Lockfile::load(&plugins_dir)?;
+    let plugins = find_plugins(&plugins_dir)?;
+
+    println!("trust mode: {}", get_config()?.get().core.trust);
+
+    let width = plugins
+        .iter()
+        .map(|p| p.name.len())
+        .chain(lockfile.plugins.keys().map(String::len))
+        .max()
+        .unwrap_or_default();
+
+    for plugin in &plugins {
+        let path = lock_path(&plugins_dir, &plugin.path);
+
+        let status = match fs::read(&plugin.path) {
+            Ok(image) => match lockfile.check(&plugin.name, &path, &image) {
+                Trust::Approved => "approved".to_owned(),
+                Trust::Unapproved => "unapproved".to_owned(),
+                Trust::Changed { .. } => "changed since approval".to_owned(),
+                Trust::Moved { approved, .. } => format!("approved at {approved}, now at {path}"),
+            },
+
+            Err(e) => format!("unreadable: {e}"),
+        };
+
+        println!("{:width$}  {path}  {status}", plugin.name);
+    }
+
+    // approvals outlive the plugins they were for
+    for (name, approval) in &lockfile.plugins {
+        if !plugins
+            .iter()
+            .any(|p| UniCase::new(p.name.as_str()) == UniCase::new(name.as_str()))
+        {
+            println!(
+                "{name:width$}  {}  approved, but not installed",
+                approval.path
+            );
+        }
+    }
+
+    Ok(true)
+}
+
+fn plugins_approve(approve: &PluginsApprove) -> Result<bool> {
+    if approve.names.is_empty() && !approve.all {
+        bail!("name the plugins to approve, or pass --all");
+    }
+
+    let plugins_dir = get_bg3_plugins_dir()?;
+    let mut lockfile = Lockfile::load(&plugins_dir)?;
+    let plugins = find_plugins(&plugins_dir)?;
+
+    let is_named = |name: &str| {
+        approve
+            .names
+            .iter()
+            .any(|n| UniCase::new(n.as_str()) == UniCase::new(name))
+    };
+
+    let mut ok = true;
+    for name in &approve.names {
+        if !plugins
+            .iter()
+            .any(|p| UniCase::new(p.name.as_str()) == UniCase::new(name.as_str()))
+        {
+            println!("no plugin named {name} in {}", plugins_dir.display());
+            ok = false;
+        }
+    }
+
+    for plugin in plugins.iter().filter(|p| approve.all || is_named(&p.name)) {
+        let image = fs::read(&plugin.path)?;
+        let path = lock_path(&plugins_dir, &plugin.path);
+
+        match lockfile.check(&plugin.name, &path, &image) {
+            Trust::Approved => println!("{} is already approved", plugin.name),
+            Trust::Unapproved => println!("approved {} ({path})", plugin.name),
+            Trust::Changed { .. } => {
+                println!("approved the new version of {} ({path})", plugin.name)
+            }
+            Trust::Moved { approved, .. } => {
+                println!("approved {} at {path}, instead of {approved}", plugin.name)
+            }
+        }
+
+        lockfile.approve(&plugin.name, path, &image);
+    }
+
+    lockfile.save(&plugins_dir)?;
+    println!("wrote {}", plugins_dir.join(LOCK_NAME).display());
+
+    Ok(ok)
+}
+
+fn plugins_revoke(revoke: &PluginsRevoke) -> Result<bool> {
+    let plugins_dir = get_bg3_plugins_dir()?;
+    let mut lockfile = Lockfile::load(&plugins_dir)?;
+
+    let mut ok = true;
+    for name in &revoke.names {
+        if lockfile.revoke(name) {
+            println!("revoked {name}");
+        } else {
+            println!("{name} is not approved");
+            ok = false;
+        }
+    }
+
+    lockfile.save(&plugins_dir)?;
+
+    Ok(ok)
+}
+
+fn bisect_start() -> Result<bool> {
+    let config = profile::apply(get_config()?.get());
+    let bisect = bisect::start(&config)?;
+
+    println!("{}", Progress::Narrowed(bisect));
+
+    Ok(true)
+}
+
+fn bisect_mark(verdict: Verdict) -> Result<bool> {
+    println!("{}", bisect::mark(verdict)?);
+
+    Ok(true)
+}
+
+fn bisect_status() -> Result<bool> {
+    match bisect::load()? {
+        Some(bisect) => println!("{}", Progress::Narrowed(bisect)),
+        None => println!("not bisecting"),
+    }
+
+    Ok(true)
+}
+
+fn bisect_reset() -> Result<bool> {
+    if bisect::reset()? {
+        println!("stopped bisecting; plugins load normally again");
+    } else {
+        println!("not bisecting");
+    }
+
+    Ok(true)
+}
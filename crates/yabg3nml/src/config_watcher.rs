@@ -0,0 +1,131 @@
+use std::{
+    fs,
+    path::PathBuf,
+    sync::{
+        Arc,
+        mpsc::{RecvTimeoutError, channel},
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, SystemTime},
+};
+
+use sayuri::sync::Mutex;
+use shared::config::{Config, read_config};
+use tracing::{error, info, trace, trace_span, warn};
+
+use crate::{
+    logging::{loader_level, plugins, reload_level, sync_loader_level},
+    profile,
+    stop_token::StopToken,
+};
+
+/// The last known good config. Cheap to clone; all clones see reloads
+#[derive(Clone)]
+pub struct LiveConfig(Arc<Mutex<Arc<Config>>>);
+
+impl LiveConfig {
+    pub fn new(config: Config) -> Self {
+        Self(Arc::new(Mutex::new(Arc::new(config))))
+    }
+
+    /// Snapshot of the current config
+    pub fn get(&self) -> Arc<Config> {
+        self.0.lock().clone()
+    }
+
+    fn set(&self, config: Config) {
+        *self.0.lock() = Arc::new(config);
+    }
+}
+
+pub struct ConfigWatcher {
+    path: PathBuf,
+    polling_rate: Duration,
+    config: LiveConfig,
+}
+
+impl ConfigWatcher {
+    pub fn new(path: PathBuf, polling_rate: Duration, config: LiveConfig) -> Self {
+        Self {
+            path,
+            polling_rate,
+            config,
+        }
+    }
+
+    /// Polls the config file for changes, and on change re-reads it into the live config
+    ///
+    /// A config which fails to read or validate is logged and ignored; the last good config stays live
+    pub fn run(self) -> (StopToken, JoinHandle<()>) {
+        let (sender, recv) = channel();
+
+        let handle = thread::spawn(move || {
+            let span = trace_span!("config_watcher");
+            let _guard = span.enter();
+
+            let modified = || fs::metadata(&self.path).and_then(|m| m.modified()).ok();
+
+            let mut last_modified: Option<SystemTime> = modified();
+
+            loop {
+                let signal = recv.recv_timeout(self.polling_rate);
+                if matches!(signal, Ok(_) | Err(RecvTimeoutError::Disconnected)) {
+                    trace!(?signal, "signal exited");
+                    break;
+                }
+
+                let current = modified();
+                if current == last_modified {
+                    continue;
+                }
+
+                last_modified = current;
+
+                if current.is_none() {
+                    warn!(path = %self.path.display(), "config file is gone; keeping last good config");
+                    continue;
+                }
+
+                self.reload();
+            }
+        });
+
+        (StopToken::new(sender), handle)
+    }
+
+    fn reload(&self) {
+        let new = match read_config(&self.path) {
+            Ok(c) => c,
+            Err(e) => {
+                error!(
+                    path = %self.path.display(),
+                    "config change rejected; keeping last good config: {e}"
+                );
+                return;
+            }
+        };
+
+        let old = self.config.get();
+
+        if old.core.install_root != new.core.install_root {
+            warn!("[core]install_root changed. The watched game paths only update after a restart");
+        }
+
+        let old_level = loader_level();
+
+        let (old_effective, new_effective) = (profile::apply(&old), profile::apply(&new));
+        if old_effective.log_level() != new_effective.log_level()
+            && let Err(e) = reload_level(new_effective.log_level())
+        {
+            error!("failed to apply new log level: {e}");
+        }
+
+        plugins::set_levels(&new);
+
+        sync_loader_level(old_level);
+
+        self.config.set(new);
+
+        info!("reloaded config.toml");
+    }
+}
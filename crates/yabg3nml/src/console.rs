@@ -1,9 +1,9 @@
 use eyre::Result;
 use windows::{
     Win32::System::Console::{
-        AllocConsole, ENABLE_PROCESSED_OUTPUT, ENABLE_VIRTUAL_TERMINAL_PROCESSING,
-        ENABLE_WRAP_AT_EOL_OUTPUT, GetStdHandle, STD_OUTPUT_HANDLE, SetConsoleMode,
-        SetConsoleTitleW,
+        ATTACH_PARENT_PROCESS, AllocConsole, AttachConsole, ENABLE_PROCESSED_OUTPUT,
+        ENABLE_VIRTUAL_TERMINAL_PROCESSING, ENABLE_WRAP_AT_EOL_OUTPUT, GetConsoleWindow,
+        GetStdHandle, STD_OUTPUT_HANDLE, SetConsoleMode, SetConsoleTitleW,
     },
     core::PCWSTR,
 };
@@ -37,3 +37,24 @@ pub fn debug_console<A: AsRef<str>>(title: A) -> Result<()> {
 
     Ok(())
 }
+
+/// Send stdout to the console this was launched from, so cli commands can print.
+/// Opens a new console if there is none, e.g. when started from explorer
+///
+/// Returns whether a new console was opened
+pub fn attach_console() -> Result<bool> {
+    // debug builds are console apps, so they already have one
+    if !unsafe { GetConsoleWindow() }.is_invalid() {
+        return Ok(false);
+    }
+
+    if unsafe { AttachConsole(ATTACH_PARENT_PROCESS) }.is_ok() {
+        return Ok(false);
+    }
+
+    unsafe {
+        AllocConsole()?;
+    }
+
+    Ok(true)
+}
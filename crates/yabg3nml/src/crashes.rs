@@ -0,0 +1,333 @@
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::PathBuf,
+    sync::atomic::{AtomicBool, Ordering},
+    thread,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use eyre::{Context as _, Result};
+use serde::{Deserialize, Serialize};
+use shared::{
+    config::{Config, ConfigEditor, config_path, write_atomic},
+    paths::get_bg3_plugins_dir,
+    popup::yes_no_popup,
+    utils::OwnedHandle,
+};
+use tracing::{error, info, trace, warn};
+use unicase::UniCase;
+use windows::Win32::System::Threading::{
+    GetExitCodeProcess, INFINITE, OpenProcess, PROCESS_QUERY_LIMITED_INFORMATION,
+    PROCESS_SYNCHRONIZE, WaitForSingleObject,
+};
+
+use crate::{
+    process_watcher::Pid,
+    session::{self, Outcome},
+};
+
+/// Holds the crash history, in the plugins dir
+const HISTORY_NAME: &str = "crashes.toml";
+
+/// A crash this long after the loader started is not blamed on plugins
+const CRASH_WINDOW: Duration = Duration::from_secs(120);
+
+/// How many crashes in a row a plugin must be loaded for before it is offered for disabling
+const QUARANTINE_AFTER: u32 = 2;
+
+/// How many sessions are kept in the history
+const MAX_SESSIONS: usize = 20;
+
+/// Whether a quarantine popup is open in the background, so launches while it is don't open more
+static ASKING: AtomicBool = AtomicBool::new(false);
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+struct History {
+    /// Most recent last
+    sessions: Vec<SessionRecord>,
+    /// Plugins loaded during recent crashes, by name
+    quarantine: BTreeMap<String, Suspect>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SessionRecord {
+    pid: Pid,
+    /// Unix time the game exited
+    exited: u64,
+    /// Seconds between the loader starting and the game exiting
+    uptime: u64,
+    exit_code: u32,
+    crashed: bool,
+    /// The plugins blamed, or loaded if it didn't crash
+    plugins: Vec<String>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+struct Suspect {
+    /// Crashes in a row this plugin was blamed for
+    crashes: u32,
+    /// The crash count when the user last declined disabling it
+    declined_at: u32,
+}
+
+impl History {
+    fn path() -> Result<PathBuf> {
+        Ok(get_bg3_plugins_dir()?.join(HISTORY_NAME))
+    }
+
+    fn load() -> Result<Self> {
+        let path = Self::path()?;
+
+        let data = match fs::read_to_string(&path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(e).with_context(|| format!("failed to read {}", path.display())),
+        };
+
+        toml::from_str(&data).with_context(|| format!("{} is invalid", path.display()))
+    }
+
+    fn save(&self) -> Result<()> {
+        let data = format!(
+            "# Recent game sessions and the plugins loaded during crashes. Safe to delete\n\n{}",
+            toml::to_string_pretty(self)?
+        );
+
+        write_atomic(&Self::path()?, &data)
+    }
+
+    fn suspect(&mut self, name: &str) -> &mut Suspect {
+        let key = self
+            .quarantine
+            .keys()
+            .find(|k| UniCase::new(k.as_str()) == UniCase::new(name))
+            .cloned()
+            .unwrap_or_else(|| name.to_owned());
+
+        self.quarantine.entry(key).or_default()
+    }
+
+    fn clear(&mut self, name: &str) {
+        self.quarantine
+            .retain(|k, _| UniCase::new(k.as_str()) != UniCase::new(name));
+    }
+}
+
+/// Whether an exit code is an NTSTATUS error, like an access violation (0xC0000005).
+/// Closing the game normally or killing it from task manager doesn't give one
+fn is_crash(code: u32) -> bool {
+    code >> 30 == 0b11
+}
+
+/// Wait in the background for the game process `pid` to exit, then record how it went
+pub fn watch(pid: Pid) {
+    thread::spawn(move || {
+        let process = unsafe {
+            OpenProcess(
+                PROCESS_SYNCHRONIZE | PROCESS_QUERY_LIMITED_INFORMATION,
+                false,
+                pid,
+            )
+        };
+
+        let process = match process {
+            Ok(v) => unsafe { OwnedHandle::new(v) },
+            Err(e) => {
+                trace!(pid, err = %e, "failed to open process to watch for crashes");
+                return;
+            }
+        };
+
+        _ = unsafe { WaitForSingleObject(*process, INFINITE) };
+
+        let mut code = 0;
+        if let Err(e) = unsafe { GetExitCodeProcess(*process, &mut code) } {
+            trace!(pid, err = %e, "failed to get exit code");
+            return;
+        }
+
+        record_exit(pid, code);
+    });
+}
+
+/// Record how the game process `pid` exited, blaming its plugins if it crashed soon after loading
+pub fn record_exit(pid: Pid, exit_code: u32) {
+    let Some((plugins, uptime)) = session::plugins_in(pid) else {
+        trace!(
+            pid,
+            exit_code, "game exited without the loader reporting in"
+        );
+        return;
+    };
+
+    let crashed = is_crash(exit_code) && uptime <= CRASH_WINDOW;
+
+    // a plugin still in DllMain or Init when the game died is far more likely the cause
+    let loading = plugins
+        .iter()
+        .filter(|(_, o)| matches!(o, Outcome::Loading))
+        .map(|(n, _)| n.clone())
+        .collect::<Vec<_>>();
+
+    let blamed = if crashed && !loading.is_empty() {
+        loading
+    } else {
+        plugins.into_iter().map(|(n, _)| n).collect()
+    };
+
+    if crashed {
+        warn!(
+            pid,
+            "Game crashed {}s after plugins loaded (exit code 0x{exit_code:08x}); plugins which were active: {}",
+            uptime.as_secs(),
+            blamed.join(", ")
+        );
+    } else {
+        trace!(pid, exit_code, ?uptime, "game exited");
+    }
+
+    let mut history = History::load().unwrap_or_else(|e| {
+        error!("{e:#}; starting a new crash history");
+        History::default()
+    });
+
+    for name in &blamed {
+        if crashed {
+            history.suspect(name).crashes += 1;
+        } else {
+            history.clear(name);
+        }
+    }
+
+    history.sessions.push(SessionRecord {
+        pid,
+        exited: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs(),
+        uptime: uptime.as_secs(),
+        exit_code,
+        crashed,
+        plugins: blamed,
+    });
+
+    let excess = history.sessions.len().saturating_sub(MAX_SESSIONS);
+    history.sessions.drain(..excess);
+
+    if let Err(e) = history.save() {
+        error!("failed to save crash history: {e:#}");
+    }
+}
+
+/// Offer to disable plugins which were loaded for several crashes in a row, before the game
+/// starts. Disabled ones are written to config.toml, and also disabled in `config` so they
+/// apply to this launch
+pub fn offer_quarantine(config: &mut Config) {
+    let suspects = suspects(config);
+    if suspects.is_empty() {
+        return;
+    }
+
+    let disable = ask(&suspects, "");
+    answer(
+        config.active_profile().map(|(name, _)| name),
+        &suspects,
+        disable,
+    );
+
+    if disable {
+        for (name, _) in &suspects {
+            config.disable_plugin(name);
+        }
+    }
+}
+
+/// Like [`offer_quarantine`], but for a game which is already starting. Plugins can't wait on
+/// a popup, so it is shown in the background and the answer applies from the next launch
+pub fn offer_quarantine_later(config: &Config) {
+    let suspects = suspects(config);
+    if suspects.is_empty() || ASKING.swap(true, Ordering::Relaxed) {
+        return;
+    }
+
+    let profile = config.active_profile().map(|(name, _)| name.to_owned());
+
+    thread::spawn(move || {
+        let disable = ask(&suspects, " They stay loaded until the game is restarted.");
+        answer(profile.as_deref(), &suspects, disable);
+
+        ASKING.store(false, Ordering::Relaxed);
+    });
+}
+
+/// Plugins to offer for disabling, with how many crashes in a row they were loaded for
+fn suspects(config: &Config) -> Vec<(String, u32)> {
+    let Ok(history) = History::load() else {
+        return Vec::new();
+    };
+
+    history
+        .quarantine
+        .into_iter()
+        .filter(|(name, s)| {
+            s.crashes >= s.declined_at + QUARANTINE_AFTER && !config.is_plugin_disabled(name)
+        })
+        .map(|(name, s)| (name, s.crashes))
+        .collect()
+}
+
+fn ask(suspects: &[(String, u32)], note: &str) -> bool {
+    let list = suspects
+        .iter()
+        .map(|(name, crashes)| format!("- {name} ({crashes} crashes in a row)"))
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    yes_no_popup(
+        "Plugins may be crashing the game",
+        format!(
+            "The game recently crashed shortly after loading these plugins:\n\n{list}\n\nDisable them?{note} They are added to disabled_plugins in config.toml, in the active profile if there is one, and can be re-enabled there at any time."
+        ),
+    )
+}
+
+/// Write the answer to config.toml and the crash history. Plugins are disabled in `profile`,
+/// the active one, since its rules take precedence over [core]
+fn answer(profile: Option<&str>, suspects: &[(String, u32)], disable: bool) {
+    // reloaded, since the game may have exited while the popup was open
+    let Ok(mut history) = History::load() else {
+        return;
+    };
+
+    if disable {
+        let result = config_path().and_then(|path| {
+            let mut editor = ConfigEditor::open(path)?;
+            for (name, _) in suspects {
+                editor.disable_plugin(profile, name)?;
+            }
+
+            editor.save()
+        });
+
+        if let Err(e) = result {
+            error!("failed to disable suspected plugins in config.toml: {e:#}");
+        }
+
+        for (name, _) in suspects {
+            info!("Disabled plugin {name}, which was loaded for repeated crashes");
+            history.clear(name);
+        }
+    } else {
+        info!("kept suspected plugins enabled");
+        for (name, crashes) in suspects {
+            history.suspect(name).declined_at = *crashes;
+        }
+    }
+
+    if let Err(e) = history.save() {
+        error!("failed to save crash history: {e:#}");
+    }
+}
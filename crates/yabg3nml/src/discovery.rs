@@ -0,0 +1,438 @@
+//! Locate BG3 installs from Steam and GOG metadata
+
+mod vdf;
+
+use std::{
+    cmp::Reverse,
+    fmt::{self, Display},
+    fs,
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+use tracing::{debug, info, trace_span, warn};
+use winreg::{
+    RegKey,
+    enums::{HKEY_CURRENT_USER, HKEY_LOCAL_MACHINE},
+};
+
+const STEAM_APP_ID: &str = "1086940";
+const GOG_GAME_ID: &str = "1456460669";
+
+const HKCU: RegKey = RegKey::predef(HKEY_CURRENT_USER);
+const HKLM: RegKey = RegKey::predef(HKEY_LOCAL_MACHINE);
+
+const STEAM_KEY: &str = r"Software\Valve\Steam";
+const STEAM_KEY_HKLM: &str = r"SOFTWARE\WOW6432Node\Valve\Steam";
+/// Each game has a subkey named after its id
+const GOG_GAMES_KEY: &str = r"SOFTWARE\WOW6432Node\GOG.com\Games";
+
+/// `StateFlags` bit in an app manifest meaning the app is fully downloaded
+const STATE_FULLY_INSTALLED: u32 = 4;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Source {
+    Steam,
+    Gog,
+}
+
+impl Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let source = match self {
+            Source::Steam => "Steam",
+            Source::Gog => "GOG",
+        };
+
+        write!(f, "{source}")
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Candidate {
+    pub source: Source,
+    /// The game root, i.e. the directory containing `bin`
+    pub root: PathBuf,
+    /// Both `bin/bg3.exe` and `bin/bg3_dx11.exe` exist
+    pub has_exes: bool,
+    /// The store reports the game as fully installed
+    pub installed: bool,
+    /// Unix timestamp of the last update, if known
+    pub last_updated: Option<u64>,
+}
+
+impl Candidate {
+    fn new(source: Source, root: PathBuf, installed: bool, last_updated: Option<u64>) -> Self {
+        let bin = root.join("bin");
+        let has_exes = bin.join("bg3.exe").is_file() && bin.join("bg3_dx11.exe").is_file();
+
+        Self {
+            source,
+            root,
+            has_exes,
+            installed,
+            last_updated,
+        }
+    }
+
+    /// Most preferred sorts first
+    fn rank(&self) -> impl Ord + use<> {
+        (
+            Reverse(self.has_exes),
+            Reverse(self.installed),
+            Reverse(self.last_updated),
+            self.source,
+        )
+    }
+}
+
+impl Display for Candidate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} install at {}", self.source, self.root.display())
+    }
+}
+
+/// Every install found, best first
+#[derive(Debug, Default)]
+pub struct Discovery {
+    pub candidates: Vec<Candidate>,
+    /// Problems encountered while looking, e.g. unreadable metadata
+    pub problems: Vec<String>,
+}
+
+impl Discovery {
+    /// The best usable install, if any
+    pub fn best(&self) -> Option<&Candidate> {
+        self.candidates.first().filter(|c| c.has_exes)
+    }
+
+    /// Why [`Discovery::best`] was picked over the other candidates
+    pub fn reason(&self) -> Option<String> {
+        let best = self.best()?;
+
+        let mut reasons = vec!["both game executables exist".to_owned()];
+
+        if best.installed {
+            reasons.push(format!("{} reports it as fully installed", best.source));
+        }
+
+        let others = &self.candidates[1..];
+        if !others.is_empty() {
+            if best.last_updated.is_some() && others.iter().any(|c| c.has_exes) {
+                reasons.push("it was updated most recently".to_owned());
+            }
+
+            reasons.push(format!("{} other candidate(s) ranked lower", others.len()));
+        }
+
+        Some(reasons.join(", "))
+    }
+
+    /// Human readable summary of everything that was found
+    pub fn report(&self) -> String {
+        let mut report = String::new();
+
+        if self.candidates.is_empty() {
+            report.push_str("No Steam or GOG installs of BG3 were found.");
+        }
+
+        for candidate in &self.candidates {
+            let status = if !candidate.has_exes {
+                "game executables missing"
+            } else if !candidate.installed {
+                "not fully installed"
+            } else {
+                "ok"
+            };
+
+            report.push_str(&format!("\n- {candidate} ({status})"));
+        }
+
+        for problem in &self.problems {
+            report.push_str(&format!("\n- {problem}"));
+        }
+
+        report.trim_start().to_owned()
+    }
+}
+
+/// Look for BG3 in every Steam library and the GOG install location
+pub fn discover() -> Discovery {
+    let span = trace_span!("discover");
+    let _guard = span.enter();
+
+    let mut discovery = Discovery::default();
+
+    steam(&mut discovery);
+    gog(&mut discovery);
+
+    discovery.candidates.sort_by_key(|c| c.rank());
+
+    for candidate in &discovery.candidates {
+        debug!(
+            source = %candidate.source,
+            root = %candidate.root.display(),
+            has_exes = candidate.has_exes,
+            installed = candidate.installed,
+            last_updated = ?candidate.last_updated,
+            "found install candidate"
+        );
+    }
+
+    for problem in &discovery.problems {
+        warn!("{problem}");
+    }
+
+    match discovery.best() {
+        Some(best) => info!(
+            reason = discovery.reason().unwrap_or_default(),
+            "discovered {best}"
+        ),
+        None => info!("no usable BG3 install discovered"),
+    }
+
+    discovery
+}
+
+fn steam_root() -> Option<PathBuf> {
+    let hkcu = HKCU
+        .open_subkey(STEAM_KEY)
+        .and_then(|k| k.get_value::<String, _>("SteamPath"));
+
+    let hklm = || {
+        HKLM.open_subkey(STEAM_KEY_HKLM)
+            .and_then(|k| k.get_value::<String, _>("InstallPath"))
+    };
+
+    hkcu.or_else(|_| hklm()).ok().map(PathBuf::from)
+}
+
+fn steam(discovery: &mut Discovery) {
+    let Some(root) = steam_root() else {
+        debug!("steam is not installed");
+        return;
+    };
+
+    let folders = root.join("steamapps").join("libraryfolders.vdf");
+    let mut libraries = match fs::read_to_string(&folders) {
+        Ok(data) => match library_folders(&data) {
+            Ok(libraries) => libraries,
+            Err(e) => {
+                discovery
+                    .problems
+                    .push(format!("failed to parse {}: {e}", folders.display()));
+                Vec::new()
+            }
+        },
+
+        Err(e) => {
+            discovery
+                .problems
+                .push(format!("failed to read {}: {e}", folders.display()));
+            Vec::new()
+        }
+    };
+
+    // the steam dir itself is always a library, but isn't listed in older formats
+    if !libraries.iter().any(|l| same_path(l, &root)) {
+        libraries.insert(0, root);
+    }
+
+    for library in libraries {
+        let steamapps = library.join("steamapps");
+        let manifest_path = steamapps.join(format!("appmanifest_{STEAM_APP_ID}.acf"));
+
+        let data = match fs::read_to_string(&manifest_path) {
+            Ok(data) => data,
+            // not installed in this library
+            Err(_) => continue,
+        };
+
+        let manifest = match app_manifest(&data) {
+            Ok(m) => m,
+            Err(e) => {
+                discovery
+                    .problems
+                    .push(format!("failed to parse {}: {e}", manifest_path.display()));
+                continue;
+            }
+        };
+
+        let root = steamapps.join("common").join(&manifest.install_dir);
+        let installed = manifest.state_flags & STATE_FULLY_INSTALLED != 0;
+
+        discovery.candidates.push(Candidate::new(
+            Source::Steam,
+            root,
+            installed,
+            manifest.last_updated,
+        ));
+    }
+}
+
+fn gog(discovery: &mut Discovery) {
+    let Ok(root) = HKLM
+        .open_subkey(format!(r"{GOG_GAMES_KEY}\{GOG_GAME_ID}"))
+        .and_then(|k| k.get_value::<String, _>("path"))
+    else {
+        debug!("gog install not registered");
+        return;
+    };
+
+    let root = PathBuf::from(root);
+
+    // GOG writes this once the install is complete
+    let info = root.join(format!("goggame-{GOG_GAME_ID}.info"));
+    let installed = match fs::read_to_string(&info) {
+        Ok(data) => match serde_json::from_str::<serde_json::Value>(&data) {
+            Ok(v) => v.get("gameId").and_then(|id| id.as_str()) == Some(GOG_GAME_ID),
+            Err(e) => {
+                discovery
+                    .problems
+                    .push(format!("failed to parse {}: {e}", info.display()));
+                false
+            }
+        },
+
+        Err(_) => false,
+    };
+
+    // GOG keeps no update timestamp, so the info file's is the next best thing
+    let last_updated = fs::metadata(&info)
+        .and_then(|m| m.modified())
+        .ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_secs());
+
+    discovery
+        .candidates
+        .push(Candidate::new(Source::Gog, root, installed, last_updated));
+}
+
+fn same_path(a: &Path, b: &Path) -> bool {
+    match (fs::canonicalize(a), fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+/// Library paths listed in `libraryfolders.vdf`
+///
+/// Newer files nest each library as `"0" { "path" "..." }`, older ones list them as `"1" "..."`
+fn library_folders(data: &str) -> Result<Vec<PathBuf>, vdf::ParseError> {
+    let root = vdf::parse(data)?;
+
+    let Some(folders) = root.get_object("libraryfolders") else {
+        return Ok(Vec::new());
+    };
+
+    let libraries = folders
+        .iter()
+        // other keys hold stats, not libraries
+        .filter(|(key, _)| key.parse::<u32>().is_ok())
+        .filter_map(|(_, value)| match value {
+            vdf::Value::String(path) => Some(path.as_str()),
+            vdf::Value::Object(library) => library.get_str("path"),
+        })
+        .map(PathBuf::from)
+        .collect();
+
+    Ok(libraries)
+}
+
+#[derive(Debug)]
+struct AppManifest {
+    install_dir: String,
+    state_flags: u32,
+    last_updated: Option<u64>,
+}
+
+fn app_manifest(data: &str) -> Result<AppManifest, vdf::ParseError> {
+    let root = vdf::parse(data)?;
+
+    let missing = |message: &str| vdf::ParseError {
+        line: 0,
+        column: 0,
+        message: message.to_owned(),
+    };
+
+    let state = root
+        .get_object("AppState")
+        .ok_or_else(|| missing("missing AppState"))?;
+
+    let install_dir = state
+        .get_str("installdir")
+        .ok_or_else(|| missing("missing AppState.installdir"))?
+        .to_owned();
+
+    let state_flags = state
+        .get_str("StateFlags")
+        .and_then(|f| f.parse().ok())
+        .unwrap_or_default();
+
+    let last_updated = state.get_str("LastUpdated").and_then(|t| t.parse().ok());
+
+    Ok(AppManifest {
+        install_dir,
+        state_flags,
+        last_updated,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture(name: &str) -> String {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests")
+            .join("fixtures")
+            .join(name);
+
+        fs::read_to_string(path).unwrap()
+    }
+
+    #[test]
+    fn library_folders_nested() {
+        let libraries = library_folders(&fixture("libraryfolders.vdf")).unwrap();
+
+        assert_eq!(
+            libraries,
+            [
+                PathBuf::from(r"C:\Program Files (x86)\Steam"),
+                PathBuf::from(r"D:\SteamLibrary"),
+            ]
+        );
+    }
+
+    #[test]
+    fn library_folders_old_format() {
+        let libraries = library_folders(&fixture("libraryfolders_old.vdf")).unwrap();
+
+        assert_eq!(
+            libraries,
+            [
+                PathBuf::from(r"D:\SteamLibrary"),
+                PathBuf::from(r"E:\Games\Steam"),
+            ]
+        );
+    }
+
+    #[test]
+    fn app_manifest_fields() {
+        let manifest = app_manifest(&fixture("appmanifest_1086940.acf")).unwrap();
+
+        assert_eq!(manifest.install_dir, "Baldurs Gate 3");
+        assert_eq!(
+            manifest.state_flags & STATE_FULLY_INSTALLED,
+            STATE_FULLY_INSTALLED
+        );
+        assert_eq!(manifest.last_updated, Some(1733952365));
+    }
+
+    #[test]
+    fn app_manifest_missing_installdir() {
+        let e = app_manifest(r#""AppState" { "appid" "1086940" }"#).unwrap_err();
+
+        assert_eq!(e.message, "missing AppState.installdir");
+    }
+}
@@ -0,0 +1,349 @@
+//! Parser for Valve's KeyValues text format, used by `libraryfolders.vdf` and `appmanifest_*.acf`
+//!
+//! ```text
+//! "AppState"
+//! {
+//!     "appid"       "1086940"
+//!     "installdir"  "Baldurs Gate 3"
+//! }
+//! ```
+
+use std::{
+    fmt::{self, Display},
+    iter::Peekable,
+    str::Chars,
+};
+
+use unicase::UniCase;
+
+/// Deeper nesting than any real file has, so a malformed one can't overflow the stack
+const MAX_DEPTH: usize = 64;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    String(String),
+    Object(Object),
+}
+
+impl Value {
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Self::String(s) => Some(s),
+            Self::Object(_) => None,
+        }
+    }
+
+    pub fn as_object(&self) -> Option<&Object> {
+        match self {
+            Self::Object(o) => Some(o),
+            Self::String(_) => None,
+        }
+    }
+}
+
+/// Key value pairs in file order. Keys are not unique in vdf, and compare case insensitively
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Object(pub Vec<(String, Value)>);
+
+impl Object {
+    /// First value with this key
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        let key = UniCase::new(key);
+        self.0
+            .iter()
+            .find(|(k, _)| UniCase::new(k.as_str()) == key)
+            .map(|(_, v)| v)
+    }
+
+    pub fn get_str(&self, key: &str) -> Option<&str> {
+        self.get(key).and_then(Value::as_str)
+    }
+
+    pub fn get_object(&self, key: &str) -> Option<&Object> {
+        self.get(key).and_then(Value::as_object)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Value)> {
+        self.0.iter().map(|(k, v)| (k.as_str(), v))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    String(String),
+    Open,
+    Close,
+    /// platform conditionals like `[$WIN32]`. We are always on windows, so they're ignored
+    Conditional,
+}
+
+struct Parser<'a> {
+    chars: Peekable<Chars<'a>>,
+    line: usize,
+    column: usize,
+}
+
+/// Parse a whole vdf document into its root object
+pub fn parse(input: &str) -> Result<Object, ParseError> {
+    let mut parser = Parser {
+        chars: input.chars().peekable(),
+        line: 1,
+        column: 1,
+    };
+
+    parser.object(0)
+}
+
+impl Parser<'_> {
+    fn error(&self, message: impl Into<String>) -> ParseError {
+        ParseError {
+            line: self.line,
+            column: self.column,
+            message: message.into(),
+        }
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.chars.next()?;
+
+        if c == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+
+        Some(c)
+    }
+
+    fn skip_trivia(&mut self) {
+        loop {
+            match self.chars.peek().copied() {
+                Some(c) if c.is_whitespace() => {
+                    self.bump();
+                }
+
+                // `//` comments run to the end of the line. A lone `/` is part of a value
+                Some('/') if self.chars.clone().nth(1) == Some('/') => {
+                    while self.chars.peek().is_some_and(|&c| c != '\n') {
+                        self.bump();
+                    }
+                }
+
+                _ => break,
+            }
+        }
+    }
+
+    fn token(&mut self) -> Result<Option<Token>, ParseError> {
+        self.skip_trivia();
+
+        let Some(&c) = self.chars.peek() else {
+            return Ok(None);
+        };
+
+        let token = match c {
+            '{' => {
+                self.bump();
+                Token::Open
+            }
+
+            '}' => {
+                self.bump();
+                Token::Close
+            }
+
+            '[' => {
+                while let Some(c) = self.bump() {
+                    if c == ']' {
+                        break;
+                    }
+                }
+
+                Token::Conditional
+            }
+
+            '"' => {
+                self.bump();
+
+                let mut s = String::new();
+                loop {
+                    match self.bump() {
+                        Some('"') => break,
+
+                        Some('\\') => match self.bump() {
+                            Some('n') => s.push('\n'),
+                            Some('t') => s.push('\t'),
+                            Some(c) => s.push(c),
+                            None => return Err(self.error("unterminated string")),
+                        },
+
+                        Some(c) => s.push(c),
+
+                        None => return Err(self.error("unterminated string")),
+                    }
+                }
+
+                Token::String(s)
+            }
+
+            _ => {
+                let mut s = String::new();
+                while let Some(&c) = self.chars.peek() {
+                    if c.is_whitespace() || matches!(c, '{' | '}' | '"') {
+                        break;
+                    }
+
+                    s.push(c);
+                    self.bump();
+                }
+
+                Token::String(s)
+            }
+        };
+
+        Ok(Some(token))
+    }
+
+    fn object(&mut self, depth: usize) -> Result<Object, ParseError> {
+        let mut object = Object::default();
+
+        loop {
+            let key = match self.token()? {
+                Some(Token::String(key)) => key,
+
+                Some(Token::Conditional) => continue,
+
+                Some(Token::Close) if depth > 0 => return Ok(object),
+                Some(Token::Close) => return Err(self.error("unexpected `}`")),
+
+                Some(Token::Open) => return Err(self.error("expected a key, found `{`")),
+
+                None if depth > 0 => return Err(self.error("unexpected end of input, missing `}`")),
+                None => return Ok(object),
+            };
+
+            let value = match self.token()? {
+                Some(Token::String(value)) => Value::String(value),
+                Some(Token::Open) if depth >= MAX_DEPTH => {
+                    return Err(self.error("objects are nested too deeply"));
+                }
+                Some(Token::Open) => Value::Object(self.object(depth + 1)?),
+                _ => return Err(self.error(format!("expected a value for key `{key}`"))),
+            };
+
+            object.0.push((key, value));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn string(s: &str) -> Value {
+        Value::String(s.to_owned())
+    }
+
+    #[test]
+    fn parses_app_manifest() {
+        let root = parse(include_str!(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/tests/fixtures/appmanifest_1086940.acf"
+        )))
+        .unwrap();
+
+        let state = root.get_object("appstate").unwrap();
+        assert_eq!(state.get_str("appid"), Some("1086940"));
+        assert_eq!(state.get_str("name"), Some("Baldur's Gate 3"));
+        assert_eq!(
+            state.get_str("LauncherPath"),
+            Some(r"C:\Program Files (x86)\Steam\steam.exe")
+        );
+
+        let depot = state
+            .get_object("InstalledDepots")
+            .and_then(|d| d.get_object("1419651"))
+            .unwrap();
+        assert_eq!(depot.get_str("size"), Some("150326574284"));
+    }
+
+    #[test]
+    fn comments_conditionals_and_unquoted() {
+        let root = parse(
+            r#"
+            // a comment with "quotes" and { braces
+            key value // trailing comment
+            path C:/Games/a/b
+            "cond" "1" [$WIN32]
+            "escaped" "a\"b\\c\td"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            root.0,
+            [
+                ("key".to_owned(), string("value")),
+                ("path".to_owned(), string("C:/Games/a/b")),
+                ("cond".to_owned(), string("1")),
+                ("escaped".to_owned(), string("a\"b\\c\td")),
+            ]
+        );
+    }
+
+    #[test]
+    fn keys_repeat_and_compare_case_insensitively() {
+        let root = parse(r#""A" "1" "a" "2" "b" {}"#).unwrap();
+
+        assert_eq!(root.get_str("a"), Some("1"));
+        assert_eq!(root.iter().count(), 3);
+        assert_eq!(root.get_object("B"), Some(&Object::default()));
+        assert_eq!(root.get_str("b"), None);
+    }
+
+    #[test]
+    fn errors_have_positions() {
+        let cases = [
+            (
+                "\"a\" {\n\"b\" \"c\"\n",
+                "3:1: unexpected end of input, missing `}`",
+            ),
+            ("\"a\" \"b\"\n}", "2:2: unexpected `}`"),
+            ("{", "1:2: expected a key, found `{`"),
+            ("\"a\"", "1:4: expected a value for key `a`"),
+            ("\"a\" \"b", "1:7: unterminated string"),
+        ];
+
+        for (input, expected) in cases {
+            assert_eq!(parse(input).unwrap_err().to_string(), expected, "{input:?}");
+        }
+    }
+
+    #[test]
+    fn deep_nesting_is_an_error() {
+        let ok = format!("{}{}", "a {".repeat(MAX_DEPTH), "}".repeat(MAX_DEPTH));
+        assert!(parse(&ok).is_ok());
+
+        let deep = "a {".repeat(100_000);
+        let e = parse(&deep).unwrap_err();
+        assert_eq!(e.message, "objects are nested too deeply");
+        // just past the first `{` over the limit
+        assert_eq!((e.line, e.column), (1, (MAX_DEPTH + 1) * 3 + 1));
+    }
+}
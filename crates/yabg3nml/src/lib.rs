@@ -1,8 +1,13 @@
 #![feature(windows_process_exit_code_from)]
 
 mod autostart;
+mod bisect;
 mod cli;
+mod commands;
+mod config_watcher;
 mod console;
+mod crashes;
+mod discovery;
 mod event;
 mod is_admin;
 mod loader;
@@ -11,9 +16,12 @@ mod panic;
 mod paths;
 mod privileges;
 mod process_watcher;
+mod profile;
 mod remote_thread;
 mod run;
+mod safe_mode;
 mod server;
+mod session;
 mod setup;
 mod single_instance;
 mod stop_token;
@@ -10,10 +10,10 @@ use native_plugin_lib::{PluginData, Version};
 use shared::{
     config::Config,
     popup::warn_popup,
-    thread_data::{LogData, ThreadData},
+    thread_data::{Buffer, LogData, ThreadData},
     utils::OwnedHandle,
 };
-use tracing::{error, info, level_filters::LevelFilter, trace, trace_span, warn};
+use tracing::{error, info, trace, trace_span, warn};
 use windows::{
     Win32::{
         Foundation::{GetLastError, WAIT_FAILED},
@@ -30,7 +30,9 @@ use windows::{
 
 use crate::remote_thread::RemoteThread;
 use crate::{
+    logging::loader_level,
     process_watcher::Pid,
+    safe_mode,
     server::{AUTH, PID},
     tmp_loader::Loader,
     wapi::get_module_base_ex::GetModuleBaseEx,
@@ -252,17 +254,35 @@ pub fn run_loader(
         "found loader.dll InitLoader addr"
     );
 
+    let safe_mode = safe_mode::is_enabled();
+    if safe_mode {
+        info!("Safe mode is on; the loader will not load any plugins");
+    }
+
     let auth_code = rand::random::<u64>();
     AUTH.store(auth_code, Ordering::Relaxed);
 
     trace!(auth_code, "generated auth");
 
+    // the loader uses our copy of the config instead of reading it from disk, so that it
+    // always sees the same config this run_loader call did
+    let config_json = serde_json::to_vec(config).context("failed to serialize config")?;
+    let Ok(config_ptr) = write_in(&process, config_json.as_ptr(), config_json.len()) else {
+        error!("failed to write config into process");
+        return Ok(());
+    };
+
     let thread_data = ThreadData {
         auth: auth_code,
         log: LogData {
-            level: LevelFilter::current().into(),
+            level: loader_level(),
             target: config.log.target,
         },
+        config: Buffer {
+            ptr: config_ptr.cast(),
+            len: config_json.len(),
+        },
+        safe_mode,
     };
 
     let Ok(ptr) = write_in(&process, &thread_data, size_of::<ThreadData>()) else {
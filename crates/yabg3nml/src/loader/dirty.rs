@@ -3,7 +3,7 @@ use std::path::Path;
 use std::{collections::HashMap, fs::OpenOptions, os::windows::fs::OpenOptionsExt as _};
 
 use eyre::{OptionExt as _, Result};
-use shared::{paths::get_bg3_plugins_dir, utils::OwnedHandle};
+use shared::{paths::get_bg3_plugins_dir, plugins::CACHE_DIR, utils::OwnedHandle};
 use tracing::{trace, trace_span};
 use widestring::U16Str;
 use windows::Win32::Foundation::HANDLE;
@@ -77,6 +77,8 @@ pub fn is_dirty(process: &OwnedHandle, loader: &Path) -> Result<bool> {
     trace!(plugins_dir = %plugins_dir.display(), "checking dll path against dirs");
 
     let plugins_dir_id = dir_id(&plugins_dir).ok_or_eyre("failed to get id for plugins_dir")?;
+    // only exists once a plugin was shadow copied
+    let cache_dir_id = dir_id(&plugins_dir.join(CACHE_DIR));
     cache_id_map.insert(plugins_dir, plugins_dir_id);
 
     let mut is_plugin = move |path: &U16Str| -> Result<bool> {
@@ -89,26 +91,29 @@ pub fn is_dirty(process: &OwnedHandle, loader: &Path) -> Result<bool> {
             return Ok(false);
         }
 
-        // get parent dir
-        let Some(parent) = path.parent() else {
-            return Ok(false);
-        };
-
-        let id = match cache_id_map.get(parent) {
-            Some(id) => *id,
-            None => {
-                let Some(path_id) = dir_id(parent) else {
-                    return Ok(false);
-                };
-
-                cache_id_map.insert(parent.to_path_buf(), path_id);
-
-                path_id
+        // plugins are either directly in the plugins dir, or in their own folder inside it.
+        // shadow copies are in .cache/<session>/<hash>/
+        for (depth, dir) in path.ancestors().skip(1).take(3).enumerate() {
+            let id = match cache_id_map.get(dir) {
+                Some(id) => *id,
+                None => {
+                    let Some(path_id) = dir_id(dir) else {
+                        continue;
+                    };
+
+                    cache_id_map.insert(dir.to_path_buf(), path_id);
+
+                    path_id
+                }
+            };
+
+            // if plugins dir is the same id as this one, then this is a plugin inside our plugins dir~
+            if (depth < 2 && plugins_dir_id == id) || cache_dir_id == Some(id) {
+                return Ok(true);
             }
-        };
+        }
 
-        // if plugins dir is the same id as this one, then this is a plugin inside our plugins dir~
-        Ok(plugins_dir_id == id)
+        Ok(false)
     };
 
     let mut detected = false;
@@ -1,20 +1,41 @@
-use std::{env, path::Path};
+pub mod plugins;
+
+use std::{env, path::Path, sync::OnceLock};
 
 use eyre::Result;
-use shared::config::Config;
-use tracing::level_filters::LevelFilter;
+use shared::{
+    config::Config,
+    pipe::commands::{Control, Level},
+};
+use tracing::{level_filters::LevelFilter, trace};
 use tracing_appender::non_blocking::WorkerGuard;
 use tracing_subscriber::EnvFilter;
 
+use crate::server;
+
+type ReloadFn = Box<dyn Fn(EnvFilter) -> Result<()> + Send + Sync>;
+
+/// Swaps out the filter of the global subscriber
+static RELOAD: OnceLock<ReloadFn> = OnceLock::new();
+
+const LOG_ENV: &str = "YABG3NML_LOG";
+
+fn make_filter(level: &str) -> EnvFilter {
+    EnvFilter::builder()
+        .with_default_directive(LevelFilter::INFO.into())
+        .parse_lossy(level)
+}
+
 pub fn setup_logs<P: AsRef<Path>>(config: &Config, plugins_dir: P) -> Result<Option<WorkerGuard>> {
     let mut worker_guard: Option<WorkerGuard> = None;
 
+    let logs_dir = plugins_dir.as_ref().join("logs");
+    plugins::init(&logs_dir, config);
+
     // env var takes precedence over config value
-    let env = env::var("YABG3NML_LOG");
-    let env = env.as_deref().unwrap_or(&config.log.level);
-    let filter = EnvFilter::builder()
-        .with_default_directive(LevelFilter::INFO.into())
-        .parse_lossy(env);
+    let env = env::var(LOG_ENV);
+    let env = env.as_deref().unwrap_or(config.log_level());
+    let filter = make_filter(env);
 
     if cfg!(debug_assertions) || config.core.cli {
         #[cfg(not(debug_assertions))]
@@ -23,27 +44,64 @@ pub fn setup_logs<P: AsRef<Path>>(config: &Config, plugins_dir: P) -> Result<Opt
             debug_console("Yet Another BG3 Native Mod Loader Debug Console")?;
         }
 
-        tracing_subscriber::fmt()
+        let builder = tracing_subscriber::fmt()
             .with_env_filter(filter)
             .with_target(config.log.target)
             .without_time()
-            .init();
-    } else {
-        let plugins_dir = plugins_dir.as_ref();
-        let logs_dir = plugins_dir.join("logs");
+            .with_filter_reloading();
+
+        let handle = builder.reload_handle();
+        _ = RELOAD.set(Box::new(move |f| Ok(handle.reload(f)?)));
 
+        builder.init();
+    } else {
         let file_appender = tracing_appender::rolling::daily(logs_dir, "ya-bg3-native-mod-loader");
         let (non_blocking, _guard) = tracing_appender::non_blocking(file_appender);
 
         worker_guard = Some(_guard);
-        tracing_subscriber::fmt()
+        let builder = tracing_subscriber::fmt()
             .with_env_filter(filter)
             .with_writer(non_blocking)
             .with_target(config.log.target)
             .without_time()
             .with_ansi(false)
-            .init();
+            .with_filter_reloading();
+
+        let handle = builder.reload_handle();
+        _ = RELOAD.set(Box::new(move |f| Ok(handle.reload(f)?)));
+
+        builder.init();
     }
 
     Ok(worker_guard)
 }
+
+/// The level the loader in the game logs at. Plugin logs may want records the main log doesn't
+pub fn loader_level() -> Level {
+    LevelFilter::current().max(plugins::max_level()).into()
+}
+
+/// Tell the loader in a running game that [`loader_level`] changed from `old`, since it
+/// filters records before sending them
+pub fn sync_loader_level(old: Level) {
+    let level = loader_level();
+    if LevelFilter::from(level) != LevelFilter::from(old) {
+        server::control(Control::LogLevel { level });
+    }
+}
+
+/// Apply a new `[log]level` to the running logger
+///
+/// Noop if the level was set through the env var, since that takes precedence
+pub fn reload_level(level: &str) -> Result<()> {
+    if env::var(LOG_ENV).is_ok() {
+        trace!("{LOG_ENV} is set; ignoring log level change");
+        return Ok(());
+    }
+
+    if let Some(reload) = RELOAD.get() {
+        reload(make_filter(level))?;
+    }
+
+    Ok(())
+}
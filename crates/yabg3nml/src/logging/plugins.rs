@@ -0,0 +1,155 @@
+//! Every plugin's logs, also written to `logs/plugins/<name>.log`
+//!
+//! Each file is started over when a game starts loading plugins, and filtered by
+//! `log_level` in the plugin's [plugins.<name>] table
+
+use std::{
+    collections::HashMap,
+    fmt::Write as _,
+    fs::{self, File},
+    io::Write as _,
+    path::{Path, PathBuf},
+    sync::{LazyLock, OnceLock},
+};
+
+use sayuri::sync::Mutex;
+use shared::{config::Config, pipe::commands::LogMsg};
+use tracing::{error, level_filters::LevelFilter};
+use unicase::UniCase;
+
+static DIR: OnceLock<PathBuf> = OnceLock::new();
+
+/// [plugins.<name>]log_level of every plugin which has one
+static LEVELS: LazyLock<Mutex<HashMap<UniCase<String>, LevelFilter>>> =
+    LazyLock::new(Mutex::default);
+
+/// The open log files of this game session. None if one couldn't be created, so it isn't
+/// retried for every record
+static FILES: LazyLock<Mutex<HashMap<UniCase<String>, Option<File>>>> =
+    LazyLock::new(Mutex::default);
+
+pub fn init(logs_dir: &Path, config: &Config) {
+    _ = DIR.set(logs_dir.join("plugins"));
+    set_levels(config);
+}
+
+/// Apply the plugin log levels of a new config
+pub fn set_levels(config: &Config) {
+    let levels = config
+        .plugins
+        .iter()
+        .filter_map(|(name, p)| {
+            let level = p.log_level.as_deref()?.parse().ok()?;
+            Some((UniCase::new(name.clone()), level))
+        })
+        .collect();
+
+    *LEVELS.lock() = levels;
+}
+
+/// The most verbose level any plugin log wants, so the loader sends those records too
+pub fn max_level() -> LevelFilter {
+    LEVELS
+        .lock()
+        .values()
+        .copied()
+        .max()
+        .unwrap_or(LevelFilter::OFF)
+}
+
+/// A game started loading plugins; their logs start over
+pub fn new_session() {
+    FILES.lock().clear();
+}
+
+/// Append a record to the log of the plugin it is attributed to
+pub fn write(plugin: &str, msg: &LogMsg, message: &str) {
+    let Some(dir) = DIR.get() else {
+        return;
+    };
+
+    let name = UniCase::new(plugin.to_owned());
+
+    let level = LEVELS
+        .lock()
+        .get(&name)
+        .copied()
+        .unwrap_or_else(LevelFilter::current);
+
+    let record_level = LevelFilter::from(msg.level);
+    if record_level == LevelFilter::OFF || record_level > level {
+        return;
+    }
+
+    let mut line = format!("{:>5} ", record_level.to_string().to_uppercase());
+    if let Some(target) = &msg.target {
+        _ = write!(line, "{target}: ");
+    }
+
+    line.push_str(message);
+
+    let mut fields = msg.fields.iter().collect::<Vec<_>>();
+    fields.sort();
+    for (key, value) in fields {
+        _ = write!(line, " {key}={value}");
+    }
+
+    let mut files = FILES.lock();
+    let file = files.entry(name).or_insert_with(|| {
+        let path = dir.join(format!("{}.log", file_name(plugin)));
+
+        let file = fs::create_dir_all(dir).and_then(|_| File::create(&path));
+        match file {
+            Ok(file) => Some(file),
+            Err(e) => {
+                error!(path = %path.display(), "failed to create plugin log: {e}");
+                None
+            }
+        }
+    });
+
+    if let Some(file) = file {
+        _ = writeln!(file, "{line}");
+    }
+}
+
+/// A plugin name usable as a file name. Records may be attributed to names which aren't
+/// plugin files, like `<unknown>`, or a `plugin` field a plugin set itself
+fn file_name(plugin: &str) -> String {
+    let name = plugin
+        .chars()
+        .map(|c| match c {
+            '<' | '>' | ':' | '"' | '/' | '\\' | '|' | '?' | '*' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect::<String>();
+
+    // windows drops trailing dots and spaces, and `..` would still leave the folder
+    let name = name.trim_end_matches(['.', ' ']);
+    if name.is_empty() {
+        "_".to_owned()
+    } else {
+        name.to_owned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn file_names() {
+        for (plugin, name) in [
+            ("FooBar", "FooBar"),
+            ("<unknown>", "_unknown_"),
+            ("../../evil", ".._.._evil"),
+            (r"C:\Windows\evil", "C__Windows_evil"),
+            ("..", "_"),
+            ("trailing. ", "trailing"),
+            ("", "_"),
+        ] {
+            assert_eq!(file_name(plugin), name, "{plugin}");
+        }
+    }
+}
@@ -4,9 +4,49 @@ use std::{
     path::{Path, PathBuf},
 };
 
-use shared::{config::Config, popup::fatal_popup};
-use tracing::{error, trace};
-use unicase::UniCase;
+use eyre::Result;
+use serde::{Deserialize, Serialize};
+use shared::{
+    config::{Config, ConfigEditor, GameExe, config_path, write_atomic},
+    paths::get_bg3_plugins_dir,
+    popup::{fatal_popup, warn_popup, yes_no_popup},
+};
+use tracing::{error, info, trace};
+
+use crate::discovery;
+
+/// Remembers discovered installs the user chose not to save, in the plugins dir
+const DECLINED_NAME: &str = "declined_installs.toml";
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+struct Declined {
+    roots: Vec<PathBuf>,
+}
+
+impl Declined {
+    fn path() -> Result<PathBuf> {
+        Ok(get_bg3_plugins_dir()?.join(DECLINED_NAME))
+    }
+
+    /// Nothing was declined if it can't be read; at worst the user is asked again
+    fn load() -> Self {
+        Self::path()
+            .ok()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .and_then(|data| toml::from_str(&data).ok())
+            .unwrap_or_default()
+    }
+
+    fn save(&self) -> Result<()> {
+        let data = format!(
+            "# Discovered game installs not to offer as install_root again. Safe to delete\n\n{}",
+            toml::to_string_pretty(self)?
+        );
+
+        write_atomic(&Self::path()?, &data)
+    }
+}
 
 #[allow(dead_code)]
 pub struct Bg3Exes {
@@ -19,56 +59,117 @@ pub fn get_game_binary_paths(config: &Config) -> Bg3Exes {
     let bin = config.core.install_root.join("bin");
 
     // first check current directory or 1 directory up for exes before using config value
-    let check_dirs = [".", "..", &bin.to_string_lossy()];
+    let check_dirs = [Path::new("."), Path::new(".."), &bin];
     for dir in check_dirs {
-        let path = Path::new(dir);
-
-        let bg3 = path.join("bg3.exe");
-        let bg3_dx11 = path.join("bg3_dx11.exe");
-
-        if bg3.is_file() && bg3_dx11.is_file() {
-            let bg3 = match fs::canonicalize(&bg3) {
-                Ok(p) => p,
-                Err(e) => {
-                    error!(error = %e, path = %bg3.display(), "failed to canonicalize");
-                    continue;
-                }
-            };
-
-            let bg3_dx11 = match fs::canonicalize(&bg3_dx11) {
-                Ok(p) => p,
-                Err(e) => {
-                    error!(error = %e, path = %bg3_dx11.display(), "failed to canonicalize");
-                    continue;
-                }
-            };
-
-            // canonicalize adds this to the prefix, but we don't want it
-            let bg3 = bg3
-                .to_string_lossy()
-                .strip_prefix(r"\\?\")
-                .map(ToOwned::to_owned)
-                .unwrap_or_else(|| bg3.to_string_lossy().to_string());
-
-            let bg3_dx11 = bg3_dx11
-                .to_string_lossy()
-                .strip_prefix(r"\\?\")
-                .map(ToOwned::to_owned)
-                .unwrap_or_else(|| bg3_dx11.to_string_lossy().to_string());
-
-            trace!(path = %bg3, "Looking for bg3");
-            trace!(path = %bg3_dx11, "Looking for bg3_dx11");
-
-            return Bg3Exes { bg3, bg3_dx11 };
+        if let Some(exes) = find_exes(dir) {
+            return exes;
         }
     }
 
+    // the configured path is wrong; see if steam or gog know where the game is
+    let discovery = discovery::discover();
+    if let Some(best) = discovery.best()
+        && let Some(exes) = find_exes(&best.root.join("bin"))
+    {
+        offer_install_root(&best.root, &discovery.reason().unwrap_or_default());
+        return exes;
+    }
+
     fatal_popup(
         "Path error",
-        "Failed to resolve `install_root` path. Does the path (or its target) exist and point to a directory? And does this program have permissions to read that path?",
+        format!(
+            "Failed to resolve `install_root` path. Does the path (or its target) exist and point to a directory? And does this program have permissions to read that path?\n\nAutomatic discovery also failed:\n{}",
+            discovery.report()
+        ),
     );
 }
 
+/// Ask whether a discovered install should be saved as `[core]install_root`, unless the
+/// user already said no for this install
+fn offer_install_root(root: &Path, reason: &str) {
+    let mut declined = Declined::load();
+    if declined.roots.iter().any(|r| r == root) {
+        trace!(path = %root.display(), "saving discovered install_root was declined before");
+        return;
+    }
+
+    let save = yes_no_popup(
+        "Game found",
+        format!(
+            "`install_root` in config.toml does not point to the game, but it was found at:\n\n{}\n\nChosen because {reason}.\n\nSave this as `install_root` in config.toml? If not, you won't be asked about this install again.",
+            root.display()
+        ),
+    );
+
+    if !save {
+        declined.roots.push(root.to_owned());
+        if let Err(e) = declined.save() {
+            error!("failed to remember declined install_root: {e}");
+        }
+
+        return;
+    }
+
+    let result = config_path().and_then(|path| {
+        let mut editor = ConfigEditor::open(path)?;
+        editor.set_install_root(root)?;
+        editor.save()
+    });
+    match result {
+        Ok(()) => info!(path = %root.display(), "saved discovered install_root"),
+        Err(e) => {
+            error!("failed to save install_root: {e}");
+            warn_popup(
+                "Failed to save config",
+                format!("Failed to save `install_root` to config.toml:\n\n{e}"),
+            );
+        }
+    }
+}
+
+fn find_exes(dir: &Path) -> Option<Bg3Exes> {
+    let bg3 = dir.join("bg3.exe");
+    let bg3_dx11 = dir.join("bg3_dx11.exe");
+
+    if !bg3.is_file() || !bg3_dx11.is_file() {
+        return None;
+    }
+
+    let bg3 = match fs::canonicalize(&bg3) {
+        Ok(p) => p,
+        Err(e) => {
+            error!(error = %e, path = %bg3.display(), "failed to canonicalize");
+            return None;
+        }
+    };
+
+    let bg3_dx11 = match fs::canonicalize(&bg3_dx11) {
+        Ok(p) => p,
+        Err(e) => {
+            error!(error = %e, path = %bg3_dx11.display(), "failed to canonicalize");
+            return None;
+        }
+    };
+
+    // canonicalize adds this to the prefix, but we don't want it
+    let bg3 = bg3
+        .to_string_lossy()
+        .strip_prefix(r"\\?\")
+        .map(ToOwned::to_owned)
+        .unwrap_or_else(|| bg3.to_string_lossy().to_string());
+
+    let bg3_dx11 = bg3_dx11
+        .to_string_lossy()
+        .strip_prefix(r"\\?\")
+        .map(ToOwned::to_owned)
+        .unwrap_or_else(|| bg3_dx11.to_string_lossy().to_string());
+
+    trace!(path = %bg3, "Looking for bg3");
+    trace!(path = %bg3_dx11, "Looking for bg3_dx11");
+
+    Some(Bg3Exes { bg3, bg3_dx11 })
+}
+
 #[allow(dead_code)]
 #[derive(Copy, Clone)]
 pub enum Bg3Exe {
@@ -91,20 +192,10 @@ impl Display for Bg3Exe {
 
 impl From<&Path> for Bg3Exe {
     fn from(value: &Path) -> Self {
-        let Some(value) = value.file_name() else {
-            return Self::None;
-        };
-
-        let value = UniCase::new(value.to_string_lossy());
-        let vulkan = UniCase::new("bg3.exe");
-        let dx11 = UniCase::new("bg3_dx11.exe");
-
-        if value == vulkan {
-            Self::Vulkan
-        } else if value == dx11 {
-            Self::Dx11
-        } else {
-            Self::None
+        match GameExe::from_path(value) {
+            Some(GameExe::Vulkan) => Self::Vulkan,
+            Some(GameExe::Dx11) => Self::Dx11,
+            None => Self::None,
         }
     }
 }
@@ -0,0 +1,40 @@
+use std::sync::LazyLock;
+
+use sayuri::sync::Mutex;
+use shared::config::Config;
+use tracing::info;
+
+/// Profile picked for this session with `--profile` or the tray menu.
+/// Takes precedence over [core]active_profile
+///
+/// `None` means there is no override, `Some(None)` means no profile
+static OVERRIDE: LazyLock<Mutex<Option<Option<String>>>> = LazyLock::new(Mutex::default);
+
+/// Use `profile` for the rest of this session instead of [core]active_profile
+pub fn set_override(profile: Option<String>) {
+    info!(?profile, "selected profile");
+    *OVERRIDE.lock() = Some(profile);
+}
+
+/// The config with the session's profile override applied
+pub fn apply(config: &Config) -> Config {
+    let mut config = config.clone();
+
+    if let Some(profile) = &*OVERRIDE.lock() {
+        config.core.active_profile = profile.clone();
+    }
+
+    config
+}
+
+/// Drop the override if its profile was removed from config.toml since it was picked
+pub fn forget_removed(config: &Config) {
+    let mut current = OVERRIDE.lock();
+
+    if let Some(Some(name)) = &*current
+        && !config.profiles.contains_key(name)
+    {
+        info!(profile = name, "selected profile no longer exists");
+        *current = None;
+    }
+}
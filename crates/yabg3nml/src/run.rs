@@ -1,15 +1,24 @@
 use std::time::Duration;
 
 use eyre::Result;
-use shared::popup::{MessageBoxIcon, display_popup, fatal_popup};
+use shared::{
+    config::{config_path, set_cli_overrides},
+    popup::{MessageBoxIcon, display_popup, fatal_popup},
+};
 use tracing::{error, trace};
 
 #[allow(unused_imports)]
 use crate::{
+    bisect,
+    cli::Args,
+    commands::{print_config_schema, run_command},
+    config_watcher::{ConfigWatcher, LiveConfig},
+    crashes,
     event::Event,
     loader::run_loader,
     paths,
     process_watcher::{CallType, ProcessWatcher, ProcessWatcherResults, Timeout},
+    profile, safe_mode,
     setup::init,
     single_instance::SingleInstance,
     tray::AppTray,
@@ -23,17 +32,53 @@ pub enum RunType {
 
 /// Process watcher entry point
 pub fn run(run_type: RunType) -> Result<()> {
+    let args = Args::parse();
+
+    let mut overrides = args.set.clone();
+    if args.cli {
+        overrides.push("core.cli=true".to_owned());
+    }
+
+    if let Err(e) = set_cli_overrides(&overrides) {
+        fatal_popup("Invalid --set", format!("{e}"));
+    }
+
+    // commands only read state, so they may run alongside a running instance
+    if args.print_config_schema {
+        return print_config_schema();
+    }
+
+    if let Some(command) = &args.command {
+        return run_command(command);
+    }
+
     // This prohibits multiple app instances
     let _singleton = SingleInstance::new();
     let _event = Event::new()?;
 
-    #[cfg(feature = "test-injection")]
-    let args: crate::cli::Args = argh::from_env();
+    if let Some(profile) = &args.profile {
+        profile::set_override(Some(profile.clone()));
+    }
+
+    if args.safe_mode {
+        safe_mode::set_override(true);
+    }
 
     let mut init = init()?;
     let _loader_lock = init.loader.file.take();
     let _worker_guard = init.worker.take();
 
+    if let Some(profile) = &args.profile
+        && !init.config.profiles.contains_key(profile)
+    {
+        fatal_popup(
+            "Unknown profile",
+            format!(
+                "Profile `{profile}` passed to --profile does not exist in [profiles] in config.toml"
+            ),
+        );
+    }
+
     #[cfg(not(feature = "test-injection"))]
     let processes = {
         use paths::{Bg3Exes, get_game_binary_paths};
@@ -44,6 +89,16 @@ pub fn run(run_type: RunType) -> Result<()> {
     #[cfg(feature = "test-injection")]
     let processes = &[args.inject];
 
+    let config = LiveConfig::new(init.config.clone());
+
+    // only the long running watcher needs to pick up config edits
+    let config_watcher = if matches!(run_type, RunType::Watcher) {
+        let watcher = ConfigWatcher::new(config_path()?, Duration::from_secs(1), config.clone());
+        Some(watcher.run())
+    } else {
+        None
+    };
+
     let (polling_rate, timeout, oneshot, wait_for_init) = if matches!(run_type, RunType::Watcher) {
         // watcher tool
         (Duration::from_secs(2), Timeout::None, false, false)
@@ -61,11 +116,24 @@ pub fn run(run_type: RunType) -> Result<()> {
         watcher_token: token,
         watcher_handle,
         timeout_token,
-    } = ProcessWatcher::new(processes, polling_rate, timeout, oneshot).run(
+    } = ProcessWatcher::new(processes, polling_rate, timeout, oneshot).run({
+        let config = config.clone();
         move |call| match call {
             CallType::Pid(pid) => {
                 trace!(pid, "Received callback for pid, now loading");
-                let res = run_loader(init.config, pid, &init.loader, true, wait_for_init);
+                let mut config = profile::apply(&config.get());
+
+                // the game is already starting, so plugins can't wait on a popup. The injector
+                // exits right after loading, which would close one left in the background
+                if matches!(run_type, RunType::Watcher) {
+                    crashes::offer_quarantine_later(&config);
+                }
+
+                if let Err(e) = bisect::apply(&mut config) {
+                    error!("failed to apply bisect selection: {e:#}");
+                }
+
+                let res = run_loader(&config, pid, &init.loader, true, wait_for_init);
                 if let Err(e) = res {
                     error!(err = %e, "run_loader failed");
                     fatal_popup(
@@ -75,6 +143,11 @@ pub fn run(run_type: RunType) -> Result<()> {
                         ),
                     );
                 }
+
+                // the injector exits right after loading, so it can't see the game exit
+                if matches!(run_type, RunType::Watcher) {
+                    crashes::watch(pid);
+                }
             }
 
             // only fires with injector
@@ -93,10 +166,10 @@ This can happen for 1 of 3 reasons:
                     MessageBoxIcon::Error,
                 );
             }
-        },
-    );
+        }
+    });
 
-    let tray = AppTray::run(token, timeout_token, run_type);
+    let tray = AppTray::run(token, timeout_token, run_type, config);
     if matches!(run_type, RunType::Watcher) {
         // will exit when Quit clicked
         _ = tray.join();
@@ -105,5 +178,10 @@ This can happen for 1 of 3 reasons:
     // will exit when signal sent
     _ = watcher_handle.join();
 
+    if let Some((token, handle)) = config_watcher {
+        token.stop();
+        _ = handle.join();
+    }
+
     Ok(())
 }
@@ -0,0 +1,30 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use shared::paths::get_bg3_plugins_dir;
+use tracing::info;
+
+/// A file by one of these names in the plugins dir turns on safe mode, including for autostart
+pub const SENTINELS: &[&str] = &["safe_mode", "safe_mode.txt"];
+
+/// Safe mode picked for this session with `--safe-mode` or the tray menu
+static OVERRIDE: AtomicBool = AtomicBool::new(false);
+
+/// Turn safe mode on or off for the rest of this session
+pub fn set_override(enabled: bool) {
+    info!(enabled, "set safe mode");
+    OVERRIDE.store(enabled, Ordering::Relaxed);
+}
+
+/// Whether the loader should be injected without loading any plugins
+pub fn is_enabled() -> bool {
+    OVERRIDE.load(Ordering::Relaxed) || sentinel_exists()
+}
+
+/// Whether safe mode is on through a sentinel file, which the tray can't turn off
+pub fn sentinel_exists() -> bool {
+    let Ok(plugins_dir) = get_bg3_plugins_dir() else {
+        return false;
+    };
+
+    SENTINELS.iter().any(|s| plugins_dir.join(s).is_file())
+}
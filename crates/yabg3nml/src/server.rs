@@ -1,30 +1,53 @@
 use std::{
     convert::Infallible,
     io,
-    sync::atomic::{AtomicU32, AtomicU64, Ordering},
+    sync::{
+        OnceLock,
+        atomic::{AtomicU32, AtomicU64, Ordering},
+    },
 };
 
 use shared::pipe::{
-    Server,
-    commands::{Level, Receive},
+    Controller, Server,
+    commands::{Control, Level, Receive},
 };
 use tracing::{debug, error, info, trace, trace_span, warn};
 
+use crate::{logging, session};
+
 pub static AUTH: AtomicU64 = AtomicU64::new(0);
 pub static PID: AtomicU32 = AtomicU32::new(0);
+static CONTROLLER: OnceLock<Controller> = OnceLock::new();
+
+/// Send a message to the loader in the game, if one is connected
+pub fn control(control: Control) {
+    match CONTROLLER.get() {
+        Some(controller) => controller.send(control),
+        None => trace!(?control, "server is not running, dropping control"),
+    }
+}
 
 pub fn server() -> io::Result<Infallible> {
     let mut server = Server::new();
+    _ = CONTROLLER.set(server.controller());
 
     let cb = |cmd| {
         let span = trace_span!("dll");
         let _guard = span.enter();
 
+        let finished = session::record(&cmd);
+
         match cmd {
             Receive::Log(mut msg) => {
+                let message = msg.fields.remove("message").unwrap_or_default();
+
+                if let Some(plugin) = &msg.plugin {
+                    logging::plugins::write(plugin, &msg, &message);
+                }
+
                 let filename = msg.filename.unwrap_or_default();
                 let line_number = msg.line_number.unwrap_or_default();
-                let message = msg.fields.remove("message").unwrap_or_default();
+                let plugin = msg.plugin;
                 let target = msg.target;
                 let span = msg.span;
                 let spans = msg.spans;
@@ -34,26 +57,68 @@ pub fn server() -> io::Result<Infallible> {
                     Level::Off => (),
 
                     Level::Trace => {
-                        trace!(target: "loader", ?target, %filename, line_number, ?span, ?spans, ?fields, "{message}")
+                        trace!(target: "loader", ?target, plugin = plugin.as_deref(), %filename, line_number, ?span, ?spans, ?fields, "{message}")
                     }
 
                     Level::Debug => {
-                        debug!(target: "loader", ?target, %filename, line_number, ?span, ?spans, ?fields, "{message}")
+                        debug!(target: "loader", ?target, plugin = plugin.as_deref(), %filename, line_number, ?span, ?spans, ?fields, "{message}")
                     }
 
                     Level::Info => {
-                        info!(target: "loader", ?target, %filename, line_number, ?span, ?spans, ?fields, "{message}")
+                        info!(target: "loader", ?target, plugin = plugin.as_deref(), %filename, line_number, ?span, ?spans, ?fields, "{message}")
                     }
 
                     Level::Warn => {
-                        warn!(target: "loader", ?target, %filename, line_number, ?span, ?spans, ?fields, "{message}")
+                        warn!(target: "loader", ?target, plugin = plugin.as_deref(), %filename, line_number, ?span, ?spans, ?fields, "{message}")
                     }
 
                     Level::Error => {
-                        error!(target: "loader", ?target, %filename, line_number, ?span, ?spans, ?fields, "{message}")
+                        error!(target: "loader", ?target, plugin = plugin.as_deref(), %filename, line_number, ?span, ?spans, ?fields, "{message}")
                     }
                 }
             }
+
+            Receive::LoadStarted { pid } => {
+                logging::plugins::new_session();
+                debug!(target: "loader", pid, "loader started loading plugins");
+            }
+
+            Receive::PluginDiscovered { name, path } => {
+                trace!(target: "loader", plugin = %name, path = %path.display(), "discovered plugin");
+            }
+
+            Receive::PluginSkipped { name, reason } => {
+                debug!(target: "loader", plugin = %name, %reason, "plugin skipped");
+            }
+
+            Receive::PluginLoading { name } => {
+                trace!(target: "loader", plugin = %name, "plugin loading");
+            }
+
+            Receive::PluginLoaded {
+                name,
+                metadata,
+                load,
+                init,
+            } => {
+                debug!(target: "loader", plugin = %name, ?metadata, ?load, ?init, "plugin loaded");
+            }
+
+            Receive::PluginExited { name } => {
+                debug!(target: "loader", plugin = %name, "plugin load thread exited");
+            }
+
+            Receive::PluginFailed { name, error } => {
+                debug!(target: "loader", plugin = %name, %error, "plugin failed");
+            }
+
+            Receive::LoadComplete(summary) => {
+                info!(target: "loader", "{summary}");
+            }
+        }
+
+        if let Some(session) = finished {
+            info!(pid = session.pid, "Plugins: {session}");
         }
     };
 
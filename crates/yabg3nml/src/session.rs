@@ -0,0 +1,143 @@
+use std::{
+    fmt::{self, Display},
+    sync::LazyLock,
+    time::{Duration, Instant},
+};
+
+use sayuri::sync::Mutex;
+use shared::pipe::commands::{PluginMetadata, Receive};
+use unicase::UniCase;
+
+/// What the loader reported about the current game process
+static SESSION: LazyLock<Mutex<Option<Session>>> = LazyLock::new(Mutex::default);
+
+#[derive(Debug, Clone)]
+pub enum Outcome {
+    /// Found, but not loaded or skipped yet
+    Discovered,
+    Skipped(String),
+    /// LoadLibrary or Init is running
+    Loading,
+    Loaded(PluginMetadata),
+    /// Its load thread exited before finishing, but its dll is loaded
+    Exited,
+    Failed(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct Session {
+    pub pid: u32,
+    /// When the loader started
+    pub started: Instant,
+    /// Every plugin the loader reported, in the order it first mentioned them
+    pub plugins: Vec<(String, Outcome)>,
+    /// Whether the loader finished loading every plugin
+    pub complete: bool,
+}
+
+impl Session {
+    fn count(&self, f: impl Fn(&Outcome) -> bool) -> usize {
+        self.plugins.iter().filter(|(_, o)| f(o)).count()
+    }
+
+    fn set(&mut self, name: String, outcome: Outcome) {
+        match self
+            .plugins
+            .iter_mut()
+            .find(|(n, _)| UniCase::new(n) == UniCase::new(&name))
+        {
+            Some((_, o)) => *o = outcome,
+            None => self.plugins.push((name, outcome)),
+        }
+    }
+}
+
+impl Display for Session {
+    /// e.g. `7 loaded, 1 failed, 2 skipped`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let loaded = self.count(|o| matches!(o, Outcome::Loaded(_) | Outcome::Exited));
+        let failed = self.count(|o| matches!(o, Outcome::Failed(_)));
+        let skipped = self.count(|o| matches!(o, Outcome::Skipped(_)));
+
+        write!(f, "{loaded} loaded, {failed} failed")?;
+
+        if skipped > 0 {
+            write!(f, ", {skipped} skipped")?;
+        }
+
+        if !self.complete {
+            write!(f, " (loading)")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Update the session from a loader event. Returns the finished session on
+/// [`Receive::LoadComplete`]
+pub fn record(event: &Receive) -> Option<Session> {
+    let mut session = SESSION.lock();
+
+    if let Receive::LoadStarted { pid } = event {
+        *session = Some(Session {
+            pid: *pid,
+            started: Instant::now(),
+            plugins: Vec::new(),
+            complete: false,
+        });
+
+        return None;
+    }
+
+    let session = session.as_mut()?;
+
+    match event {
+        Receive::PluginDiscovered { name, .. } => session.set(name.clone(), Outcome::Discovered),
+
+        Receive::PluginSkipped { name, reason } => {
+            session.set(name.clone(), Outcome::Skipped(reason.clone()))
+        }
+
+        Receive::PluginLoading { name } => session.set(name.clone(), Outcome::Loading),
+
+        Receive::PluginLoaded { name, metadata, .. } => {
+            session.set(name.clone(), Outcome::Loaded(metadata.clone()))
+        }
+
+        Receive::PluginExited { name } => session.set(name.clone(), Outcome::Exited),
+
+        Receive::PluginFailed { name, error } => {
+            session.set(name.clone(), Outcome::Failed(error.clone()))
+        }
+
+        Receive::LoadComplete(_) => {
+            session.complete = true;
+            return Some(session.clone());
+        }
+
+        Receive::Log(_) | Receive::LoadStarted { .. } => (),
+    }
+
+    None
+}
+
+/// Plugins which were loaded or still loading in the game process `pid`, and how long ago
+/// the loader started. None if the loader never reported anything for it
+pub fn plugins_in(pid: u32) -> Option<(Vec<(String, Outcome)>, Duration)> {
+    let session = SESSION.lock();
+    let session = session.as_ref().filter(|s| s.pid == pid)?;
+
+    let plugins = session
+        .plugins
+        .iter()
+        .filter(|(_, o)| matches!(o, Outcome::Loading | Outcome::Loaded(_) | Outcome::Exited))
+        .cloned()
+        .collect();
+
+    Some((plugins, session.started.elapsed()))
+}
+
+/// The latest session's status, e.g. `7 loaded, 1 failed`
+pub fn status() -> Option<String> {
+    SESSION.lock().as_ref().map(ToString::to_string)
+}
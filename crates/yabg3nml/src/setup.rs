@@ -1,8 +1,8 @@
-use std::{process, thread};
+use std::{fs, process, thread};
 
 use eyre::{Context as _, Result};
 use shared::{
-    config::{Config, ConfigState, get_config},
+    config::{Config, ConfigState, check, config_path, get_config},
     paths::{get_bg3_local_dir, get_bg3_plugins_dir},
     popup::{MessageBoxIcon, display_popup, fatal_popup},
 };
@@ -15,12 +15,12 @@ use crate::{
     logging::setup_logs,
     panic::set_hook,
     privileges::set_privilege,
+    profile,
     server::server,
     tmp_loader::{Loader, init_loader},
 };
 
 pub struct InitData {
-    #[cfg_attr(feature = "test-injection", allow(dead_code))]
     pub config: &'static Config,
     pub worker: Option<WorkerGuard>,
     pub loader: Loader,
@@ -55,11 +55,37 @@ pub fn init() -> Result<InitData> {
     let config = match get_config() {
         Ok(ConfigState::Exists(c)) => c,
 
+        Ok(ConfigState::Migrated {
+            config,
+            migration,
+            backup,
+        }) => {
+            let changes = migration
+                .changes
+                .iter()
+                .map(|c| format!("- {c}"))
+                .collect::<Vec<_>>()
+                .join("\n");
+
+            display_popup(
+                "Config Upgraded",
+                format!(
+                    "`config.toml` was upgraded from version {} to {}. The original was backed up to\n{}\n\nChanges:\n{changes}",
+                    migration.from,
+                    migration.to,
+                    backup.display()
+                ),
+                MessageBoxIcon::Info,
+            );
+
+            config
+        }
+
         Ok(ConfigState::New(_)) if first_time => {
             display_popup(
                 "Finish Setup",
                 format!(
-                    "The plugins folder was just created at\n{}\n\nTo install plugins, place the plugin dll files inside the plugins folder.\n\nPlease also double-check `config.toml` in the plugins folder. install_root in the config likely needs to be adjusted to the correct path. If the tools are placed in <bg3_root>/bin or <bg3_root>/bin/subfolder, the tools will automatically detect the correct root path and do not require install_root to be configured, otherwise you need to configure install_root",
+                    "The plugins folder was just created at\n{}\n\nTo install plugins, place the plugin dll files inside the plugins folder. Plugins which come with extra files can have their own folder instead, e.g. Plugins/FooBar/FooBar.dll.\n\nPlease also double-check `config.toml` in the plugins folder. install_root in the config likely needs to be adjusted to the correct path. If the tools are placed in <bg3_root>/bin or <bg3_root>/bin/subfolder, the tools will automatically detect the correct root path and do not require install_root to be configured, otherwise you need to configure install_root",
                     plugins_dir.display()
                 ),
                 MessageBoxIcon::Info,
@@ -79,17 +105,33 @@ pub fn init() -> Result<InitData> {
         }
 
         Err(e) => {
+            // serde stops at the first error, but it's nicer to see all of them at once
+            let problems = config_path()
+                .and_then(|p| Ok(fs::read_to_string(p)?))
+                .map(|data| check(&data))
+                .unwrap_or_default()
+                .iter()
+                .map(|p| format!("\n- {p}"))
+                .collect::<String>();
+
+            let problems = if problems.is_empty() {
+                problems
+            } else {
+                format!("\n\nProblems found in config.toml (line:column):{problems}")
+            };
+
             fatal_popup(
                 "Error reading config",
                 format!(
-                    "Failed to get config file. Most likely either it failed to read the file, or your config file is malformed.\n\nError: {e}"
+                    "Failed to get config file. Most likely either it failed to read the file, or your config file is malformed.\n\nError: {e}{problems}"
                 ),
             );
         }
     };
 
     // start logger
-    let worker_guard = setup_logs(config, &plugins_dir).context("Failed to set up logs")?;
+    let worker_guard =
+        setup_logs(&profile::apply(config), &plugins_dir).context("Failed to set up logs")?;
 
     // enable unfettered access through debug privilege if we have admin access
     if is_admin() {
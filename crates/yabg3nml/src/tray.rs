@@ -1,20 +1,39 @@
-use std::thread::{self, JoinHandle};
+use std::{
+    iter,
+    thread::{self, JoinHandle},
+};
 
+use shared::{
+    config::Config,
+    pipe::commands::Control,
+    popup::{MessageBoxIcon, display_popup, warn_popup},
+};
+use tracing::error;
 use tray_icon::{
     Icon, TrayIconBuilder,
-    menu::{AboutMetadata, Menu, MenuEvent, MenuItem, PredefinedMenuItem},
+    menu::{
+        AboutMetadata, CheckMenuItem, Menu, MenuEvent, MenuId, MenuItem, PredefinedMenuItem,
+        Submenu,
+    },
 };
 use windows::Win32::{
     Foundation::{LPARAM, WPARAM},
-    UI::WindowsAndMessaging::{GetClassNameW, PostMessageW, WM_CLOSE},
+    UI::WindowsAndMessaging::{GetClassNameW, PostMessageW, SetTimer, WM_CLOSE, WM_TIMER},
 };
 
 use crate::{
     RunType,
+    bisect::{self, Progress, Verdict},
+    config_watcher::LiveConfig,
+    logging::{loader_level, reload_level, sync_loader_level},
+    profile, safe_mode, server, session,
     stop_token::StopToken,
     wapi::{enum_windows::EnumWindowsRs, event_loop::EventLoop},
 };
 
+/// How often the plugin status in the menu and tooltip is refreshed, in ms
+const STATUS_INTERVAL: u32 = 1000;
+
 pub struct AppTray;
 
 impl AppTray {
@@ -22,12 +41,31 @@ impl AppTray {
         watcher_token: StopToken,
         timeout_token: Option<StopToken>,
         kind: RunType,
+        config: LiveConfig,
     ) -> JoinHandle<()> {
         thread::spawn(move || {
             let icon = Icon::from_resource(1, None).unwrap();
 
             let tray_menu = Menu::new();
 
+            let mut profile_menu = ProfileMenu::new(&config.get());
+
+            // a sentinel file turns safe mode on for good, so it can't be turned off here
+            let safe_mode_i = CheckMenuItem::new(
+                "Safe mode (load no plugins)",
+                !safe_mode::sentinel_exists(),
+                safe_mode::is_enabled(),
+                None,
+            );
+
+            let bisect_menu = BisectMenu::new();
+
+            // what the loader reported about the running game; not clickable
+            let status_i = MenuItem::new("Plugins: waiting for game", false, None);
+
+            // the loader only reloads in hot reload mode
+            let reload_i = MenuItem::new("Reload plugins", config.get().core.hot_reload, None);
+
             let quit_i = MenuItem::new("Quit", true, None);
 
             let authors = env!("CARGO_PKG_AUTHORS")
@@ -59,23 +97,95 @@ impl AppTray {
                         }),
                     ),
                     &PredefinedMenuItem::separator(),
+                    &status_i,
+                    &reload_i,
+                    &profile_menu.menu,
+                    &safe_mode_i,
+                    &bisect_menu.menu,
+                    &PredefinedMenuItem::separator(),
                     &quit_i,
                 ])
                 .unwrap();
 
             let mut tray_icon = Some(
                 TrayIconBuilder::new()
-                    .with_tooltip(title)
+                    .with_tooltip(&title)
                     .with_menu(Box::new(tray_menu))
                     .with_icon(icon)
                     .build()
                     .unwrap(),
             );
 
-            EventLoop::new().run(move |event_loop, _| {
-                if let Ok(event) = MenuEvent::receiver().try_recv()
-                    && event.id == quit_i.id()
-                {
+            // the loop only wakes up on messages, so have a timer post one regularly to
+            // pick up status changes. A timer without a window or callback just posts WM_TIMER
+            _ = unsafe { SetTimer(None, 0, STATUS_INTERVAL, None) };
+            let mut status = None;
+
+            EventLoop::new().run(move |event_loop, msg| {
+                if msg.message == WM_TIMER {
+                    let current = session::status();
+                    if current != status {
+                        if let Some(current) = &current {
+                            status_i.set_text(format!("Plugins: {current}"));
+
+                            if let Some(tray_icon) = &tray_icon {
+                                _ = tray_icon.set_tooltip(Some(format!("{title}\n{current}")));
+                            }
+                        }
+
+                        status = current;
+                    }
+
+                    safe_mode_i.set_enabled(!safe_mode::sentinel_exists());
+                    safe_mode_i.set_checked(safe_mode::is_enabled());
+
+                    bisect_menu.refresh();
+
+                    let config = config.get();
+                    profile_menu.refresh(&config);
+                    reload_i.set_enabled(config.core.hot_reload);
+                }
+
+                let Ok(event) = MenuEvent::receiver().try_recv() else {
+                    return;
+                };
+
+                if let Some(selected) = profile_menu.profile(&event.id) {
+                    profile::set_override(selected);
+
+                    // behave like radio buttons
+                    let config = config.get();
+                    profile_menu.refresh(&config);
+
+                    let old_level = loader_level();
+                    let config = profile::apply(&config);
+                    if let Err(e) = reload_level(config.log_level()) {
+                        error!("failed to apply profile log level: {e}");
+                    }
+
+                    sync_loader_level(old_level);
+                } else if event.id == safe_mode_i.id() {
+                    // the check may have been reset by a refresh, so go by the state instead
+                    safe_mode::set_override(!safe_mode::is_enabled());
+                    safe_mode_i.set_checked(safe_mode::is_enabled());
+                } else if event.id == reload_i.id() {
+                    server::control(Control::Reload { name: None });
+                } else if let Some(action) = bisect_menu.action(&event.id) {
+                    let result = match action {
+                        BisectAction::Start => bisect::start(&profile::apply(&config.get()))
+                            .map(|b| Progress::Narrowed(b).to_string()),
+                        BisectAction::Mark(verdict) => bisect::mark(verdict).map(|p| p.to_string()),
+                        BisectAction::Stop => bisect::reset()
+                            .map(|_| "Stopped bisecting; plugins load normally again".to_owned()),
+                    };
+
+                    bisect_menu.refresh();
+
+                    match result {
+                        Ok(message) => display_popup("Bisect", message, MessageBoxIcon::Info),
+                        Err(e) => warn_popup("Bisect", format!("{e}")),
+                    }
+                } else if event.id == quit_i.id() {
                     if let Some(token) = timeout_token.as_ref() {
                         token.stop();
                     }
@@ -115,3 +225,140 @@ impl AppTray {
         })
     }
 }
+
+/// Picks the profile for this session; the first entry is for using no profile
+struct ProfileMenu {
+    menu: Submenu,
+    /// The profile each entry selects
+    items: Vec<(Option<String>, CheckMenuItem)>,
+}
+
+impl ProfileMenu {
+    fn new(config: &Config) -> Self {
+        let mut this = Self {
+            menu: Submenu::new("Profile", false),
+            items: Vec::new(),
+        };
+
+        this.refresh(config);
+        this
+    }
+
+    /// [profiles] may have been edited since the menu was built, so the entries follow the
+    /// live config
+    fn refresh(&mut self, config: &Config) {
+        profile::forget_removed(config);
+
+        let profiles = iter::once(None)
+            .chain(config.profiles.keys().cloned().map(Some))
+            .collect::<Vec<_>>();
+
+        if !self.items.iter().map(|(p, _)| p).eq(&profiles) {
+            for (_, item) in self.items.drain(..) {
+                _ = self.menu.remove(&item);
+            }
+
+            for profile in profiles {
+                let item =
+                    CheckMenuItem::new(profile.as_deref().unwrap_or("None"), true, false, None);
+                self.menu.append(&item).unwrap();
+                self.items.push((profile, item));
+            }
+
+            self.menu.set_enabled(!config.profiles.is_empty());
+        }
+
+        let config = profile::apply(config);
+        let active = config.active_profile().map(|(name, _)| name);
+
+        for (profile, item) in &self.items {
+            item.set_checked(profile.as_deref() == active);
+        }
+    }
+
+    /// The profile an entry selects
+    fn profile(&self, id: &MenuId) -> Option<Option<String>> {
+        self.items
+            .iter()
+            .find(|(_, item)| item.id() == id)
+            .map(|(profile, _)| profile.clone())
+    }
+}
+
+enum BisectAction {
+    Start,
+    Mark(Verdict),
+    Stop,
+}
+
+struct BisectMenu {
+    menu: Submenu,
+    status: MenuItem,
+    start: MenuItem,
+    good: MenuItem,
+    bad: MenuItem,
+    stop: MenuItem,
+}
+
+impl BisectMenu {
+    fn new() -> Self {
+        let this = Self {
+            menu: Submenu::new("Bisect", true),
+            status: MenuItem::new("Not bisecting", false, None),
+            start: MenuItem::new("Start bisecting", true, None),
+            good: MenuItem::new("Last launch was good", false, None),
+            bad: MenuItem::new("Last launch was bad", false, None),
+            stop: MenuItem::new("Stop bisecting", false, None),
+        };
+
+        this.menu
+            .append_items(&[
+                &this.status,
+                &PredefinedMenuItem::separator(),
+                &this.start,
+                &this.good,
+                &this.bad,
+                &this.stop,
+            ])
+            .unwrap();
+
+        this.refresh();
+        this
+    }
+
+    /// The bisect state lives in a file, so it may also have been changed from the cli
+    fn refresh(&self) {
+        let bisect = bisect::load().ok().flatten();
+
+        let status = match &bisect {
+            Some(b) => format!(
+                "{} suspects left; testing {}",
+                b.suspects.len(),
+                b.testing.join(", ")
+            ),
+            None => "Not bisecting".to_owned(),
+        };
+
+        self.status.set_text(status);
+        self.start.set_enabled(bisect.is_none());
+        for item in [&self.good, &self.bad, &self.stop] {
+            item.set_enabled(bisect.is_some());
+        }
+    }
+
+    fn action(&self, id: &MenuId) -> Option<BisectAction> {
+        let action = if id == self.start.id() {
+            BisectAction::Start
+        } else if id == self.good.id() {
+            BisectAction::Mark(Verdict::Good)
+        } else if id == self.bad.id() {
+            BisectAction::Mark(Verdict::Bad)
+        } else if id == self.stop.id() {
+            BisectAction::Stop
+        } else {
+            return None;
+        };
+
+        Some(action)
+    }
+}